@@ -83,6 +83,10 @@ export interface MatchHistoryItem {
   opponent_name: string;
   opponent_elo: number;
   result: 'win' | 'loss' | 'draw';
+  ended_by: 'win' | 'draw' | 'forfeit' | 'timeout' | 'resign' | 'abort' | null;
+  reason: string | null;
+  players: number;
+  rank: number;
   my_elo_before: number;
   my_elo_after: number;
   opponent_elo_before: number;
@@ -172,6 +176,107 @@ export const gamesApi = {
     const response = await api.get(`/games/${gameId}`);
     return response.data;
   },
+
+  exportGame: async (gameId: string, format: GameFormat = 'notation'): Promise<string> => {
+    const response = await api.get(`/games/${gameId}/export`, { params: { format }, responseType: 'text' });
+    return response.data;
+  },
+
+  importGame: async (text: string, format: GameFormat = 'notation', variant?: string) => {
+    const response = await api.post('/games/import', { text, format, variant });
+    return response.data as { id: string; moves: number; result: string | null; rules: any };
+  },
+};
+
+export type GameFormat = 'notation' | 'psq' | 'renlib';
+
+export interface Cell {
+  x: number;
+  y: number;
+}
+
+export interface Threats {
+  wins: Cell[];
+  fours: Cell[];
+  open_threes: Cell[];
+}
+
+export interface Analysis {
+  to_move: number;
+  threats: Threats[];
+  forced_win: Cell[] | null;
+  eval: number;
+  best_move: Cell | null;
+  nodes: number;
+  exhausted: boolean;
+}
+
+export const analysisApi = {
+  analyseGame: async (gameId: string, ply?: number, depth?: number): Promise<Analysis> => {
+    const response = await api.post<Analysis>('/analysis', { game_id: gameId, ply, depth });
+    return response.data;
+  },
+
+  analyseMoves: async (moves: Cell[], rules?: any, depth?: number): Promise<Analysis> => {
+    const response = await api.post<Analysis>('/analysis', { moves, rules, depth });
+    return response.data;
+  },
+};
+
+export interface Continuation {
+  move: Cell;
+  games: number;
+  win_percent: number;
+  draw_percent: number;
+  loss_percent: number;
+  average_rating: number;
+  average_opponent_rating: number;
+}
+
+export interface OpeningFilter {
+  variant?: string;
+  infinite?: boolean;
+  min_rating?: number;
+  max_rating?: number;
+}
+
+export const openingApi = {
+  explore: async (moves: Cell[], filter: OpeningFilter = {}) => {
+    const response = await api.get('/openings', {
+      params: { moves: moves.map((m) => `${m.x},${m.y}`).join(';'), ...filter },
+    });
+    return response.data as { moves: Cell[]; to_move: number; games: number; continuations: Continuation[] };
+  },
+};
+
+export interface Puzzle {
+  id: string;
+  rules: any;
+  moves: Cell[];
+  to_move: number;
+  rating: number;
+  length: number;
+  attempts: number;
+  solves: number;
+}
+
+export interface PuzzleAttempt {
+  status: 'continue' | 'solved' | 'failed';
+  reply?: Cell;
+  rating: { before: number; after: number } | null;
+  solution?: Cell[];
+}
+
+export const puzzleApi = {
+  next: async (): Promise<Puzzle> => {
+    const response = await api.get<Puzzle>('/puzzles/next');
+    return response.data;
+  },
+
+  attempt: async (puzzleId: string, moves: Cell[]): Promise<PuzzleAttempt> => {
+    const response = await api.post<PuzzleAttempt>(`/puzzles/${puzzleId}/attempt`, { moves });
+    return response.data;
+  },
 };
 
 export const adminApi = {
@@ -198,4 +303,74 @@ export const adminApi = {
   },
 };
 
-export default api;
\ No newline at end of file
+export default api;
+export type TournamentFormat = 'round_robin' | 'swiss' | 'single_elimination';
+export type PairingResult = 'black' | 'white' | 'draw';
+
+export interface TournamentPairing {
+  id: string;
+  round: number;
+  board: number;
+  black: string;
+  white: string | null;
+  result: PairingResult | null;
+  game: string | null;
+  room: string | null;
+}
+
+export interface TournamentStanding {
+  rank: number;
+  player: string;
+  points: number;
+  buchholz: number;
+  sonneborn_berger: number;
+  wins: number;
+  draws: number;
+  losses: number;
+}
+
+export interface Tournament {
+  id: string;
+  name: string;
+  format: TournamentFormat;
+  rules: any;
+  rounds: number | null;
+  round: number;
+  status: 'registration' | 'running' | 'finished';
+  player_count: number;
+  players?: { id: string; username: string | null; elo: number | null; seed: number }[];
+  pairings?: TournamentPairing[];
+  standings?: TournamentStanding[];
+}
+
+export const tournamentApi = {
+  list: async (): Promise<Tournament[]> => {
+    const response = await api.get<{ tournaments: Tournament[] }>('/tournaments');
+    return response.data.tournaments;
+  },
+
+  get: async (tournamentId: string): Promise<Tournament> => {
+    const response = await api.get<Tournament>(`/tournaments/${tournamentId}`);
+    return response.data;
+  },
+
+  create: async (name: string, format: TournamentFormat, rules?: any, rounds?: number): Promise<Tournament> => {
+    const response = await api.post<Tournament>('/tournaments', { name, format, rules, rounds });
+    return response.data;
+  },
+
+  join: async (tournamentId: string) => {
+    const response = await api.post(`/tournaments/${tournamentId}/join`);
+    return response.data;
+  },
+
+  start: async (tournamentId: string): Promise<Tournament> => {
+    const response = await api.post<Tournament>(`/tournaments/${tournamentId}/start`);
+    return response.data;
+  },
+
+  adjudicate: async (tournamentId: string, pairingId: string, result: PairingResult) => {
+    const response = await api.post(`/tournaments/${tournamentId}/pairings/${pairingId}/result`, { result });
+    return response.data;
+  },
+};
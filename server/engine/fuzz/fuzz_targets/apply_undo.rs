@@ -0,0 +1,39 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tictac_engine::{Move, Outcome, Position, Rules};
+
+// First bytes pick the rules, the rest are moves; illegal moves must be
+// rejected without changing the position, and undoing must retrace the game
+fuzz_target!(|data: &[u8]| {
+    let [width, height, win, players, moves @ ..] = data else { return };
+    let width = 1 + *width as usize % 19;
+    let height = 1 + *height as usize % 19;
+    let rules = Rules::new(width, height, 1 + *win as usize % 7, 1 + *players as usize % 4);
+
+    let mut position = Position::new(rules);
+    let mut seen = vec![position.clone()];
+    for pair in moves.chunks_exact(2) {
+        // One cell beyond every edge, negative coordinates included
+        let mv = Move::new(
+            (pair[0] as usize % (width + 2)) as isize - 1,
+            (pair[1] as usize % (height + 2)) as isize - 1,
+        );
+        let before = position.clone();
+        match position.apply(mv) {
+            Ok(outcome) => {
+                if let Some(Outcome::Win { player, line }) = outcome {
+                    assert!(line.cells().all(|c| position.get(c.x, c.y) == Some(player)));
+                }
+                seen.push(position.clone());
+            }
+            Err(_) => assert_eq!(position, before),
+        }
+    }
+
+    seen.pop();
+    while let Some(previous) = seen.pop() {
+        position.undo().unwrap();
+        assert_eq!(position, previous);
+    }
+});
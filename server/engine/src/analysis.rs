@@ -0,0 +1,429 @@
+//! Threats, forced wins and a quick evaluation of a position, for reviewing
+//! games. Searches stop at the node or cell limit in [`Limits`], so an
+//! analysis can't run away with the CPU however sharp or crowded the position.
+
+use crate::{
+    position::DIRECTIONS,
+    renju::{self, BLACK},
+    Move, Outcome, Position, Variant,
+};
+use std::collections::HashSet;
+
+/// Score of a position that is decided
+pub const WIN_SCORE: i32 = 1_000_000;
+
+/// How deep and how long to search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Fours the side to move may play on the way to a forced win
+    pub depth: usize,
+    /// Positions to visit before giving up
+    pub nodes: usize,
+    /// Cells to look at before giving up, counting every cell scanned for
+    /// threats and every window evaluated, since those grow with the stones
+    pub cells: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { depth: 4, nodes: 20_000, cells: 4_000_000 }
+    }
+}
+
+/// Cells where a player threatens something, by what a stone there makes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Threats {
+    /// Cells that complete a winning line at once
+    pub wins: Vec<Move>,
+    /// Cells that make a four, one stone short of a winning line
+    pub fours: Vec<Move>,
+    /// Cells that turn an open three into a straight four, which wins at
+    /// either end and can't be stopped with one stone
+    pub open_threes: Vec<Move>,
+}
+
+/// What [`analyse`] found
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Analysis {
+    pub to_move: usize,
+    /// Threats of every player, in player order
+    pub threats: Vec<Threats>,
+    /// Moves to a win the side to move can force with fours, the opponent's
+    /// only replies included. Only searched in two-player games placing one
+    /// stone a turn.
+    pub forced_win: Option<Vec<Move>>,
+    /// How good the position is for the side to move, `WIN_SCORE` when won
+    pub eval: i32,
+    pub best_move: Option<Move>,
+    pub nodes: usize,
+    /// The node or cell limit cut the search short
+    pub exhausted: bool,
+}
+
+/// Look for threats, a forced win and the best move for the side to move
+pub fn analyse(position: &Position, limits: Limits) -> Analysis {
+    let mut search = Search { position: position.clone(), limits, nodes: 0, cells: 0 };
+    let to_move = position.to_move();
+    let threats = (0..position.rules().players).map(|p| search.threats(p)).collect();
+
+    let (forced_win, eval, best_move) = match position.outcome() {
+        Some(Outcome::Win { player, .. } | Outcome::Captures { player }) => {
+            let eval = if *player == to_move { WIN_SCORE } else { -WIN_SCORE };
+            (None, eval, None)
+        }
+        Some(Outcome::Draw) => (None, 0, None),
+        None => match search.forced_win() {
+            Some(line) => (Some(line.clone()), WIN_SCORE - line.len() as i32, line.first().copied()),
+            None => (None, evaluate(position, to_move), search.best_move()),
+        },
+    };
+
+    Analysis {
+        to_move,
+        threats,
+        forced_win,
+        eval,
+        best_move,
+        nodes: search.nodes,
+        exhausted: search.exhausted(),
+    }
+}
+
+/// Score for `player` from every line of `win_length` cells that only one
+/// player has stones in, more for fuller lines, less the best opponent's
+pub fn evaluate(position: &Position, player: usize) -> i32 {
+    let rules = position.rules();
+    let win = rules.win_length as isize;
+    let mut scores = vec![0i64; rules.players];
+
+    // Only windows with a stone in them score, so start from the stones
+    let windows: HashSet<(Move, (isize, isize))> = occupied(position)
+        .into_iter()
+        .flat_map(|stone| DIRECTIONS.iter().flat_map(move |&dir| (0..win).map(move |k| (stone.offset(dir, -k), dir))))
+        .collect();
+    for (start, dir) in windows {
+        let mut owner = None;
+        let mut stones = 0u32;
+        for cell in (0..win).map(|k| start.offset(dir, k)) {
+            if !rules.contains(cell.x, cell.y) {
+                owner = Some(usize::MAX);
+                break;
+            }
+            match (position.get(cell.x, cell.y), owner) {
+                (None, _) => {}
+                (Some(p), None) => {
+                    owner = Some(p);
+                    stones = 1;
+                }
+                (Some(p), Some(o)) if p == o => stones += 1,
+                // Two players share the window, so neither can fill it
+                (Some(_), Some(_)) => {
+                    owner = Some(usize::MAX);
+                    break;
+                }
+            }
+        }
+        if let Some(p) = owner.filter(|&p| p < rules.players) {
+            scores[p] += 10i64.pow(stones.min(6) - 1);
+        }
+    }
+
+    let best_other = (0..rules.players).filter(|&p| p != player).map(|p| scores[p]).max().unwrap_or(0);
+    (scores[player] - best_other).clamp(1 - WIN_SCORE as i64, WIN_SCORE as i64 - 1) as i32
+}
+
+// Cells with a stone on them, found through the moves so that huge infinite
+// boards cost no more than small ones
+fn occupied(position: &Position) -> HashSet<Move> {
+    position.history().iter().copied().filter(|mv| position.get(mv.x, mv.y).is_some()).collect()
+}
+
+struct Search {
+    position: Position,
+    limits: Limits,
+    nodes: usize,
+    cells: usize,
+}
+
+impl Search {
+    fn exhausted(&self) -> bool {
+        self.nodes >= self.limits.nodes || self.cells >= self.limits.cells
+    }
+
+    // Empty cells within `reach` of a stone of `owner`, or of anyone's, row
+    // by row. A cell only wins or makes a four for a player near their own
+    // stones, so threat scans look no further.
+    fn candidates(&mut self, reach: isize, owner: Option<usize>) -> Vec<Move> {
+        let stones: Vec<Move> = occupied(&self.position)
+            .into_iter()
+            .filter(|stone| owner.is_none_or(|owner| self.position.get(stone.x, stone.y) == Some(owner)))
+            .collect();
+        let side = 2 * reach as usize + 1;
+        self.cells += stones.len() * side * side;
+        let cells: HashSet<Move> = stones
+            .into_iter()
+            .flat_map(|stone| {
+                (-reach..=reach).flat_map(move |dy| (-reach..=reach).map(move |dx| Move::new(stone.x + dx, stone.y + dy)))
+            })
+            .filter(|mv| self.position.rules().contains(mv.x, mv.y) && self.position.get(mv.x, mv.y).is_none())
+            .collect();
+        let mut cells: Vec<Move> = cells.into_iter().collect();
+        cells.sort_by_key(|mv| (mv.y, mv.x));
+        cells
+    }
+
+    // Whether `player` may put a stone on the empty cell `mv`
+    fn playable(&self, mv: Move, player: usize) -> bool {
+        !(self.position.rules().variant == Variant::Renju
+            && player == BLACK
+            && renju::forbidden(&self.position, mv).is_some())
+    }
+
+    fn wins_at(&self, mv: Move, player: usize) -> bool {
+        DIRECTIONS.iter().any(|&dir| self.position.wins_along(mv, dir, player))
+    }
+
+    /// Cells where `player` wins at once
+    fn wins(&mut self, player: usize) -> Vec<Move> {
+        self.candidates(1, Some(player))
+            .into_iter()
+            .filter(|&mv| self.wins_at(mv, player) && self.playable(mv, player))
+            .collect()
+    }
+
+    fn threats(&mut self, player: usize) -> Threats {
+        let mut threats = Threats::default();
+        let reach = self.position.rules().win_length as isize - 1;
+        for mv in self.candidates(reach, Some(player)) {
+            if !self.playable(mv, player) {
+                continue;
+            }
+            if self.wins_at(mv, player) {
+                threats.wins.push(mv);
+                continue;
+            }
+            self.position.put(mv, player);
+            let made: Vec<usize> = DIRECTIONS.iter().map(|&dir| self.new_wins(mv, dir, player)).collect();
+            self.position.lift(mv);
+            if made.iter().any(|&n| n >= 2) {
+                threats.open_threes.push(mv);
+            } else if made.iter().any(|&n| n > 0) {
+                threats.fours.push(mv);
+            }
+        }
+        threats
+    }
+
+    // Winning cells for `player` along the line through their stone at `mv`
+    // that only win because of it
+    fn new_wins(&mut self, mv: Move, dir: (isize, isize), player: usize) -> usize {
+        let reach = self.position.rules().win_length as isize;
+        let winning: Vec<Move> = (-reach..=reach)
+            .filter(|&k| k != 0)
+            .map(|k| mv.offset(dir, k))
+            .filter(|q| self.position.rules().contains(q.x, q.y) && self.position.get(q.x, q.y).is_none())
+            .filter(|&q| self.position.wins_along(q, dir, player))
+            .collect();
+        self.position.lift(mv);
+        let count = winning.iter().filter(|&&q| !self.position.wins_along(q, dir, player)).count();
+        self.position.put(mv, player);
+        count
+    }
+
+    /// Fours for the side to move that win whatever the opponent answers
+    fn forced_win(&mut self) -> Option<Vec<Move>> {
+        let rules = self.position.rules();
+        if rules.players != 2 || rules.variant == Variant::Connect6 {
+            return None;
+        }
+        self.fours_to_win(self.limits.depth)
+    }
+
+    fn fours_to_win(&mut self, depth: usize) -> Option<Vec<Move>> {
+        let attacker = self.position.to_move();
+        if let Some(&win) = self.wins(attacker).first() {
+            return Some(vec![win]);
+        }
+        let defender_wins = self.wins(1 - attacker);
+        if depth == 0 || defender_wins.len() > 1 || self.exhausted() {
+            return None;
+        }
+
+        let threats = self.threats(attacker);
+        let mut fours = [threats.open_threes, threats.fours].concat();
+        // Any other four would let the defender win first
+        if let Some(block) = defender_wins.first() {
+            fours.retain(|mv| mv == block);
+        }
+        for mv in fours {
+            if self.exhausted() {
+                break;
+            }
+            self.nodes += 1;
+            if self.position.apply(mv).is_err() {
+                continue;
+            }
+            let line = self.answer_four(mv, depth);
+            self.position.undo();
+            if line.is_some() {
+                return line;
+            }
+        }
+        None
+    }
+
+    // The defender's only answer to the four just played at `mv`, and the
+    // rest of the attack after it
+    fn answer_four(&mut self, mv: Move, depth: usize) -> Option<Vec<Move>> {
+        if self.position.is_over() {
+            return Some(vec![mv]);
+        }
+        let defender = self.position.to_move();
+        if !self.wins(defender).is_empty() {
+            return None;
+        }
+        match self.wins(1 - defender).as_slice() {
+            [] => None,
+            // Blocking one end leaves the other
+            &[block, win, ..] => Some(vec![mv, block, win]),
+            &[block] => {
+                self.nodes += 1;
+                self.position.apply(block).ok()?;
+                let rest = match self.position.is_over() {
+                    true => None,
+                    false => self.fours_to_win(depth - 1),
+                };
+                self.position.undo();
+                rest.map(|rest| [vec![mv, block], rest].concat())
+            }
+        }
+    }
+
+    /// Win at once, else stop the opponent winning, else the move that
+    /// leaves the best evaluation
+    fn best_move(&mut self) -> Option<Move> {
+        let to_move = self.position.to_move();
+        let rules = *self.position.rules();
+        if let Some(&win) = self.wins(to_move).first() {
+            return Some(win);
+        }
+        for player in (0..rules.players).filter(|&p| p != to_move) {
+            if let Some(&block) = self.wins(player).iter().find(|&&mv| self.position.is_legal(mv)) {
+                return Some(block);
+            }
+        }
+
+        let candidates: Vec<Move> = self.candidates(2, None).into_iter().filter(|&mv| self.position.is_legal(mv)).collect();
+        if candidates.is_empty() {
+            let centre = Move::new(rules.width as isize / 2, rules.height as isize / 2);
+            return match self.position.is_legal(centre) {
+                true => Some(centre),
+                false => self.position.legal_moves().next(),
+            };
+        }
+
+        let mut best = None;
+        for mv in candidates {
+            if self.exhausted() && best.is_some() {
+                break;
+            }
+            self.nodes += 1;
+            if self.position.apply(mv).is_err() {
+                continue;
+            }
+            // Every window through every stone is scored
+            let win = self.position.rules().win_length;
+            self.cells += self.position.history().len() * DIRECTIONS.len() * win * win;
+            let score = match self.position.outcome() {
+                Some(Outcome::Win { .. } | Outcome::Captures { .. }) => WIN_SCORE,
+                _ => evaluate(&self.position, to_move),
+            };
+            self.position.undo();
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((mv, score));
+            }
+        }
+        best.map(|(mv, _)| mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rules;
+
+    fn position(moves: &[(isize, isize)]) -> Position {
+        let mut position = Position::new(Rules::new(15, 15, 5, 2));
+        for &(x, y) in moves {
+            position.apply(Move::new(x, y)).unwrap();
+        }
+        position
+    }
+
+    // Black's open three in row 5, white scattered in the corners
+    const OPEN_THREE: [(isize, isize); 6] = [(3, 5), (0, 0), (4, 5), (0, 14), (5, 5), (14, 0)];
+
+    #[test]
+    fn test_open_three_and_fours() {
+        let analysis = analyse(&position(&OPEN_THREE), Limits::default());
+        let black = &analysis.threats[0];
+        assert!(black.wins.is_empty());
+        assert_eq!(black.open_threes, vec![Move::new(2, 5), Move::new(6, 5)]);
+        assert_eq!(black.fours, vec![Move::new(1, 5), Move::new(7, 5)]);
+        assert_eq!(analysis.threats[1], Threats::default());
+    }
+
+    #[test]
+    fn test_straight_four_is_a_forced_win() {
+        let analysis = analyse(&position(&OPEN_THREE), Limits::default());
+        let line = vec![Move::new(2, 5), Move::new(1, 5), Move::new(6, 5)];
+        assert_eq!(analysis.forced_win, Some(line));
+        assert_eq!(analysis.best_move, Some(Move::new(2, 5)));
+        assert_eq!(analysis.eval, WIN_SCORE - 3);
+    }
+
+    #[test]
+    fn test_four_must_be_blocked() {
+        // White's four along the top edge comes first
+        let analysis = analyse(
+            &position(&[(3, 5), (0, 0), (4, 5), (1, 0), (5, 5), (2, 0), (10, 10), (3, 0)]),
+            Limits::default(),
+        );
+        assert_eq!(analysis.threats[1].wins, vec![Move::new(4, 0)]);
+        assert_eq!(analysis.forced_win, None);
+        assert_eq!(analysis.best_move, Some(Move::new(4, 0)));
+    }
+
+    #[test]
+    fn test_node_limit_stops_the_search() {
+        let analysis = analyse(&position(&OPEN_THREE), Limits { nodes: 0, ..Limits::default() });
+        assert_eq!(analysis.forced_win, None);
+        assert!(analysis.exhausted);
+    }
+
+    #[test]
+    fn test_cell_limit_stops_a_crowded_search() {
+        // Black's open three among stones scattered far apart on an infinite board
+        let mut position = Position::new(Rules::infinite(5, 2));
+        for &(x, y) in &OPEN_THREE[..5] {
+            position.apply(Move::new(x, y)).unwrap();
+        }
+        for i in 0..101 {
+            position.apply(Move::new(1000 * (i % 20) + 100, 1000 * (i / 20) + 100)).unwrap();
+        }
+        assert!(analyse(&position, Limits::default()).forced_win.is_some());
+
+        let analysis = analyse(&position, Limits { cells: 1000, ..Limits::default() });
+        assert_eq!(analysis.forced_win, None);
+        assert!(analysis.exhausted);
+    }
+
+    #[test]
+    fn test_evaluation_is_symmetric() {
+        let position = position(&[(7, 7), (8, 8)]);
+        assert_eq!(evaluate(&position, 0), -evaluate(&position, 1));
+        assert_eq!(evaluate(&Position::default(), 0), 0);
+    }
+}
@@ -0,0 +1,221 @@
+//! Reviewing a finished game move by move: each move is compared with the
+//! best one [`analyse`] finds in the position it was played from.
+
+use crate::{analyse, evaluate, Limits, Move, Outcome, Position, Rules, WIN_SCORE};
+
+/// Evaluation lost against the best move, below which a move is still
+/// good, an inaccuracy or a mistake; anything more is a blunder
+const GOOD_LOSS: i32 = 100;
+const INACCURACY_LOSS: i32 = 500;
+const MISTAKE_LOSS: i32 = 2_000;
+
+/// How a move compares with the best one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Judgement {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    fn from_loss(loss: i32) -> Self {
+        match loss {
+            ..=0 => Judgement::Best,
+            1..GOOD_LOSS => Judgement::Good,
+            GOOD_LOSS..INACCURACY_LOSS => Judgement::Inaccuracy,
+            INACCURACY_LOSS..MISTAKE_LOSS => Judgement::Mistake,
+            _ => Judgement::Blunder,
+        }
+    }
+
+    /// Share of a perfect score a move judged this way earns
+    pub const fn score(self) -> f64 {
+        match self {
+            Judgement::Best => 1.0,
+            Judgement::Good => 0.8,
+            Judgement::Inaccuracy => 0.5,
+            Judgement::Mistake => 0.2,
+            Judgement::Blunder => 0.0,
+        }
+    }
+}
+
+/// Why a move is a blunder whatever the evaluation says
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Reason {
+    /// The player had a forced win and let it go
+    MissedForcedWin,
+    /// The opponent has a forced win now, an open four among them, that the
+    /// best move would have prevented
+    AllowedForcedWin,
+}
+
+/// What [`annotate`] says about one move
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Annotation {
+    pub ply: usize,
+    pub player: usize,
+    pub judgement: Judgement,
+    pub reason: Option<Reason>,
+    /// The move the analysis preferred, if it wasn't the one played
+    pub best_move: Option<Move>,
+    /// Evaluation the move gave away against the best move
+    pub loss: i32,
+}
+
+/// Judge every move of a game played under `rules`, stopping at the first
+/// illegal one
+pub fn annotate(rules: Rules, moves: &[Move], limits: Limits) -> Vec<Annotation> {
+    let mut position = Position::new(rules);
+    let mut before = analyse(&position, limits);
+    let mut annotations = Vec::new();
+
+    for (ply, &mv) in moves.iter().enumerate() {
+        let player = position.to_move();
+        let best = before.best_move.filter(|&best| best != mv);
+        let best_score = best.map(|best| score_after(&mut position, best, player));
+        if position.apply(mv).is_err() {
+            break;
+        }
+        let played_score = score(&position, player);
+        let after = analyse(&position, limits);
+
+        let won = played_score == WIN_SCORE;
+        let reason = if won {
+            None
+        } else if before.forced_win.is_some()
+            && after.threats[player].wins.len() <= before.threats[player].wins.len()
+        {
+            // Neither winning nor another four on the way to a win
+            Some(Reason::MissedForcedWin)
+        } else if after.to_move != player
+            && after.forced_win.is_some()
+            && best.is_some_and(|best| !loses_after(&mut position, best, limits))
+        {
+            Some(Reason::AllowedForcedWin)
+        } else {
+            None
+        };
+
+        let loss = best_score.map_or(0, |best_score| (best_score - played_score).max(0));
+        let judgement = match (won, reason) {
+            (true, _) => Judgement::Best,
+            (false, Some(_)) => Judgement::Blunder,
+            (false, None) => Judgement::from_loss(loss),
+        };
+        annotations.push(Annotation { ply, player, judgement, reason, best_move: best, loss });
+        before = after;
+    }
+    annotations
+}
+
+/// Average score of each player's moves as a percentage, 100 for a player
+/// without moves
+pub fn accuracy(annotations: &[Annotation], players: usize) -> Vec<f64> {
+    (0..players)
+        .map(|player| {
+            let scores: Vec<f64> =
+                annotations.iter().filter(|a| a.player == player).map(|a| a.judgement.score()).collect();
+            match scores.len() {
+                0 => 100.0,
+                n => (scores.iter().sum::<f64>() * 1000.0 / n as f64).round() / 10.0,
+            }
+        })
+        .collect()
+}
+
+// How good the position is for `player`, whoever is to move
+fn score(position: &Position, player: usize) -> i32 {
+    match position.outcome() {
+        Some(Outcome::Win { player: winner, .. } | Outcome::Captures { player: winner }) => match *winner == player {
+            true => WIN_SCORE,
+            false => -WIN_SCORE,
+        },
+        Some(Outcome::Draw) => 0,
+        None => evaluate(position, player),
+    }
+}
+
+fn score_after(position: &mut Position, mv: Move, player: usize) -> i32 {
+    if position.apply(mv).is_err() {
+        return -WIN_SCORE;
+    }
+    let score = score(position, player);
+    position.undo();
+    score
+}
+
+// Whether the opponent has a forced win after `mv` in place of the move
+// just played
+fn loses_after(position: &mut Position, mv: Move, limits: Limits) -> bool {
+    let played = position.undo();
+    let loses = match position.apply(mv) {
+        Ok(_) => {
+            let loses = analyse(position, limits).forced_win.is_some();
+            position.undo();
+            loses
+        }
+        Err(_) => true,
+    };
+    if let Some(played) = played {
+        position.apply(played).expect("the move was legal before");
+    }
+    loses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(moves: &[(isize, isize)]) -> Vec<Move> {
+        moves.iter().map(|&(x, y)| Move::new(x, y)).collect()
+    }
+
+    #[test]
+    fn test_missing_a_win_is_a_blunder() {
+        let rules = Rules::new(15, 15, 5, 2);
+        // Black's open four in row 5 is left for a stone in the corner
+        let game = moves(&[(3, 5), (0, 0), (4, 5), (0, 14), (5, 5), (14, 0), (6, 5), (1, 0), (14, 14)]);
+        let annotations = annotate(rules, &game, Limits::default());
+        assert_eq!(annotations.len(), game.len());
+
+        let last = annotations.last().unwrap();
+        assert_eq!(last.judgement, Judgement::Blunder);
+        assert_eq!(last.reason, Some(Reason::MissedForcedWin));
+        assert!(matches!(last.best_move, Some(Move { x: 2 | 7, y: 5 })));
+    }
+
+    #[test]
+    fn test_allowing_an_open_four_is_a_blunder() {
+        let rules = Rules::new(15, 15, 5, 2);
+        // White leaves black's open three alone, so black gets a straight four
+        let game = moves(&[(3, 5), (0, 0), (4, 5), (0, 14), (5, 5), (14, 14)]);
+        let annotations = annotate(rules, &game, Limits::default());
+
+        let last = annotations.last().unwrap();
+        assert_eq!(last.player, 1);
+        assert_eq!(last.judgement, Judgement::Blunder);
+        assert_eq!(last.reason, Some(Reason::AllowedForcedWin));
+    }
+
+    #[test]
+    fn test_winning_moves_are_best() {
+        let rules = Rules::new(10, 10, 5, 2);
+        let game = moves(&[(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1), (3, 0), (3, 1), (4, 0)]);
+        let annotations = annotate(rules, &game, Limits::default());
+        assert_eq!(annotations[8].judgement, Judgement::Best);
+        // White had to block at (4, 0) and didn't
+        assert_eq!(annotations[7].judgement, Judgement::Blunder);
+
+        let accuracy = accuracy(&annotations, 2);
+        assert!(accuracy[0] > accuracy[1]);
+        assert!(accuracy.iter().all(|a| (0.0..=100.0).contains(a)));
+    }
+}
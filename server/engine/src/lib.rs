@@ -0,0 +1,27 @@
+//! Rules of the game, independent of rooms, sockets and storage.
+//!
+//! A [`Position`] holds the stones on the board and the moves that led there.
+//! Moves are applied and undone in place, so searches in bots and tooling can
+//! walk the game tree without allocating.
+
+mod analysis;
+mod annotation;
+mod notation;
+mod opening;
+mod position;
+mod puzzle;
+mod renju;
+mod rules;
+mod symmetry;
+
+pub use analysis::{analyse, evaluate, Analysis, Limits, Threats, WIN_SCORE};
+pub use annotation::{accuracy, annotate, Annotation, Judgement, Reason};
+pub use notation::{
+    format_move, parse_move, read_psq, read_renlib, write_psq, write_renlib, NotationError, Transcript,
+};
+pub use opening::{Choice, Opening, OpeningError, OpeningRule, Seat, Stage};
+pub use position::{Line, Move, MoveError, Outcome, Position};
+pub use puzzle::{attempt, find_puzzle, unique_win, Attempt, Puzzle};
+pub use renju::Forbidden;
+pub use rules::{Rules, Variant, INFINITE_REACH};
+pub use symmetry::{canonical, transfer, Symmetry};
@@ -0,0 +1,337 @@
+//! Games as text. The native format is like PGN: `[Name "Value"]` tag lines,
+//! then the moves in rounds, `1. h8 i9 2. ...`. Columns are letters from `a`
+//! on the left and rows are numbers from 1 at the bottom, as on a printed
+//! board; infinite boards have neither, so their moves are written `x,y`.
+//!
+//! Piskvork's `.psq` files and RenLib's move strings are read and written too.
+
+use crate::{Move, Rules, INFINITE_REACH};
+use std::fmt;
+
+/// Stones on a RenLib board, which is always the 15x15 Renju board
+const RENLIB_SIZE: usize = 15;
+/// Letters of the longest column name, enough for `INFINITE_REACH` columns
+const MAX_COLUMN_LETTERS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotationError {
+    /// A tag line that isn't `[Name "Value"]`
+    Tag(String),
+    /// A move that isn't a cell of the board
+    Move(String),
+    /// The board can't be written in the format, or read from it
+    Board(String),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotationError::Tag(line) => write!(f, "malformed tag: {}", line),
+            NotationError::Move(token) => write!(f, "not a move on this board: {}", token),
+            NotationError::Board(reason) => write!(f, "unsupported board: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+/// A game as tags and moves, without any rules applied to the moves
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<Move>,
+}
+
+impl Transcript {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// The game in the native format. A `Result` tag is repeated at the end
+    /// of the moves, as in PGN.
+    pub fn write(&self, rules: &Rules) -> String {
+        let mut text = String::new();
+        for (name, value) in &self.tags {
+            text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        text.push('\n');
+
+        let mut tokens: Vec<String> = Vec::new();
+        let mut round = 0;
+        for (ply, &mv) in self.moves.iter().enumerate() {
+            // A round starts when the first player is back, after any
+            // stones they place twice in a row
+            if rules.player_at(ply) == 0 && (ply == 0 || rules.player_at(ply - 1) != 0) {
+                round += 1;
+                tokens.push(format!("{}.", round));
+            }
+            tokens.push(format_move(mv, rules));
+        }
+        tokens.push(self.tag("Result").unwrap_or("*").to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                text.push_str(&line);
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        text.push_str(&line);
+        text.push('\n');
+        text
+    }
+
+    /// Read the native format. The board depends on the tags, so `rules`
+    /// gets them before any move is read.
+    pub fn read(
+        text: &str,
+        rules: impl FnOnce(&[(String, String)]) -> Result<Rules, NotationError>,
+    ) -> Result<(Transcript, Rules), NotationError> {
+        let mut tags = Vec::new();
+        let mut body = String::new();
+        for line in text.lines().map(str::trim) {
+            match line.strip_prefix('[') {
+                Some(tag) if body.trim().is_empty() => tags.push(read_tag(tag).ok_or_else(|| NotationError::Tag(line.to_string()))?),
+                _ => {
+                    body.push_str(line);
+                    body.push('\n');
+                }
+            }
+        }
+        let rules = rules(&tags)?;
+
+        let mut moves = Vec::new();
+        let mut in_comment = false;
+        for token in body.split_whitespace() {
+            if in_comment || token.starts_with('{') {
+                in_comment = !token.ends_with('}');
+                continue;
+            }
+            let numbering = token.trim_end_matches('.').chars().all(|c| c.is_ascii_digit()) && token.ends_with('.');
+            let result = token == "*" || token.chars().all(|c| c.is_ascii_digit() || c == '-' || c == '/');
+            if !numbering && !result {
+                moves.push(parse_move(token, &rules)?);
+            }
+        }
+        Ok((Transcript { tags, moves }, rules))
+    }
+}
+
+// `Name "Value"]`, the opening bracket already gone
+fn read_tag(tag: &str) -> Option<(String, String)> {
+    let (name, rest) = tag.strip_suffix(']')?.split_once(' ')?;
+    let value = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+// `a`..`z`, then `aa`, `ab`, ... for boards wider than the alphabet
+fn column_name(mut x: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'a' + (x % 26) as u8);
+        if x < 26 {
+            break;
+        }
+        x = x / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// A cell as a column letter and a row number counted from the bottom, or
+/// as `x,y` on an infinite board
+pub fn format_move(mv: Move, rules: &Rules) -> String {
+    match rules.infinite {
+        true => format!("{},{}", mv.x, mv.y),
+        false => format!("{}{}", column_name(mv.x as usize), rules.height as isize - mv.y),
+    }
+}
+
+/// A cell written by `format_move`. Whatever the board, no coordinate may be
+/// more than `INFINITE_REACH` from the origin.
+pub fn parse_move(token: &str, rules: &Rules) -> Result<Move, NotationError> {
+    let invalid = || NotationError::Move(token.to_string());
+    let mv = if rules.infinite {
+        let (x, y) = token.split_once(',').ok_or_else(invalid)?;
+        Move::new(x.parse().map_err(|_| invalid())?, y.parse().map_err(|_| invalid())?)
+    } else {
+        let split = token.find(|c: char| !c.is_ascii_alphabetic()).ok_or_else(invalid)?;
+        let (letters, row) = token.split_at(split);
+        if letters.is_empty() || letters.len() > MAX_COLUMN_LETTERS {
+            return Err(invalid());
+        }
+        let column = letters
+            .to_ascii_lowercase()
+            .bytes()
+            .try_fold(0isize, |x, b| x.checked_mul(26)?.checked_add((b - b'a') as isize + 1))
+            .ok_or_else(invalid)?
+            - 1;
+        let row: isize = row.parse().map_err(|_| invalid())?;
+        let y = isize::try_from(rules.height).ok().and_then(|height| height.checked_sub(row)).ok_or_else(invalid)?;
+        Move::new(column, y)
+    };
+    let reachable = mv.x.unsigned_abs() <= INFINITE_REACH && mv.y.unsigned_abs() <= INFINITE_REACH;
+    match reachable && rules.contains(mv.x, mv.y) {
+        true => Ok(mv),
+        false => Err(invalid()),
+    }
+}
+
+/// A Piskvork `.psq` file: a `Piskvorky WxH, ...` header line, then one
+/// `x,y,time` line per move counting from 1 at the top left
+pub fn write_psq(rules: &Rules, moves: &[Move]) -> Result<String, NotationError> {
+    if rules.infinite {
+        return Err(NotationError::Board("Piskvork boards have edges".to_string()));
+    }
+    let mut text = format!("Piskvorky {}x{}, 11:11, 0\n", rules.width, rules.height);
+    for mv in moves {
+        text.push_str(&format!("{},{},0\n", mv.x + 1, mv.y + 1));
+    }
+    text.push_str("-1\n");
+    Ok(text)
+}
+
+/// Board size and moves of a Piskvork `.psq` file. Reading stops at the first
+/// line after the header that isn't a move, where Piskvork lists the engines.
+pub fn read_psq(text: &str) -> Result<((usize, usize), Vec<Move>), NotationError> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    let header = lines.next().unwrap_or_default();
+    let size = header
+        .strip_prefix("Piskvorky ")
+        .and_then(|rest| rest.split(',').next())
+        .and_then(|size| size.split_once('x'))
+        .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+        .ok_or_else(|| NotationError::Board(format!("expected a Piskvorky header, found {:?}", header)))?;
+
+    let mut moves = Vec::new();
+    for line in lines {
+        let fields: Vec<&str> = line.split(',').collect();
+        let (Some(x), Some(y)) = (fields.first(), fields.get(1)) else {
+            break;
+        };
+        let (Ok(x), Ok(y)) = (x.trim().parse::<isize>(), y.trim().parse::<isize>()) else {
+            break;
+        };
+        if x < 1 || y < 1 || x as usize > size.0 || y as usize > size.1 {
+            return Err(NotationError::Move(line.to_string()));
+        }
+        moves.push(Move::new(x - 1, y - 1));
+    }
+    Ok((size, moves))
+}
+
+/// The moves of a 15x15 game run together as RenLib copies them, `h8i9j10`
+pub fn write_renlib(rules: &Rules, moves: &[Move]) -> Result<String, NotationError> {
+    if rules.infinite || rules.width != RENLIB_SIZE || rules.height != RENLIB_SIZE {
+        return Err(NotationError::Board(format!("RenLib boards are {}x{}", RENLIB_SIZE, RENLIB_SIZE)));
+    }
+    Ok(moves.iter().map(|&mv| format_move(mv, rules)).collect())
+}
+
+/// Moves of a RenLib move string, on the 15x15 board. Spaces are allowed
+/// between moves.
+pub fn read_renlib(text: &str) -> Result<Vec<Move>, NotationError> {
+    let rules = Rules::new(RENLIB_SIZE, RENLIB_SIZE, 5, 2);
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut moves = Vec::new();
+    let mut rest = text.as_str();
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| c.is_ascii_digit()).ok_or_else(|| NotationError::Move(rest.to_string()))?;
+        let end = rest[digits..].find(|c: char| !c.is_ascii_digit()).map_or(rest.len(), |i| digits + i);
+        moves.push(parse_move(&rest[..end], &rules)?);
+        rest = &rest[end..];
+    }
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coordinates_count_rows_from_the_bottom() {
+        let rules = Rules::new(15, 15, 5, 2);
+        assert_eq!(format_move(Move::new(7, 7), &rules), "h8");
+        assert_eq!(format_move(Move::new(0, 14), &rules), "a1");
+        assert_eq!(parse_move("O15", &rules), Ok(Move::new(14, 0)));
+        assert!(parse_move("p1", &rules).is_err());
+        assert!(parse_move("a16", &rules).is_err());
+
+        let wide = Rules::new(30, 30, 5, 2);
+        assert_eq!(format_move(Move::new(27, 29), &wide), "ab1");
+        assert_eq!(parse_move("ab1", &wide), Ok(Move::new(27, 29)));
+
+        let infinite = Rules::infinite(5, 2);
+        assert_eq!(format_move(Move::new(-3, 4), &infinite), "-3,4");
+        assert_eq!(parse_move("-3,4", &infinite), Ok(Move::new(-3, 4)));
+    }
+
+    #[test]
+    fn test_moves_far_off_the_board_are_rejected() {
+        let rules = Rules::new(15, 15, 5, 2);
+        let huge = Rules { width: usize::MAX, height: usize::MAX, ..rules };
+        let invalid = |token: &str| Err(NotationError::Move(token.to_string()));
+
+        // Long columns would overflow before reaching the board's edge
+        assert_eq!(parse_move("zzzzzzzzzzzzzzzz1", &huge), invalid("zzzzzzzzzzzzzzzz1"));
+        assert_eq!(parse_move("aaaaaa1", &huge), invalid("aaaaaa1"));
+        assert_eq!(parse_move("zzzzz1", &rules), invalid("zzzzz1"));
+        // As would rows below the bottom, or on a board too tall for a row count
+        let far = format!("a{}", isize::MIN);
+        assert_eq!(parse_move(&far, &rules), invalid(&far));
+        assert_eq!(parse_move("a1", &huge), invalid("a1"));
+        let tall = Rules { height: 1 << 30, ..rules };
+        assert_eq!(parse_move("a1", &tall), invalid("a1"));
+
+        let infinite = Rules::infinite(5, 2);
+        let edge = format!("{},0", INFINITE_REACH);
+        assert_eq!(parse_move(&edge, &infinite), Ok(Move::new(INFINITE_REACH as isize, 0)));
+        for token in [format!("{},0", INFINITE_REACH + 1), format!("0,{}", isize::MIN), "0,99999999999999999999".to_string()] {
+            assert_eq!(parse_move(&token, &infinite), invalid(&token));
+        }
+    }
+
+    #[test]
+    fn test_transcripts_round_trip() {
+        let rules = Rules::new(10, 10, 5, 2);
+        let transcript = Transcript {
+            tags: vec![
+                ("Black".to_string(), "alice \"the wall\"".to_string()),
+                ("Result".to_string(), "1-0".to_string()),
+            ],
+            moves: (0..30).map(|i| Move::new(i % 10, i / 10)).collect(),
+        };
+        let text = transcript.write(&rules);
+        assert!(text.contains("1. a10 b10 2. c10"));
+        assert!(text.trim_end().ends_with("1-0"));
+        assert!(text.lines().all(|line| line.len() <= 80));
+
+        let (read, _) = Transcript::read(&text, |_| Ok(rules)).unwrap();
+        assert_eq!(read, transcript);
+        let comments = "[Result \"*\"]\n\n1. a1 {a corner, why not} b2 *";
+        let (read, _) = Transcript::read(comments, |_| Ok(rules)).unwrap();
+        assert_eq!(read.moves, vec![Move::new(0, 9), Move::new(1, 8)]);
+        assert!(Transcript::read("[Black alice]\n1. a1", |_| Ok(rules)).is_err());
+    }
+
+    #[test]
+    fn test_piskvork_and_renlib() {
+        let text = "Piskvorky 20x20, 11:11, 0\n10,10,0\n11,10,453\n-1\npbrain-one.exe\n";
+        let (size, moves) = read_psq(text).unwrap();
+        assert_eq!(size, (20, 20));
+        assert_eq!(moves, vec![Move::new(9, 9), Move::new(10, 9)]);
+        let rules = Rules::new(20, 20, 5, 2);
+        assert_eq!(read_psq(&write_psq(&rules, &moves).unwrap()).unwrap().1, moves);
+
+        let moves = read_renlib("h8i9 j10").unwrap();
+        assert_eq!(moves, vec![Move::new(7, 7), Move::new(8, 6), Move::new(9, 5)]);
+        let rules = Rules::new(15, 15, 5, 2);
+        assert_eq!(write_renlib(&rules, &moves).unwrap(), "h8i9j10");
+        assert!(write_renlib(&Rules::default(), &moves).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{Move, MoveError, Position};
+use std::fmt;
+
+/// Balanced openings that take away the first player's advantage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum OpeningRule {
+    /// One stone is placed, then the opponent picks a colour
+    Pie,
+    /// Three stones are placed, then the opponent picks a colour
+    Swap,
+    /// Three stones are placed, then the opponent picks a colour or places
+    /// two more and lets the first player pick
+    Swap2,
+}
+
+/// Who sets up the opening and who answers it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Seat {
+    Proposer,
+    Responder,
+}
+
+impl Seat {
+    fn other(self) -> Self {
+        match self {
+            Seat::Proposer => Seat::Responder,
+            Seat::Responder => Seat::Proposer,
+        }
+    }
+}
+
+/// Answer to an opening: a colour for the chooser, or two more stones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Choice {
+    Black,
+    White,
+    PlaceTwo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// `seat` places `remaining` more stones, colours alternating as usual
+    Place { seat: Seat, remaining: usize },
+    /// `seat` picks one of [`Opening::choices`]
+    Choose { seat: Seat },
+    /// The seat that ended up with black
+    Done { black: Seat },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpeningError {
+    WrongStage,
+    WrongSeat,
+    ChoiceNotOffered,
+    Move(MoveError),
+}
+
+impl fmt::Display for OpeningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpeningError::WrongStage => write!(f, "not expected at this stage of the opening"),
+            OpeningError::WrongSeat => write!(f, "not this player's turn in the opening"),
+            OpeningError::ChoiceNotOffered => write!(f, "choice is not offered"),
+            OpeningError::Move(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for OpeningError {}
+
+/// Progress through an opening rule, placing its stones on a [`Position`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opening {
+    rule: OpeningRule,
+    stage: Stage,
+}
+
+impl Opening {
+    pub fn new(rule: OpeningRule) -> Self {
+        let remaining = match rule {
+            OpeningRule::Pie => 1,
+            OpeningRule::Swap | OpeningRule::Swap2 => 3,
+        };
+        Self { rule, stage: Stage::Place { seat: Seat::Proposer, remaining } }
+    }
+
+    pub fn rule(&self) -> OpeningRule {
+        self.rule
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// Seat expected to act next, until the opening is done
+    pub fn to_act(&self) -> Option<Seat> {
+        match self.stage {
+            Stage::Place { seat, .. } | Stage::Choose { seat } => Some(seat),
+            Stage::Done { .. } => None,
+        }
+    }
+
+    pub fn choices(&self) -> &'static [Choice] {
+        match (self.rule, self.stage) {
+            // Only the first answer to Swap2 may hand the choice back
+            (OpeningRule::Swap2, Stage::Choose { seat: Seat::Responder }) => {
+                &[Choice::Black, Choice::White, Choice::PlaceTwo]
+            }
+            (_, Stage::Choose { .. }) => &[Choice::Black, Choice::White],
+            _ => &[],
+        }
+    }
+
+    pub fn place(&mut self, seat: Seat, position: &mut Position, mv: Move) -> Result<(), OpeningError> {
+        let Stage::Place { seat: placing, remaining } = self.stage else {
+            return Err(OpeningError::WrongStage);
+        };
+        if seat != placing {
+            return Err(OpeningError::WrongSeat);
+        }
+        position.apply(mv).map_err(OpeningError::Move)?;
+
+        self.stage = match remaining - 1 {
+            0 => Stage::Choose { seat: seat.other() },
+            remaining => Stage::Place { seat, remaining },
+        };
+        Ok(())
+    }
+
+    pub fn choose(&mut self, seat: Seat, choice: Choice) -> Result<(), OpeningError> {
+        let Stage::Choose { seat: choosing } = self.stage else {
+            return Err(OpeningError::WrongStage);
+        };
+        if seat != choosing {
+            return Err(OpeningError::WrongSeat);
+        }
+        if !self.choices().contains(&choice) {
+            return Err(OpeningError::ChoiceNotOffered);
+        }
+
+        self.stage = match choice {
+            Choice::Black => Stage::Done { black: seat },
+            Choice::White => Stage::Done { black: seat.other() },
+            Choice::PlaceTwo => Stage::Place { seat, remaining: 2 },
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place_all(opening: &mut Opening, seat: Seat, position: &mut Position, moves: &[(isize, isize)]) {
+        for &(x, y) in moves {
+            opening.place(seat, position, Move::new(x, y)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pie_responder_can_take_the_first_stone() {
+        let mut position = Position::default();
+        let mut opening = Opening::new(OpeningRule::Pie);
+        place_all(&mut opening, Seat::Proposer, &mut position, &[(4, 4)]);
+
+        assert_eq!(opening.stage(), Stage::Choose { seat: Seat::Responder });
+        assert_eq!(opening.choose(Seat::Responder, Choice::PlaceTwo), Err(OpeningError::ChoiceNotOffered));
+        opening.choose(Seat::Responder, Choice::Black).unwrap();
+        assert_eq!(opening.stage(), Stage::Done { black: Seat::Responder });
+        assert_eq!(position.to_move(), 1);
+    }
+
+    #[test]
+    fn test_swap_places_three_stones_of_alternating_colour() {
+        let mut position = Position::default();
+        let mut opening = Opening::new(OpeningRule::Swap);
+        place_all(&mut opening, Seat::Proposer, &mut position, &[(4, 4), (5, 5), (4, 5)]);
+
+        assert_eq!(position.get(4, 4), Some(0));
+        assert_eq!(position.get(5, 5), Some(1));
+        assert_eq!(position.get(4, 5), Some(0));
+        assert_eq!(
+            opening.place(Seat::Proposer, &mut position, Move::new(0, 0)),
+            Err(OpeningError::WrongStage)
+        );
+
+        opening.choose(Seat::Responder, Choice::White).unwrap();
+        assert_eq!(opening.stage(), Stage::Done { black: Seat::Proposer });
+    }
+
+    #[test]
+    fn test_swap2_can_hand_the_choice_back() {
+        let mut position = Position::default();
+        let mut opening = Opening::new(OpeningRule::Swap2);
+        place_all(&mut opening, Seat::Proposer, &mut position, &[(4, 4), (5, 5), (4, 5)]);
+
+        assert_eq!(opening.choose(Seat::Proposer, Choice::Black), Err(OpeningError::WrongSeat));
+        opening.choose(Seat::Responder, Choice::PlaceTwo).unwrap();
+        assert_eq!(
+            opening.place(Seat::Responder, &mut position, Move::new(4, 4)),
+            Err(OpeningError::Move(MoveError::Occupied))
+        );
+        place_all(&mut opening, Seat::Responder, &mut position, &[(6, 6), (3, 3)]);
+
+        // The second choice is only between colours
+        assert_eq!(opening.choices(), &[Choice::Black, Choice::White]);
+        opening.choose(Seat::Proposer, Choice::White).unwrap();
+        assert_eq!(opening.stage(), Stage::Done { black: Seat::Responder });
+        assert_eq!(position.history().len(), 5);
+        assert_eq!(position.to_move(), 1);
+    }
+}
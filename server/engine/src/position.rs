@@ -0,0 +1,613 @@
+use crate::{
+    renju::{self, Forbidden, BLACK},
+    Rules, Variant,
+};
+use std::{collections::HashMap, fmt};
+
+/// A cell on the board. Coordinates are signed so that infinite boards can
+/// grow in every direction; bounded boards start at zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Move {
+    pub x: isize,
+    pub y: isize,
+}
+
+impl Move {
+    pub const fn new(x: isize, y: isize) -> Self {
+        Self { x, y }
+    }
+
+    /// The cell `k` steps away in direction `(dx, dy)`
+    pub const fn offset(self, (dx, dy): (isize, isize), k: isize) -> Self {
+        Self::new(self.x + dx * k, self.y + dy * k)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    OutOfBounds,
+    Occupied,
+    GameOver,
+    Forbidden(Forbidden),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::OutOfBounds => write!(f, "move is outside the board"),
+            MoveError::Occupied => write!(f, "cell is already occupied"),
+            MoveError::GameOver => write!(f, "game is already over"),
+            MoveError::Forbidden(Forbidden::DoubleThree) => write!(f, "double three is forbidden"),
+            MoveError::Forbidden(Forbidden::DoubleFour) => write!(f, "double four is forbidden"),
+            MoveError::Forbidden(Forbidden::Overline) => write!(f, "overline is forbidden"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// A straight run of stones: `len` cells from `start`, stepping by `step`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    pub start: Move,
+    pub step: (isize, isize),
+    pub len: usize,
+}
+
+impl Line {
+    pub fn cells(&self) -> impl Iterator<Item = Move> {
+        let Line { start, step, len } = *self;
+        (0..len as isize).map(move |i| start.offset(step, i))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win { player: usize, line: Line },
+    /// `player` reached [`Rules::captures_to_win`]
+    Captures { player: usize },
+    Draw,
+}
+
+// Horizontal, vertical and both diagonals; the opposite directions are
+// covered by walking each one backwards
+pub(crate) const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+// Owner of every cell on a bounded board; infinite boards only keep the
+// occupied cells
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Cells {
+    Dense { width: usize, cells: Vec<Option<u8>> },
+    Sparse(HashMap<Move, u8>),
+}
+
+impl Cells {
+    // `mv` must be on the board
+    fn get(&self, mv: Move) -> Option<u8> {
+        match self {
+            Cells::Dense { width, cells } => cells[mv.y as usize * width + mv.x as usize],
+            Cells::Sparse(cells) => cells.get(&mv).copied(),
+        }
+    }
+
+    fn set(&mut self, mv: Move, owner: u8) {
+        match self {
+            Cells::Dense { width, cells } => cells[mv.y as usize * *width + mv.x as usize] = Some(owner),
+            Cells::Sparse(cells) => {
+                cells.insert(mv, owner);
+            }
+        }
+    }
+
+    fn take(&mut self, mv: Move) -> Option<u8> {
+        match self {
+            Cells::Dense { width, cells } => cells[mv.y as usize * *width + mv.x as usize].take(),
+            Cells::Sparse(cells) => cells.remove(&mv),
+        }
+    }
+}
+
+/// Stones on the board plus the moves that placed them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    rules: Rules,
+    cells: Cells,
+    history: Vec<Move>,
+    // Stones each move removed and whose they were, alongside `history`
+    removed: Vec<Vec<(Move, u8)>>,
+    captures: Vec<usize>,  // Pairs captured by each player
+    outcome: Option<Outcome>,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new(Rules::default())
+    }
+}
+
+impl Position {
+    pub fn new(rules: Rules) -> Self {
+        assert!(rules.players > 0 && rules.players <= u8::MAX as usize, "unsupported player count");
+        let cells = match rules.cells() {
+            Some(count) => Cells::Dense { width: rules.width, cells: vec![None; count] },
+            None => Cells::Sparse(HashMap::new()),
+        };
+        let capacity = rules.cells().unwrap_or_default();
+        Self {
+            rules,
+            cells,
+            history: Vec::with_capacity(capacity),
+            removed: Vec::with_capacity(capacity),
+            captures: vec![0; rules.players],
+            outcome: None,
+        }
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Player whose stone is at `(x, y)`, if any
+    pub fn get(&self, x: isize, y: isize) -> Option<usize> {
+        if !self.rules.contains(x, y) {
+            return None;
+        }
+        self.cells.get(Move::new(x, y)).map(usize::from)
+    }
+
+    /// Stones still on the board
+    pub fn stones(&self) -> usize {
+        let taken: usize = self.captures.iter().sum();
+        self.history.len() - 2 * taken
+    }
+
+    /// Every stone on the board and whose it is, by column and then row
+    pub fn occupied(&self) -> Vec<(Move, usize)> {
+        let mut stones: Vec<(Move, usize)> = match &self.cells {
+            Cells::Dense { width, cells } => cells
+                .iter()
+                .enumerate()
+                .filter_map(|(i, owner)| Some((Move::new((i % width) as isize, (i / width) as isize), usize::from((*owner)?))))
+                .collect(),
+            Cells::Sparse(cells) => cells.iter().map(|(&mv, &owner)| (mv, usize::from(owner))).collect(),
+        };
+        stones.sort_by_key(|&(mv, _)| (mv.x, mv.y));
+        stones
+    }
+
+    /// Smallest and largest corner of the box around every stone on the
+    /// board, `None` while it is empty
+    pub fn bounds(&self) -> Option<(Move, Move)> {
+        let occupied: Vec<Move> = match &self.cells {
+            Cells::Dense { .. } => self.history.iter().copied().filter(|&mv| self.cells.get(mv).is_some()).collect(),
+            Cells::Sparse(cells) => cells.keys().copied().collect(),
+        };
+        let min = Move::new(occupied.iter().map(|mv| mv.x).min()?, occupied.iter().map(|mv| mv.y).min()?);
+        let max = Move::new(occupied.iter().map(|mv| mv.x).max()?, occupied.iter().map(|mv| mv.y).max()?);
+        Some((min, max))
+    }
+
+    /// Player to place the next stone
+    pub fn to_move(&self) -> usize {
+        self.rules.player_at(self.history.len())
+    }
+
+    /// Stones the player to move still places this turn, including the next one
+    pub fn stones_left(&self) -> usize {
+        let ply = self.history.len();
+        (ply..).take_while(|&p| self.rules.player_at(p) == self.to_move()).count()
+    }
+
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    /// Stones captured by the move at `ply` in [`Position::history`]
+    pub fn captured(&self, ply: usize) -> impl Iterator<Item = Move> + '_ {
+        self.removed.get(ply).into_iter().flatten().map(|&(mv, _)| mv)
+    }
+
+    /// Pairs of stones `player` has captured
+    pub fn captures(&self, player: usize) -> usize {
+        self.captures.get(player).copied().unwrap_or(0)
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.history.last().copied()
+    }
+
+    pub fn outcome(&self) -> Option<&Outcome> {
+        self.outcome.as_ref()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    /// Infinite boards never fill up
+    pub fn is_full(&self) -> bool {
+        self.rules.cells() == Some(self.stones())
+    }
+
+    /// Whether `mv` could be played now, and why not
+    pub fn check(&self, mv: Move) -> Result<(), MoveError> {
+        if self.outcome.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if !self.rules.contains(mv.x, mv.y) {
+            return Err(MoveError::OutOfBounds);
+        }
+        if self.cells.get(mv).is_some() {
+            return Err(MoveError::Occupied);
+        }
+        if self.rules.variant == Variant::Renju && self.to_move() == BLACK {
+            if let Some(reason) = renju::forbidden(self, mv) {
+                return Err(MoveError::Forbidden(reason));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.check(mv).is_ok()
+    }
+
+    /// Every legal move on a bounded board. An infinite board has no end of
+    /// them, so only those in the box around the stones and one cell beyond
+    /// are listed, or the origin on an empty board.
+    pub fn legal_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        let (min, max) = match (self.rules.infinite, self.bounds()) {
+            (false, _) => (Move::new(0, 0), Move::new(self.rules.width as isize - 1, self.rules.height as isize - 1)),
+            (true, Some((min, max))) => (min.offset((1, 1), -1), max.offset((1, 1), 1)),
+            (true, None) => (Move::new(0, 0), Move::new(0, 0)),
+        };
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| Move::new(x, y)))
+            .filter(|&mv| self.is_legal(mv))
+    }
+
+    /// Place a stone for the player to move, returning the outcome if this
+    /// move ended the game
+    pub fn apply(&mut self, mv: Move) -> Result<Option<Outcome>, MoveError> {
+        self.check(mv)?;
+
+        let player = self.to_move();
+        self.cells.set(mv, player as u8);
+        self.history.push(mv);
+        let removed = match self.rules.captures_to_win() {
+            Some(_) => self.capture(mv, player),
+            None => Vec::new(),
+        };
+        self.captures[player] += removed.len() / 2;
+        self.removed.push(removed);
+
+        self.outcome = match self.winning_line(mv) {
+            Some(line) => Some(Outcome::Win { player, line }),
+            None if self.rules.captures_to_win().is_some_and(|n| self.captures[player] >= n) => {
+                Some(Outcome::Captures { player })
+            }
+            None if self.is_full() => Some(Outcome::Draw),
+            None => None,
+        };
+        Ok(self.outcome)
+    }
+
+    /// Take back the last move, putting back any stones it captured
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.history.pop()?;
+        let player = self.cells.take(mv).map_or(0, usize::from);
+        let removed = self.removed.pop().unwrap_or_default();
+        self.captures[player] -= removed.len() / 2;
+        for (stone, owner) in removed {
+            self.cells.set(stone, owner);
+        }
+        self.outcome = None;
+        Some(mv)
+    }
+
+    // Remove every pair of one opponent's stones that `mv` closes off, in all
+    // eight directions
+    fn capture(&mut self, mv: Move, player: usize) -> Vec<(Move, u8)> {
+        let mut removed = Vec::new();
+        for dir in DIRECTIONS.into_iter().flat_map(|(dx, dy)| [(dx, dy), (-dx, -dy)]) {
+            let (first, second, end) = (mv.offset(dir, 1), mv.offset(dir, 2), mv.offset(dir, 3));
+            let owner = self.get(first.x, first.y);
+            if owner.is_none_or(|o| o == player)
+                || self.get(second.x, second.y) != owner
+                || self.get(end.x, end.y) != Some(player)
+            {
+                continue;
+            }
+            for stone in [first, second] {
+                removed.push((stone, self.cells.take(stone).unwrap()));
+            }
+        }
+        removed
+    }
+
+    /// Longest run through the stone at `mv` that wins under the variant
+    pub fn winning_line(&self, mv: Move) -> Option<Line> {
+        let player = self.get(mv.x, mv.y)?;
+        DIRECTIONS
+            .into_iter()
+            .map(|dir| self.line_through(mv, dir, player))
+            .filter(|line| self.wins(line, player))
+            .max_by_key(|line| line.len)
+    }
+
+    /// Whether a stone of `player` on the empty cell `mv` would make a
+    /// winning line in direction `dir`
+    pub(crate) fn wins_along(&self, mv: Move, dir: (isize, isize), player: usize) -> bool {
+        self.wins(&self.line_through(mv, dir, player), player)
+    }
+
+    // Stones set and lifted outside of play, to look at positions that
+    // could arise without going through `apply`
+    pub(crate) fn put(&mut self, mv: Move, player: usize) {
+        self.cells.set(mv, player as u8);
+    }
+
+    pub(crate) fn lift(&mut self, mv: Move) {
+        self.cells.take(mv);
+    }
+
+    // Run of `player`'s stones through `mv`, counting `mv` as theirs
+    fn line_through(&self, mv: Move, (dx, dy): (isize, isize), player: usize) -> Line {
+        let back = self.run(mv, (-dx, -dy), player);
+        let forward = self.run(mv, (dx, dy), player);
+        Line { start: mv.offset((dx, dy), -(back as isize)), step: (dx, dy), len: back + forward + 1 }
+    }
+
+    fn wins(&self, line: &Line, player: usize) -> bool {
+        let win_length = self.rules.win_length;
+        match self.rules.variant {
+            Variant::Freestyle | Variant::Pente | Variant::Connect6 => line.len >= win_length,
+            Variant::Standard => line.len == win_length,
+            Variant::Renju if player == BLACK => line.len == win_length,
+            Variant::Renju => line.len >= win_length,
+            Variant::Caro => {
+                line.len >= win_length
+                    && !(self.blocked(line.start, line.step, -1, player)
+                        && self.blocked(line.start, line.step, line.len as isize, player))
+            }
+        }
+    }
+
+    // Whether the cell `k` steps along from `start` holds an opponent's stone
+    fn blocked(&self, start: Move, step: (isize, isize), k: isize, player: usize) -> bool {
+        let cell = start.offset(step, k);
+        self.get(cell.x, cell.y).is_some_and(|p| p != player)
+    }
+
+    // Stones of `player` next to `from` in one direction, not counting `from`
+    fn run(&self, from: Move, step: (isize, isize), player: usize) -> usize {
+        (1..)
+            .map(|k| from.offset(step, k))
+            .take_while(|cell| self.get(cell.x, cell.y) == Some(player))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::INFINITE_REACH;
+
+    fn play(position: &mut Position, moves: &[(isize, isize)]) -> Option<Outcome> {
+        let mut outcome = None;
+        for &(x, y) in moves {
+            outcome = position.apply(Move::new(x, y)).unwrap();
+        }
+        outcome
+    }
+
+    #[test]
+    fn test_players_alternate() {
+        let mut position = Position::default();
+        play(&mut position, &[(0, 0), (5, 5)]);
+        assert_eq!(position.get(0, 0), Some(0));
+        assert_eq!(position.get(5, 5), Some(1));
+        assert_eq!(position.to_move(), 0);
+    }
+
+    #[test]
+    fn test_illegal_moves_are_rejected() {
+        let mut position = Position::default();
+        play(&mut position, &[(3, 3)]);
+        assert_eq!(position.apply(Move::new(3, 3)), Err(MoveError::Occupied));
+        assert_eq!(position.apply(Move::new(10, 0)), Err(MoveError::OutOfBounds));
+        assert_eq!(position.history().len(), 1);
+    }
+
+    #[test]
+    fn test_diagonal_win_reports_line() {
+        let mut position = Position::default();
+        let outcome = play(
+            &mut position,
+            &[(4, 4), (0, 9), (1, 1), (1, 9), (2, 2), (2, 9), (3, 3), (3, 9), (0, 0)],
+        );
+
+        let Some(Outcome::Win { player, line }) = outcome else {
+            panic!("expected a win, got {:?}", outcome);
+        };
+        assert_eq!(player, 0);
+        let cells: Vec<_> = line.cells().collect();
+        assert_eq!(cells, (0..5).map(|i| Move::new(i, i)).collect::<Vec<_>>());
+        assert_eq!(position.apply(Move::new(9, 9)), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn test_anti_diagonal_win() {
+        let mut position = Position::default();
+        let outcome = play(
+            &mut position,
+            &[(0, 4), (9, 0), (1, 3), (9, 1), (2, 2), (9, 2), (3, 1), (9, 3), (4, 0)],
+        );
+        let Some(Outcome::Win { line, .. }) = outcome else {
+            panic!("expected a win, got {:?}", outcome);
+        };
+        assert_eq!(line.start, Move::new(0, 4));
+        assert_eq!(line.step, (1, -1));
+    }
+
+    #[test]
+    fn test_full_board_is_a_draw() {
+        let mut position = Position::new(Rules::new(3, 3, 3, 2));
+        let outcome = play(
+            &mut position,
+            &[(0, 0), (1, 0), (2, 0), (1, 1), (0, 1), (2, 1), (1, 2), (0, 2), (2, 2)],
+        );
+        assert_eq!(outcome, Some(Outcome::Draw));
+        assert_eq!(position.legal_moves().count(), 0);
+    }
+
+    #[test]
+    fn test_undo_reopens_a_finished_game() {
+        let mut position = Position::default();
+        play(&mut position, &[(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1), (3, 0), (3, 1), (4, 0)]);
+        assert!(position.is_over());
+
+        assert_eq!(position.undo(), Some(Move::new(4, 0)));
+        assert!(!position.is_over());
+        assert_eq!(position.get(4, 0), None);
+        assert_eq!(position.to_move(), 0);
+    }
+
+    // Black builds (0..5, 0) with (5, 0) played first; white plays on row 9
+    fn six_in_a_row(variant: Variant) -> Option<Outcome> {
+        let mut position = Position::new(Rules::default().with_variant(variant));
+        play(
+            &mut position,
+            &[(5, 0), (0, 9), (0, 0), (1, 9), (1, 0), (2, 9), (2, 0), (3, 9), (3, 0), (9, 9), (4, 0)],
+        )
+    }
+
+    #[test]
+    fn test_overlines_win_only_in_freestyle() {
+        assert!(matches!(six_in_a_row(Variant::Freestyle), Some(Outcome::Win { line, .. }) if line.len == 6));
+        assert_eq!(six_in_a_row(Variant::Standard), None);
+    }
+
+    #[test]
+    fn test_renju_rejects_black_overline() {
+        let mut position = Position::new(Rules::default().with_variant(Variant::Renju));
+        play(
+            &mut position,
+            &[(5, 0), (0, 9), (0, 0), (1, 9), (1, 0), (2, 9), (2, 0), (3, 9), (3, 0), (9, 9)],
+        );
+        assert_eq!(position.apply(Move::new(4, 0)), Err(MoveError::Forbidden(Forbidden::Overline)));
+        assert!(!position.legal_moves().any(|mv| mv == Move::new(4, 0)));
+    }
+
+    #[test]
+    fn test_pente_captures_flanked_pairs() {
+        let mut position = Position::new(Rules::default().with_variant(Variant::Pente));
+        // Black closes white's (1, 0), (2, 0) off; white's (5, 5), (6, 5) have no black stone beyond
+        play(&mut position, &[(0, 0), (1, 0), (9, 9), (2, 0), (4, 5), (5, 5), (9, 8), (6, 5)]);
+        assert_eq!(play(&mut position, &[(3, 0)]), None);
+        assert_eq!((position.get(1, 0), position.get(2, 0)), (None, None));
+        assert_eq!(position.captured(8).collect::<Vec<_>>(), vec![Move::new(2, 0), Move::new(1, 0)]);
+        assert_eq!((position.captures(0), position.captures(1)), (1, 0));
+        assert_eq!(position.get(5, 5), Some(1));
+
+        // The cells are free again, and undoing puts the pair back
+        assert!(position.is_legal(Move::new(1, 0)));
+        position.undo();
+        assert_eq!((position.get(1, 0), position.get(2, 0)), (Some(1), Some(1)));
+        assert_eq!(position.captures(0), 0);
+    }
+
+    #[test]
+    fn test_pente_five_captures_win() {
+        let mut position = Position::new(Rules::new(15, 15, 5, 2).with_variant(Variant::Pente));
+        // Each row: black at 0, white at 1 and 2, then black closes at 3
+        for y in 0..4 {
+            let y = y * 2;
+            play(&mut position, &[(0, y), (1, y), (14, y), (2, y), (3, y), (14, y + 1)]);
+        }
+        play(&mut position, &[(0, 10), (1, 10), (14, 12), (2, 10)]);
+        assert_eq!(position.captures(0), 4);
+        assert_eq!(play(&mut position, &[(3, 10)]), Some(Outcome::Captures { player: 0 }));
+    }
+
+    #[test]
+    fn test_connect6_places_two_stones_a_turn() {
+        let mut position = Position::new(Rules::new(19, 19, 6, 2).with_variant(Variant::Connect6));
+        assert_eq!((position.to_move(), position.stones_left()), (0, 1));
+        play(&mut position, &[(9, 9)]);
+        assert_eq!((position.to_move(), position.stones_left()), (1, 2));
+        play(&mut position, &[(0, 0)]);
+        assert_eq!((position.to_move(), position.stones_left()), (1, 1));
+        play(&mut position, &[(0, 1)]);
+        assert_eq!((position.to_move(), position.stones_left()), (0, 2));
+
+        // Black's (9..15, 9); five of them don't win yet
+        play(&mut position, &[(10, 9), (11, 9), (0, 2), (0, 3), (12, 9), (13, 9), (5, 0), (6, 0)]);
+        assert_eq!(position.get(0, 3), Some(1));
+        assert!(matches!(
+            play(&mut position, &[(14, 9)]),
+            Some(Outcome::Win { player: 0, line }) if line.len == 6
+        ));
+    }
+
+    #[test]
+    fn test_caro_needs_an_open_end() {
+        let mut position = Position::new(Rules::default().with_variant(Variant::Caro));
+        // White at both ends of black's row
+        let outcome = play(
+            &mut position,
+            &[(1, 0), (0, 0), (2, 0), (6, 0), (3, 0), (0, 9), (4, 0), (1, 9), (5, 0)],
+        );
+        assert_eq!(outcome, None);
+
+        let mut position = Position::new(Rules::default().with_variant(Variant::Caro));
+        let outcome = play(
+            &mut position,
+            &[(1, 0), (0, 0), (2, 0), (0, 9), (3, 0), (1, 9), (4, 0), (2, 9), (5, 0)],
+        );
+        assert!(matches!(outcome, Some(Outcome::Win { player: 0, .. })));
+    }
+
+    #[test]
+    fn test_infinite_board_has_no_edges() {
+        let mut position = Position::new(Rules::infinite(5, 2));
+        assert_eq!(position.bounds(), None);
+        assert_eq!(position.legal_moves().collect::<Vec<_>>(), vec![Move::new(0, 0)]);
+
+        // A row running from negative coordinates into positive ones
+        let outcome = play(
+            &mut position,
+            &[(-2, -1), (-2, 5), (-1, -1), (-1, 5), (0, -1), (0, 5), (1, -1), (1, 5), (2, -1)],
+        );
+        let Some(Outcome::Win { player: 0, line }) = outcome else {
+            panic!("expected a win, got {:?}", outcome);
+        };
+        assert_eq!(line.start, Move::new(-2, -1));
+        assert_eq!(position.bounds(), Some((Move::new(-2, -1), Move::new(2, 5))));
+        assert!(!position.is_full());
+
+        position.undo();
+        assert_eq!(position.bounds(), Some((Move::new(-2, -1), Move::new(1, 5))));
+        // The box around the stones and one cell beyond it
+        assert_eq!(position.legal_moves().count(), 6 * 9 - 8);
+        assert!(position.is_legal(Move::new(1_000_000, -1_000_000)));
+        // Still far enough out that nothing near it overflows
+        let reach = INFINITE_REACH as isize;
+        assert!(position.is_legal(Move::new(-reach, reach)));
+        assert_eq!(position.check(Move::new(reach + 1, 0)), Err(MoveError::OutOfBounds));
+        assert_eq!(position.check(Move::new(0, isize::MIN)), Err(MoveError::OutOfBounds));
+    }
+
+    #[test]
+    fn test_occupied_lists_only_the_stones() {
+        let mut position = Position::new(Rules::infinite(5, 2));
+        play(&mut position, &[(1_000_000, 1_000_000), (0, 0), (-1_000_000, 5)]);
+        let owners: Vec<(isize, isize, usize)> = position.occupied().iter().map(|&(mv, p)| (mv.x, mv.y, p)).collect();
+        assert_eq!(owners, vec![(-1_000_000, 5, 0), (0, 0, 1), (1_000_000, 1_000_000, 0)]);
+
+        let mut position = Position::new(Rules::default());
+        play(&mut position, &[(3, 1), (0, 2)]);
+        assert_eq!(position.occupied(), vec![(Move::new(0, 2), 1), (Move::new(3, 1), 0)]);
+    }
+}
@@ -0,0 +1,160 @@
+//! Tactics puzzles: positions where the side to move has exactly one way to
+//! force a win with fours, and checking a solver's moves against it.
+
+use crate::{analyse, Limits, Move, MoveError, Outcome, Position};
+
+/// Fewest moves in a solution worth a puzzle: a four, its block and the win
+const MIN_SOLUTION: usize = 3;
+
+/// A position of a game, given by its first `ply` moves, and the only line
+/// that wins from there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    pub ply: usize,
+    /// The solver's moves and the opponent's forced replies, alternating
+    pub solution: Vec<Move>,
+}
+
+/// How far a solver's moves got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case", tag = "status"))]
+pub enum Attempt {
+    /// Right so far; the opponent answers with `reply`
+    Continue { reply: Move },
+    Solved,
+    Failed,
+}
+
+/// The first position of a game with a unique forced win for the side to move
+pub fn find_puzzle(position: &Position, moves: &[Move], limits: Limits) -> Option<Puzzle> {
+    let mut position = position.clone();
+    for (ply, &mv) in moves.iter().enumerate() {
+        if let Some(solution) = unique_win(&position, limits) {
+            return Some(Puzzle { ply, solution });
+        }
+        position.apply(mv).ok()?;
+    }
+    None
+}
+
+/// The forced win of the side to move, if there is one and no other first
+/// move or later attacking move wins as well
+pub fn unique_win(position: &Position, limits: Limits) -> Option<Vec<Move>> {
+    let analysis = analyse(position, limits);
+    let line = analysis.forced_win.filter(|line| line.len() >= MIN_SOLUTION)?;
+    let mut position = position.clone();
+
+    // Each attacking move but the last, which only has to win
+    for (i, &mv) in line.iter().enumerate().take(line.len() - 1) {
+        if i % 2 == 0 && has_other_win(&mut position, mv, limits) {
+            return None;
+        }
+        position.apply(mv).ok()?;
+    }
+    Some(line)
+}
+
+// Whether the side to move forces a win with some four other than `chosen`
+fn has_other_win(position: &mut Position, chosen: Move, limits: Limits) -> bool {
+    let analysis = analyse(position, limits);
+    let attacker = analysis.to_move;
+    let threats = &analysis.threats[attacker];
+    let others: Vec<Move> =
+        threats.fours.iter().chain(&threats.open_threes).copied().filter(|&mv| mv != chosen).collect();
+
+    others.into_iter().any(|mv| {
+        if position.apply(mv).is_err() {
+            return false;
+        }
+        let after = analyse(position, limits);
+        let wins = match after.threats[attacker].wins.as_slice() {
+            _ if !after.threats[after.to_move].wins.is_empty() => false,
+            [] => false,
+            [_, _, ..] => true,
+            &[block] => match position.apply(block) {
+                Ok(_) => {
+                    let wins = analyse(position, limits).forced_win.is_some();
+                    position.undo();
+                    wins
+                }
+                Err(_) => true,
+            },
+        };
+        position.undo();
+        wins
+    })
+}
+
+/// Check the solver's `moves` from the puzzle `position`. A move is right if
+/// it is the solution's or wins at once; the opponent replies with the
+/// solution's moves.
+pub fn attempt(position: &Position, solution: &[Move], moves: &[Move]) -> Result<Attempt, MoveError> {
+    let mut position = position.clone();
+    let solver = position.to_move();
+    let mut status = Attempt::Failed;
+
+    for (i, &mv) in moves.iter().enumerate() {
+        let outcome = position.apply(mv)?;
+        if matches!(outcome, Some(Outcome::Win { player, .. } | Outcome::Captures { player }) if player == solver) {
+            return Ok(Attempt::Solved);
+        }
+        if solution.get(2 * i) != Some(&mv) {
+            return Ok(Attempt::Failed);
+        }
+        status = match solution.get(2 * i + 1) {
+            Some(&reply) => {
+                position.apply(reply)?;
+                Attempt::Continue { reply }
+            }
+            None => Attempt::Failed,
+        };
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rules;
+
+    fn position(moves: &[(isize, isize)]) -> Position {
+        let mut position = Position::new(Rules::new(15, 15, 5, 2));
+        for &(x, y) in moves {
+            position.apply(Move::new(x, y)).unwrap();
+        }
+        position
+    }
+
+    #[test]
+    fn test_broken_three_has_one_solution() {
+        // Black's three with a gap: only filling the gap makes a straight
+        // four, the other fours are blocked in the gap
+        let position = position(&[(3, 5), (0, 0), (5, 5), (0, 14), (6, 5), (14, 0)]);
+        let solution = unique_win(&position, Limits::default()).expect("a unique win");
+        assert_eq!(solution[0], Move::new(4, 5));
+        assert_eq!(solution.len(), 3);
+    }
+
+    #[test]
+    fn test_open_three_wins_at_either_end() {
+        // An open three makes a straight four at both ends, so it isn't unique
+        let position = position(&[(3, 5), (0, 0), (4, 5), (0, 14), (5, 5), (14, 0)]);
+        assert!(analyse(&position, Limits::default()).forced_win.is_some());
+        assert_eq!(unique_win(&position, Limits::default()), None);
+    }
+
+    #[test]
+    fn test_attempts_are_checked_move_by_move() {
+        let position = position(&[(3, 5), (0, 0), (5, 5), (0, 14), (6, 5), (14, 0)]);
+        let solution = unique_win(&position, Limits::default()).unwrap();
+
+        let first = attempt(&position, &solution, &[solution[0]]).unwrap();
+        assert_eq!(first, Attempt::Continue { reply: solution[1] });
+        // The straight four wins at the end the reply left open
+        let open = [Move::new(2, 5), Move::new(7, 5)].into_iter().find(|&mv| mv != solution[1]).unwrap();
+        assert_eq!(attempt(&position, &solution, &[solution[0], open]), Ok(Attempt::Solved));
+        assert_eq!(attempt(&position, &solution, &[Move::new(7, 7)]), Ok(Attempt::Failed));
+        assert!(attempt(&position, &solution, &[Move::new(3, 5)]).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Black's forbidden moves under Renju rules.
+//!
+//! Positions are examined with hypothetical black stones laid over the real
+//! board, so checking a move never has to place it first.
+
+use crate::{position::DIRECTIONS, Move, Position};
+
+pub(crate) const BLACK: usize = 0;
+
+/// Why a move is not allowed for black
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Forbidden {
+    DoubleThree,
+    DoubleFour,
+    Overline,
+}
+
+// The board plus up to three extra black stones
+struct View<'a> {
+    position: &'a Position,
+    extra: [Option<Move>; 3],
+}
+
+impl View<'_> {
+    fn with(&self, mv: Move) -> Self {
+        let mut extra = self.extra;
+        if let Some(slot) = extra.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(mv);
+        }
+        View { position: self.position, extra }
+    }
+
+    fn is_black(&self, mv: Move) -> bool {
+        self.extra.contains(&Some(mv)) || self.position.get(mv.x, mv.y) == Some(BLACK)
+    }
+
+    fn is_empty(&self, mv: Move) -> bool {
+        !self.extra.contains(&Some(mv)) && self.position.get(mv.x, mv.y).is_none()
+    }
+
+    fn offset(&self, mv: Move, dir: (isize, isize), k: isize) -> Option<Move> {
+        let cell = mv.offset(dir, k);
+        self.position.rules().contains(cell.x, cell.y).then_some(cell)
+    }
+
+    // Black stones on either side of `mv`, not counting `mv` itself
+    fn run(&self, mv: Move, (dx, dy): (isize, isize)) -> (usize, usize) {
+        let count = |dir| {
+            (1..)
+                .map_while(|k| self.offset(mv, dir, k))
+                .take_while(|&m| self.is_black(m))
+                .count()
+        };
+        (count((-dx, -dy)), count((dx, dy)))
+    }
+
+    fn line_len(&self, mv: Move, dir: (isize, isize)) -> usize {
+        let (back, forward) = self.run(mv, dir);
+        back + forward + 1
+    }
+
+    /// Empty cells that would complete exactly five through `mv`: how many,
+    /// and the offsets of the first and last
+    fn completions(&self, mv: Move, dir: (isize, isize)) -> (usize, isize, isize) {
+        let win = self.position.rules().win_length as isize;
+        let (mut count, mut first, mut last) = (0, 0, 0);
+        for k in -(win - 1)..win {
+            let Some(q) = self.offset(mv, dir, k).filter(|&q| k != 0 && self.is_empty(q)) else {
+                continue;
+            };
+            let view = self.with(q);
+            let (back, forward) = view.run(q, dir);
+            // The five has to pass through `mv`, not just somewhere along the line
+            let covers = if k > 0 { back as isize >= k } else { forward as isize >= -k };
+            if covers && back + forward + 1 == win as usize {
+                if count == 0 {
+                    first = k;
+                }
+                last = k;
+                count += 1;
+            }
+        }
+        (count, first, last)
+    }
+
+    fn fours(&self, mv: Move, dir: (isize, isize)) -> usize {
+        let win = self.position.rules().win_length as isize;
+        match self.completions(mv, dir) {
+            (0, ..) => 0,
+            (1, ..) => 1,
+            // Both ends of one straight four
+            (2, first, last) if last - first == win => 1,
+            // Two fours on the same line, like X.XXX.X
+            _ => 2,
+        }
+    }
+
+    fn is_straight_four(&self, mv: Move, dir: (isize, isize)) -> bool {
+        let win = self.position.rules().win_length as isize;
+        matches!(self.completions(mv, dir), (2, first, last) if last - first == win)
+    }
+
+    /// A three is a line one stone away from a straight four. Whether that
+    /// stone would itself be forbidden is not considered.
+    fn is_three(&self, mv: Move, dir: (isize, isize)) -> bool {
+        let win = self.position.rules().win_length as isize;
+        (-(win - 1)..win)
+            .filter(|&k| k != 0)
+            .filter_map(|k| self.offset(mv, dir, k))
+            .filter(|&q| self.is_empty(q))
+            .any(|q| self.with(q).is_straight_four(mv, dir))
+    }
+}
+
+/// Why black may not play `mv`, if it may not. Making exactly five always wins.
+pub(crate) fn forbidden(position: &Position, mv: Move) -> Option<Forbidden> {
+    let view = View { position, extra: [Some(mv), None, None] };
+    let win = position.rules().win_length;
+
+    let lengths = DIRECTIONS.map(|dir| view.line_len(mv, dir));
+    if lengths.contains(&win) {
+        return None;
+    }
+    if lengths.iter().any(|&len| len > win) {
+        return Some(Forbidden::Overline);
+    }
+
+    let fours: usize = DIRECTIONS.iter().map(|&dir| view.fours(mv, dir)).sum();
+    if fours >= 2 {
+        return Some(Forbidden::DoubleFour);
+    }
+    // A direction already holding a four can't also count as a three
+    let threes = DIRECTIONS
+        .iter()
+        .filter(|&&dir| view.fours(mv, dir) == 0 && view.is_three(mv, dir))
+        .count();
+    if threes >= 2 {
+        return Some(Forbidden::DoubleThree);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MoveError, Rules, Variant};
+
+    // Black stones at `black`, white stones far away in the corner row
+    fn renju(black: &[(isize, isize)]) -> Position {
+        let mut position = Position::new(Rules::new(15, 15, 5, 2).with_variant(Variant::Renju));
+        for (i, &(x, y)) in (0..).zip(black) {
+            position.apply(Move::new(x, y)).unwrap();
+            position.apply(Move::new(i * 2 % 15, 14 - i * 2 / 15)).unwrap();
+        }
+        position
+    }
+
+    #[test]
+    fn test_double_three_is_forbidden() {
+        let position = renju(&[(5, 7), (6, 7), (7, 5), (7, 6)]);
+        assert_eq!(forbidden(&position, Move::new(7, 7)), Some(Forbidden::DoubleThree));
+        assert_eq!(position.check(Move::new(7, 7)), Err(MoveError::Forbidden(Forbidden::DoubleThree)));
+    }
+
+    #[test]
+    fn test_blocked_three_does_not_count() {
+        let mut position = renju(&[(5, 7), (6, 7), (7, 5), (7, 6)]);
+        // White closes one end of the horizontal three instead of playing in the corner
+        position.undo();
+        position.apply(Move::new(4, 7)).unwrap();
+        position.apply(Move::new(12, 2)).unwrap();
+        position.apply(Move::new(13, 0)).unwrap();
+        assert_eq!(forbidden(&position, Move::new(7, 7)), None);
+    }
+
+    #[test]
+    fn test_double_four_is_forbidden() {
+        let position = renju(&[(4, 7), (5, 7), (6, 7), (7, 4), (7, 5), (7, 6)]);
+        assert_eq!(forbidden(&position, Move::new(7, 7)), Some(Forbidden::DoubleFour));
+    }
+
+    #[test]
+    fn test_four_in_one_line_twice_is_forbidden() {
+        let position = renju(&[(3, 7), (5, 7), (6, 7), (9, 7)]);
+        assert_eq!(forbidden(&position, Move::new(7, 7)), Some(Forbidden::DoubleFour));
+    }
+
+    #[test]
+    fn test_overline_is_forbidden_but_five_wins() {
+        let position = renju(&[(2, 7), (3, 7), (4, 7), (6, 7), (7, 7)]);
+        assert_eq!(forbidden(&position, Move::new(5, 7)), Some(Forbidden::Overline));
+
+        let position = renju(&[(3, 7), (4, 7), (6, 7), (7, 7), (5, 3), (5, 4), (5, 5)]);
+        assert_eq!(forbidden(&position, Move::new(5, 7)), None);
+    }
+
+    #[test]
+    fn test_white_is_never_forbidden() {
+        let mut position = renju(&[(5, 7), (6, 7), (7, 5), (7, 6)]);
+        position.apply(Move::new(0, 0)).unwrap();
+        assert_eq!(position.to_move(), 1);
+        assert!(position.is_legal(Move::new(7, 7)));
+    }
+}
@@ -0,0 +1,116 @@
+/// Which lines win, and which moves are off limits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Variant {
+    /// Any line of `win_length` or more wins
+    #[default]
+    Freestyle,
+    /// Exactly `win_length`; longer lines don't win
+    Standard,
+    /// Black needs exactly `win_length` and may not make double threes,
+    /// double fours or overlines; white wins with any longer line too
+    Renju,
+    /// A line wins unless both of its ends are blocked by the opponent
+    Caro,
+    /// Flanking exactly two stones of one opponent captures them; five
+    /// captures win as well as a line of `win_length` or more
+    Pente,
+    /// After the first stone every turn places two; any line of
+    /// `win_length` or more wins
+    Connect6,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 6] =
+        [Variant::Freestyle, Variant::Standard, Variant::Renju, Variant::Caro, Variant::Pente, Variant::Connect6];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Variant::Freestyle => "freestyle",
+            Variant::Standard => "standard",
+            Variant::Renju => "renju",
+            Variant::Caro => "caro",
+            Variant::Pente => "pente",
+            Variant::Connect6 => "connect6",
+        }
+    }
+
+    /// The variant called `name`, in any case
+    pub fn from_name(name: &str) -> Option<Variant> {
+        Variant::ALL.into_iter().find(|v| v.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// How far from the origin an infinite board goes in each direction, which
+/// keeps coordinates and any offsets from them far from overflowing
+pub const INFINITE_REACH: usize = 1 << 20;
+
+/// Board size, line length needed to win and number of players taking turns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rules {
+    pub width: usize,
+    pub height: usize,
+    pub win_length: usize,
+    pub players: usize,
+    pub variant: Variant,
+    /// No edges: any coordinate, negative ones included, is on the board and
+    /// `width` and `height` are unused
+    pub infinite: bool,
+}
+
+impl Rules {
+    pub const fn new(width: usize, height: usize, win_length: usize, players: usize) -> Self {
+        Self { width, height, win_length, players, variant: Variant::Freestyle, infinite: false }
+    }
+
+    /// A board without edges
+    pub const fn infinite(win_length: usize, players: usize) -> Self {
+        Self { infinite: true, ..Self::new(0, 0, win_length, players) }
+    }
+
+    pub const fn with_variant(self, variant: Variant) -> Self {
+        Self { variant, ..self }
+    }
+
+    /// Cells on the board, `None` if it has no edges
+    pub const fn cells(&self) -> Option<usize> {
+        match self.infinite {
+            true => None,
+            false => Some(self.width * self.height),
+        }
+    }
+
+    /// Whether `(x, y)` is on the board; an infinite board still stops
+    /// [`INFINITE_REACH`] cells from the origin
+    pub const fn contains(&self, x: isize, y: isize) -> bool {
+        match self.infinite {
+            true => x.unsigned_abs() <= INFINITE_REACH && y.unsigned_abs() <= INFINITE_REACH,
+            false => x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height,
+        }
+    }
+
+    /// Player placing the stone at `ply`, counting from zero
+    pub const fn player_at(&self, ply: usize) -> usize {
+        match self.variant {
+            // One stone, then pairs: 0, 1 1, 0 0, 1 1, ...
+            Variant::Connect6 => ply.div_ceil(2) % self.players,
+            _ => ply % self.players,
+        }
+    }
+
+    /// Captures that win the game, if the variant captures at all
+    pub const fn captures_to_win(&self) -> Option<usize> {
+        match self.variant {
+            Variant::Pente => Some(5),
+            _ => None,
+        }
+    }
+}
+
+/// Two players, 10x10 board, five in a row
+impl Default for Rules {
+    fn default() -> Self {
+        Self::new(10, 10, 5, 2)
+    }
+}
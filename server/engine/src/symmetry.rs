@@ -0,0 +1,126 @@
+//! Turning and mirroring the board, so that openings played in different
+//! corners or orientations can be recognised as the same one.
+
+use crate::{Move, Rules};
+
+/// One of the eight ways to turn or mirror a square board. Boards that
+/// aren't square only have the four that keep width and height; infinite
+/// boards have all eight, turning about the first stone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symmetry {
+    transpose: bool,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl Symmetry {
+    pub const IDENTITY: Symmetry = Symmetry { transpose: false, flip_x: false, flip_y: false };
+
+    /// Every symmetry, the identity first
+    pub fn all() -> impl Iterator<Item = Symmetry> {
+        (0..8).map(|i| Symmetry { transpose: i & 4 != 0, flip_x: i & 1 != 0, flip_y: i & 2 != 0 })
+    }
+
+    /// Whether the board under `rules` looks the same after this symmetry
+    pub fn fits(self, rules: &Rules) -> bool {
+        !self.transpose || rules.infinite || rules.width == rules.height
+    }
+
+    pub fn apply(self, mv: Move, rules: &Rules) -> Move {
+        let (mut x, mut y) = if self.transpose { (mv.y, mv.x) } else { (mv.x, mv.y) };
+        let flip = |v: isize, size: usize| match rules.infinite {
+            true => -v,
+            false => size as isize - 1 - v,
+        };
+        if self.flip_x {
+            x = flip(x, rules.width);
+        }
+        if self.flip_y {
+            y = flip(y, rules.height);
+        }
+        Move::new(x, y)
+    }
+
+    // The moves after this symmetry, shifted on infinite boards so that the
+    // first one lands on the origin
+    fn map(self, moves: &[Move], rules: &Rules) -> Vec<Move> {
+        let mapped: Vec<Move> = moves.iter().map(|&mv| self.apply(mv, rules)).collect();
+        match (rules.infinite, mapped.first()) {
+            (true, Some(&first)) => mapped.iter().map(|mv| Move::new(mv.x - first.x, mv.y - first.y)).collect(),
+            _ => mapped,
+        }
+    }
+}
+
+/// The same sequence of moves under every symmetry of the board, picking the
+/// one that sorts first, so that equivalent openings share one form
+pub fn canonical(rules: &Rules, moves: &[Move]) -> Vec<Move> {
+    Symmetry::all()
+        .filter(|s| s.fits(rules))
+        .map(|s| s.map(moves, rules))
+        .min_by_key(|line| line.iter().map(|mv| (mv.y, mv.x)).collect::<Vec<_>>())
+        .unwrap_or_default()
+}
+
+/// Carry `mv` along with the symmetry that takes the moves `from` onto `to`,
+/// if there is one
+pub fn transfer(rules: &Rules, from: &[Move], to: &[Move], mv: Move) -> Option<Move> {
+    if from.len() != to.len() {
+        return None;
+    }
+    Symmetry::all().filter(|s| s.fits(rules)).find_map(|s| {
+        let mapped: Vec<Move> = from.iter().map(|&m| s.apply(m, rules)).collect();
+        // Infinite boards also shift, by whatever lines the first moves up
+        let (dx, dy) = match (rules.infinite, mapped.first(), to.first()) {
+            (true, Some(a), Some(b)) => (b.x - a.x, b.y - a.y),
+            _ => (0, 0),
+        };
+        let shifted = |m: Move| Move::new(m.x + dx, m.y + dy);
+        mapped.iter().zip(to).all(|(&a, &b)| shifted(a) == b).then(|| shifted(s.apply(mv, rules)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(moves: &[(isize, isize)]) -> Vec<Move> {
+        moves.iter().map(|&(x, y)| Move::new(x, y)).collect()
+    }
+
+    #[test]
+    fn test_mirrored_openings_share_a_form() {
+        let rules = Rules::new(15, 15, 5, 2);
+        let opening = moves(&[(7, 7), (8, 6), (9, 7)]);
+        let forms: Vec<Vec<Move>> = Symmetry::all()
+            .map(|s| canonical(&rules, &opening.iter().map(|&mv| s.apply(mv, &rules)).collect::<Vec<_>>()))
+            .collect();
+        assert!(forms.iter().all(|form| *form == forms[0]));
+        assert_ne!(canonical(&rules, &moves(&[(7, 7), (8, 6), (10, 7)])), forms[0]);
+    }
+
+    #[test]
+    fn test_rectangles_are_not_transposed() {
+        let rules = Rules::new(10, 6, 5, 2);
+        assert_eq!(Symmetry::all().filter(|s| s.fits(&rules)).count(), 4);
+        assert_eq!(canonical(&rules, &moves(&[(8, 4)])), moves(&[(1, 1)]));
+    }
+
+    #[test]
+    fn test_infinite_boards_ignore_where_play_starts() {
+        let rules = Rules::infinite(5, 2);
+        let here = moves(&[(100, -40), (101, -40)]);
+        let there = moves(&[(0, 3), (0, 2)]);
+        assert_eq!(canonical(&rules, &here), canonical(&rules, &there));
+        assert_eq!(transfer(&rules, &here, &there, Move::new(102, -40)), Some(Move::new(0, 1)));
+    }
+
+    #[test]
+    fn test_moves_are_carried_back() {
+        let rules = Rules::new(15, 15, 5, 2);
+        let played = moves(&[(3, 3), (4, 3)]);
+        let stored = canonical(&rules, &played);
+        let next = transfer(&rules, &played, &stored, Move::new(5, 3)).unwrap();
+        assert_eq!(transfer(&rules, &stored, &played, next), Some(Move::new(5, 3)));
+    }
+}
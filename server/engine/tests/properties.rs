@@ -0,0 +1,130 @@
+//! Randomised checks over many self-played games. The generator is seeded, so
+//! a failure reproduces by rerunning with the printed seed.
+
+use tictac_engine::{Move, Outcome, Position, Rules, Variant};
+
+const GAMES: u64 = 500;
+
+/// xorshift64*, enough to drive random playouts without extra dependencies
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) % n as u64) as usize
+    }
+}
+
+fn rules_for(rng: &mut Rng) -> Rules {
+    let width = 3 + rng.below(10);
+    let height = 3 + rng.below(10);
+    let win_length = 3 + rng.below(width.min(height) - 2);
+    Rules::new(width, height, win_length, 2 + rng.below(2))
+}
+
+/// Play random legal moves until the game ends, returning every position passed
+fn random_game(rng: &mut Rng, rules: Rules) -> Vec<Position> {
+    let mut position = Position::new(rules);
+    let mut seen = vec![position.clone()];
+    while !position.is_over() {
+        let moves: Vec<Move> = position.legal_moves().collect();
+        let mv = moves[rng.below(moves.len())];
+        position.apply(mv).unwrap();
+        seen.push(position.clone());
+    }
+    seen
+}
+
+// Reference implementation: scan every cell and direction for a run
+fn brute_force_winner(position: &Position) -> Option<usize> {
+    let rules = position.rules();
+    for x in 0..rules.width as isize {
+        for y in 0..rules.height as isize {
+            let Some(player) = position.get(x, y) else { continue };
+            for (dx, dy) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+                let run = (0..rules.win_length as isize)
+                    .take_while(|&i| position.get(x + dx * i, y + dy * i) == Some(player))
+                    .count();
+                if run == rules.win_length {
+                    return Some(player);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[test]
+fn test_outcome_matches_brute_force() {
+    for seed in 0..GAMES {
+        let mut rng = Rng::new(seed);
+        let rules = rules_for(&mut rng);
+        for position in random_game(&mut rng, rules) {
+            let expected = brute_force_winner(&position);
+            match position.outcome() {
+                Some(Outcome::Win { player, .. }) => assert_eq!(Some(*player), expected, "seed {}", seed),
+                Some(Outcome::Draw) => {
+                    assert!(position.is_full() && expected.is_none(), "seed {}", seed)
+                }
+                None => assert!(expected.is_none() && !position.is_full(), "seed {}", seed),
+                Some(Outcome::Captures { .. }) => panic!("capture win without captures, seed {}", seed),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_winning_line_belongs_to_winner() {
+    for seed in 0..GAMES {
+        let mut rng = Rng::new(seed);
+        let rules = rules_for(&mut rng);
+        let last = random_game(&mut rng, rules).pop().unwrap();
+        let Some(Outcome::Win { player, line }) = last.outcome() else { continue };
+
+        assert!(line.len >= rules.win_length, "seed {}", seed);
+        assert!(line.cells().any(|c| Some(c) == last.last_move()), "seed {}", seed);
+        for cell in line.cells() {
+            assert_eq!(last.get(cell.x, cell.y), Some(*player), "seed {}", seed);
+        }
+    }
+}
+
+#[test]
+fn test_undo_restores_every_position() {
+    for seed in 0..GAMES {
+        let mut rng = Rng::new(seed);
+        // Pente has captured stones to put back, Connect6 turns of two stones
+        let variant = [Variant::Freestyle, Variant::Pente, Variant::Connect6][seed as usize % 3];
+        let rules = rules_for(&mut rng).with_variant(variant);
+        let mut seen = random_game(&mut rng, rules);
+        let mut position = seen.pop().unwrap();
+        while let Some(previous) = seen.pop() {
+            position.undo().unwrap();
+            assert_eq!(position, previous, "seed {}", seed);
+        }
+        assert_eq!(position.undo(), None);
+    }
+}
+
+#[test]
+fn test_legal_moves_are_exactly_the_empty_cells() {
+    for seed in 0..GAMES {
+        let mut rng = Rng::new(seed);
+        let rules = rules_for(&mut rng);
+        for position in random_game(&mut rng, rules) {
+            let legal = position.legal_moves().count();
+            if position.is_over() {
+                assert_eq!(legal, 0, "seed {}", seed);
+            } else {
+                assert_eq!(legal, rules.cells().unwrap() - position.history().len(), "seed {}", seed);
+                assert!(position.legal_moves().all(|mv| position.get(mv.x, mv.y).is_none()));
+            }
+        }
+    }
+}
@@ -1,15 +1,16 @@
 use crate::{
     api::auth::AppError,
-    auth::AdminUser,
-    db::get_db,
-    models::{AdminUpdateUserRequest, User, UserProfile},
+    auth::{ManageUsers, Permitted, ViewStats},
+    models::{AdminUpdateUserRequest, LockedAccount, User, UserProfile},
+    repo::user_record,
+    roles::{Permission, Role},
+    state::AppState,
 };
 use axum::{
-    extract::{Path, Query},
+    extract::{Path, Query, State},
     Json,
 };
 use serde::Deserialize;
-use surrealdb::RecordId;
 
 #[derive(Deserialize)]
 pub struct UserListQuery {
@@ -25,110 +26,64 @@ fn default_limit() -> usize {
     50
 }
 
-pub async fn list_users(
-    _admin: AdminUser,
-    Query(params): Query<UserListQuery>,
-) -> Result<Json<Vec<UserProfile>>, AppError> {
-    let mut result = if let Some(ref search) = params.search {
-        get_db()
-            .query(
-                r#"
-                SELECT * FROM user 
-                WHERE email ~ $search OR username ~ $search
-                ORDER BY created_at DESC 
-                LIMIT $limit 
-                START $offset
-                "#,
-            )
-            .bind(("search", search.clone()))
-            .bind(("limit", params.limit))
-            .bind(("offset", params.offset))
-            .await
-            .map_err(|e| AppError::Database(e.to_string()))?
+async fn profile_with_stats(state: &AppState, user: User) -> Result<UserProfile, AppError> {
+    let user_id = user.id.clone().unwrap();
+    let (games_played, games_won) = state.users.game_stats(&user_id).await?;
+
+    let win_rate = if games_played > 0 {
+        (games_won as f64 / games_played as f64) * 100.0
     } else {
-        get_db()
-            .query(
-                r#"
-                SELECT * FROM user 
-                ORDER BY created_at DESC 
-                LIMIT $limit 
-                START $offset
-                "#,
-            )
-            .bind(("limit", params.limit))
-            .bind(("offset", params.offset))
-            .await
-            .map_err(|e| AppError::Database(e.to_string()))?
+        0.0
     };
 
-    let users: Vec<User> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    Ok(UserProfile {
+        id: user_id.to_string(),
+        email: user.email,
+        username: user.username,
+        profile_picture: user.profile_picture,
+        elo: user.elo,
+        puzzle_rating: user.puzzle_rating,
+        games_played,
+        games_won,
+        win_rate,
+        is_guest: user.is_guest,
+        roles: user.roles,
+        is_bot: user.is_bot,
+    })
+}
+
+pub async fn list_users(
+    State(state): State<AppState>,
+    _admin: Permitted<ManageUsers>,
+    Query(params): Query<UserListQuery>,
+) -> Result<Json<Vec<UserProfile>>, AppError> {
+    let users = state.users
+        .list(params.search, params.limit, params.offset)
+        .await?;
 
     // Get game stats for all users
     let mut profiles = Vec::new();
-    
     for user in users {
-        let user_id = user.id.as_ref().unwrap();
-        
-        // Query game stats - simplify to avoid multiple result sets
-        // Count total games - select only id to avoid serialization issues
-        let mut total_result = get_db()
-            .query("SELECT id FROM game WHERE (player1 = $user_id OR player2 = $user_id) AND status = 'completed'")
-            .bind(("user_id", user_id.clone()))
-            .await
-            .map_err(|e| AppError::Database(e.to_string()))?;
-        
-        let total_games: Vec<serde_json::Value> = total_result
-            .take(0)
-            .map_err(|e| AppError::Database(e.to_string()))?;
-        
-        let games_played = total_games.len() as i32;
-        
-        // Count wins - select only id to avoid serialization issues
-        let mut wins_result = get_db()
-            .query("SELECT id FROM game WHERE winner = $user_id AND status = 'completed'")
-            .bind(("user_id", user_id.clone()))
-            .await
-            .map_err(|e| AppError::Database(e.to_string()))?;
-        
-        let wins: Vec<serde_json::Value> = wins_result
-            .take(0)
-            .map_err(|e| AppError::Database(e.to_string()))?;
-        
-        let games_won = wins.len() as i32;
-
-        let win_rate = if games_played > 0 {
-            (games_won as f64 / games_played as f64) * 100.0
-        } else {
-            0.0
-        };
-
-        profiles.push(UserProfile {
-            id: user_id.to_string(),
-            email: user.email,
-            username: user.username,
-            profile_picture: user.profile_picture,
-            elo: user.elo,
-            games_played,
-            games_won,
-            win_rate,
-        });
+        profiles.push(profile_with_stats(&state, user).await?);
     }
 
     Ok(Json(profiles))
 }
 
 pub async fn update_user(
-    _admin: AdminUser,
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<ManageUsers>,
     Path(user_id): Path<String>,
     Json(req): Json<AdminUpdateUserRequest>,
 ) -> Result<Json<UserProfile>, AppError> {
-    let user_id = RecordId::from(("user", user_id.as_str()));
-    
-    let mut update_data = serde_json::json!({
-        "updated_at": chrono::Utc::now(),
-    });
+    let user_id = user_record(&user_id);
+
+    // Granting or revoking roles needs more than plain user management
+    if (req.roles.is_some() || req.is_admin.is_some()) && !claims.has_permission(Permission::ManageRoles) {
+        return Err(AppError::Forbidden);
+    }
+
+    let mut update_data = serde_json::json!({});
 
     if let Some(email) = req.email {
         update_data["email"] = serde_json::json!(email);
@@ -139,117 +94,100 @@ pub async fn update_user(
     if let Some(elo) = req.elo {
         update_data["elo"] = serde_json::json!(elo);
     }
-    if let Some(is_admin) = req.is_admin {
-        update_data["is_admin"] = serde_json::json!(is_admin);
+    if let Some(is_bot) = req.is_bot {
+        update_data["is_bot"] = serde_json::json!(is_bot);
+    }
+    // Keep the legacy is_admin flag and the admin role in sync
+    if req.roles.is_some() || req.is_admin.is_some() {
+        let mut roles: Vec<Role> = match req.roles {
+            Some(requested) => requested.into_iter().fold(Vec::new(), |mut roles, role| {
+                if !roles.contains(&role) {
+                    roles.push(role);
+                }
+                roles
+            }),
+            None => state.users.find(&user_id).await?.ok_or(AppError::NotFound)?.roles,
+        };
+        match req.is_admin {
+            Some(true) if !roles.contains(&Role::Admin) => roles.push(Role::Admin),
+            Some(false) => roles.retain(|role| *role != Role::Admin),
+            _ => {}
+        }
+        update_data["is_admin"] = serde_json::json!(roles.contains(&Role::Admin));
+        update_data["roles"] = serde_json::json!(roles);
     }
 
-    let _: Option<User> = get_db()
-        .update(user_id.clone())
-        .merge(update_data)
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    let user: Option<User> = get_db()
-        .select(user_id.clone())
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    let user = state.users
+        .merge(&user_id, update_data)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    let user = user.ok_or(AppError::NotFound)?;
+    Ok(Json(profile_with_stats(&state, user).await?))
+}
 
-    // Query game stats - simplify to avoid multiple result sets
-    // Count total games
-    let mut total_result = get_db()
-        .query("SELECT * FROM game WHERE (player1 = $user_id OR player2 = $user_id) AND status = 'completed'")
-        .bind(("user_id", user_id.clone()))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let total_games: Vec<serde_json::Value> = total_result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let games_played = total_games.len() as i32;
-    
-    // Count wins
-    let mut wins_result = get_db()
-        .query("SELECT * FROM game WHERE winner = $user_id AND status = 'completed'")
-        .bind(("user_id", user_id))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let wins: Vec<serde_json::Value> = wins_result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let games_won = wins.len() as i32;
+pub async fn delete_user(
+    State(state): State<AppState>,
+    _admin: Permitted<ManageUsers>,
+    Path(user_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    state.users.delete(&user_record(&user_id)).await?;
 
-    let win_rate = if games_played > 0 {
-        (games_won as f64 / games_played as f64) * 100.0
-    } else {
-        0.0
-    };
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "User deleted successfully"
+    })))
+}
 
-    let profile = UserProfile {
-        id: user.id.as_ref().unwrap().to_string(),
-        email: user.email,
-        username: user.username,
-        profile_picture: user.profile_picture,
-        elo: user.elo,
-        games_played,
-        games_won,
-        win_rate,
-    };
+/// Accounts that currently have failed logins or an active lock
+pub async fn list_locked_users(
+    State(state): State<AppState>,
+    _admin: Permitted<ManageUsers>,
+) -> Result<Json<Vec<LockedAccount>>, AppError> {
+    let accounts = state.users
+        .locked()
+        .await?
+        .into_iter()
+        .map(|user| LockedAccount {
+            id: user.id.as_ref().unwrap().to_string(),
+            email: user.email,
+            username: user.username,
+            failed_logins: user.failed_logins,
+            locked_until: user.locked_until,
+        })
+        .collect();
 
-    Ok(Json(profile))
+    Ok(Json(accounts))
 }
 
-pub async fn delete_user(
-    _admin: AdminUser,
+pub async fn unlock_user(
+    State(state): State<AppState>,
+    _admin: Permitted<ManageUsers>,
     Path(user_id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let user_id = RecordId::from(("user", user_id.as_str()));
-    
-    let _: Option<User> = get_db()
-        .delete(user_id)
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    state.users.reset_lockout(&user_record(&user_id)).await?;
 
     Ok(Json(serde_json::json!({
         "success": true,
-        "message": "User deleted successfully"
+        "message": "User unlocked successfully"
     })))
 }
 
-pub async fn get_stats(_admin: AdminUser) -> Result<Json<serde_json::Value>, AppError> {
-    // Get stats separately to avoid multiple result sets
-    let mut users_result = get_db()
-        .query("SELECT * FROM user")
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let users: Vec<User> = users_result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
+#[allow(clippy::unnecessary_literal_unwrap)]
+pub async fn get_stats(
+    State(state): State<AppState>,
+    _admin: Permitted<ViewStats>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let users = state.users.all().await?;
+
     let total_users = users.len();
     let average_elo = if total_users > 0 {
         users.iter().map(|u| u.elo as f64).sum::<f64>() / total_users as f64
     } else {
         1200.0
     };
-    
-    // Select only id to avoid serialization issues
-    let mut games_result = get_db()
-        .query("SELECT id FROM game WHERE status = 'completed'")
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let games: Vec<serde_json::Value> = games_result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let total_games = games.len();
-    
+
+    let total_games = state.games.count_completed().await?;
+
     let stats = Some(serde_json::json!({
         "total_users": total_users,
         "total_games": total_games,
@@ -261,4 +199,4 @@ pub async fn get_stats(_admin: AdminUser) -> Result<Json<serde_json::Value>, App
         "total_games": 0,
         "average_elo": 1200
     }))))
-}
\ No newline at end of file
+}
@@ -0,0 +1,70 @@
+use crate::{
+    api::{auth::AppError, notation},
+    auth::AuthUser,
+    game::{GameState, RoomRules},
+    state::AppState,
+};
+use axum::{extract::State, Json};
+use serde::Deserialize;
+use tictac_engine::{analyse, Analysis, Limits, Move, Position};
+
+// Longest run of fours searched for a forced win, and positions visited per
+// request, so one analysis can't hog a core
+const MAX_DEPTH: usize = 6;
+const MAX_NODES: usize = 50_000;
+// Moves replayed per request, a full 19x19 board; every scan of the search
+// grows with the stones
+const MAX_MOVES: usize = 19 * 19;
+
+/// A position given by the moves leading to it, or a stored game up to `ply`
+#[derive(Debug, Deserialize)]
+pub struct AnalysisRequest {
+    #[serde(default)]
+    pub rules: RoomRules,
+    #[serde(default)]
+    pub moves: Vec<Move>,
+    pub game_id: Option<String>,
+    pub ply: Option<usize>,  // Moves of the game to replay, all of them by default
+    pub depth: Option<usize>,
+}
+
+pub async fn analyse_position(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<AnalysisRequest>,
+) -> Result<Json<Analysis>, AppError> {
+    let (rules, mut moves) = match &req.game_id {
+        Some(game_id) => {
+            let game = state.games.find(game_id).await?.ok_or(AppError::NotFound)?;
+            if !notation::readable_by(&game, &claims.user_id) {
+                return Err(AppError::NotFound);
+            }
+            let moves = game.moves.iter().map(|mv| Move::new(mv.x, mv.y)).collect();
+            (game.rules, moves)
+        }
+        None => (req.rules, req.moves),
+    };
+    rules.validate().map_err(AppError::BadRequest)?;
+    moves.truncate(req.ply.unwrap_or(moves.len()));
+    if moves.len() > MAX_MOVES {
+        return Err(AppError::BadRequest(format!("At most {} moves can be analysed", MAX_MOVES)));
+    }
+
+    let mut position = Position::new(GameState::rules(&rules));
+    for (i, mv) in moves.into_iter().enumerate() {
+        position.apply(mv).map_err(|e| {
+            AppError::BadRequest(format!("Move {} at ({}, {}) can't be played: {}", i + 1, mv.x, mv.y, e))
+        })?;
+    }
+
+    let limits = Limits {
+        depth: req.depth.unwrap_or(Limits::default().depth).min(MAX_DEPTH),
+        nodes: MAX_NODES,
+        ..Limits::default()
+    };
+    let analysis = tokio::task::spawn_blocking(move || analyse(&position, limits))
+        .await
+        .map_err(|e| AppError::Database(format!("Analysis failed: {}", e)))?;
+
+    Ok(Json(analysis))
+}
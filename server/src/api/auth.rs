@@ -1,48 +1,29 @@
 use crate::{
     auth::{create_jwt, AuthUser},
-    db::get_db,
-    models::{CreateUserRequest, LoginRequest, LoginResponse, User, UserProfile},
+    guest,
+    jwt_keys,
+    login_guard::{self, ClientIp},
+    models::{CreateUserRequest, LoginRequest, LoginResponse, UserProfile},
+    repo::{user_record, RepoError},
+    state::AppState,
 };
 use axum::{
-    extract::Json,
-    http::StatusCode,
+    extract::{Json, State},
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
 use bcrypt::{hash, verify, DEFAULT_COST};
 use serde_json::json;
 
 pub async fn register(
+    State(state): State<AppState>,
     Json(req): Json<CreateUserRequest>,
 ) -> Result<Json<LoginResponse>, AppError> {
     let password_hash = hash(&req.password, DEFAULT_COST)?;
 
-    // Use raw SQL to handle datetime properly
-    let mut result = get_db()
-        .query(r#"
-            CREATE user CONTENT {
-                email: $email,
-                username: $username,
-                password_hash: $password_hash,
-                profile_picture: NONE,
-                elo: 1200,
-                is_admin: false,
-                created_at: time::now(),
-                updated_at: time::now()
-            };
-        "#)
-        .bind(("email", req.email.clone()))
-        .bind(("username", req.username.clone()))
-        .bind(("password_hash", password_hash))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    let created: Vec<User> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    let created_user = created
-        .into_iter()
-        .next()
+    let created_user = state.users
+        .create(&req.email, &req.username, &password_hash, Vec::new())
+        .await?
         .ok_or_else(|| AppError::Database("Failed to create user".to_string()))?;
 
     let token = create_jwt(&created_user)?;
@@ -53,256 +34,159 @@ pub async fn register(
         username: created_user.username,
         profile_picture: created_user.profile_picture,
         elo: created_user.elo,
+        puzzle_rating: created_user.puzzle_rating,
         games_played: 0,
         games_won: 0,
         win_rate: 0.0,
+        is_guest: false,
+        roles: Vec::new(),
+        is_bot: false,
     };
 
     Ok(Json(LoginResponse { token, user: profile }))
 }
 
-pub async fn login(Json(req): Json<LoginRequest>) -> Result<Json<LoginResponse>, AppError> {
-    // Find user by email for password verification
-    let mut result = get_db()
-        .query("SELECT * FROM user WHERE email = $email")
-        .bind(("email", req.email.clone()))
+pub async fn guest_login(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+) -> Result<Json<LoginResponse>, AppError> {
+    if let Some(ip) = ip {
+        state.guests.claim(ip).map_err(AppError::TooManyRequests)?;
+    }
+    let guest = guest::create_guest(&state.users)
         .await
         .map_err(|e| AppError::Database(e.to_string()))?;
 
-    let users: Vec<User> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let user = users.into_iter().next();
-    
-    let user = user.ok_or(AppError::InvalidCredentials)?;
+    let token = create_jwt(&guest)?;
 
-    if !verify(&req.password, &user.password_hash)? {
-        return Err(AppError::InvalidCredentials);
+    let profile = UserProfile {
+        id: guest.id.as_ref().unwrap().to_string(),
+        email: guest.email,
+        username: guest.username,
+        profile_picture: guest.profile_picture,
+        elo: guest.elo,
+        puzzle_rating: guest.puzzle_rating,
+        games_played: 0,
+        games_won: 0,
+        win_rate: 0.0,
+        is_guest: true,
+        roles: Vec::new(),
+        is_bot: false,
+    };
+
+    Ok(Json(LoginResponse { token, user: profile }))
+}
+
+/// Turn the calling guest into a registered account, keeping its record id
+/// so every game it played stays in its match history
+pub async fn upgrade_guest(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<CreateUserRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    if !claims.is_guest {
+        return Err(AppError::BadRequest("Account is already registered".to_string()));
     }
 
+    let password_hash = hash(&req.password, DEFAULT_COST)?;
+    let user_id = user_record(&claims.user_id);
+
+    let user = state.users
+        .upgrade_guest(&user_id, &req.email, &req.username, &password_hash)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Guest account not found".to_string()))?;
+
     let token = create_jwt(&user)?;
-    let user_id = user.id.as_ref().unwrap().to_string();
-    let user_id_clean = user_id.split(':').last().unwrap_or(&user_id).to_string();
-    
-    // Get full profile with game statistics using SurrealQL - all logic inside SurrealQL
-    let user_record_id = format!("user:{}", user_id_clean);
-    
-    // First, get the basic profile
-    let mut result = get_db()
-        .query(r#"
-            SELECT 
-                string::concat('user:', id.id) as id_str,
-                email,
-                username,
-                profile_picture,
-                elo
-            FROM user
-            WHERE id = type::thing($uid)
-            LIMIT 1;
-        "#)
-        .bind(("uid", user_record_id.clone()))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let basic_profiles: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    if basic_profiles.is_empty() {
-        return Err(AppError::Database("User not found".to_string()));
-    }
-    
-    let basic_profile = &basic_profiles[0];
-    
-    // Get game statistics separately
-    let mut result = get_db()
-        .query(r#"
-            SELECT 
-                count() as total_games
-            FROM game 
-            WHERE status = 'completed' 
-            AND type::thing($uid) IN [player1, player2];
-        "#)
-        .bind(("uid", user_record_id.clone()))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let game_counts: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let total_games = game_counts.get(0)
-        .and_then(|v| v.get("total_games"))
-        .and_then(|v| v.as_i64())
-        .unwrap_or(0) as i32;
-    
-    // Get won games
-    let mut result = get_db()
-        .query(r#"
-            SELECT 
-                count() as won_games
-            FROM game 
-            WHERE status = 'completed' 
-            AND winner = type::thing($uid);
-        "#)
-        .bind(("uid", user_record_id))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let won_counts: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let won_games = won_counts.get(0)
-        .and_then(|v| v.get("won_games"))
-        .and_then(|v| v.as_i64())
-        .unwrap_or(0) as i32;
-    
-    let win_rate = if total_games > 0 {
-        (won_games as f64 * 100.0 / total_games as f64).round()
-    } else {
-        0.0
-    };
-    
-    // Construct the profile
-    let profile = UserProfile {
-        id: user_id.clone(),
-        email: basic_profile.get("email")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        username: basic_profile.get("username")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        profile_picture: basic_profile.get("profile_picture")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string()),
-        elo: basic_profile.get("elo")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(1200) as i32,
-        games_played: total_games,
-        games_won: won_games,
-        win_rate,
-    };
-    
-    
+    let profile = state.users.profile(&user_id).await?.ok_or(AppError::NotFound)?;
+
     Ok(Json(LoginResponse { token, user: profile }))
 }
 
-pub async fn me(AuthUser(claims): AuthUser) -> Result<Json<UserProfile>, AppError> {
-    let user_id = claims.user_id.split(':').last().unwrap_or(&claims.user_id).to_string();
-
-    // Get full profile with stats using SurrealQL - all logic in SurrealQL
-    let user_record_id = format!("user:{}", user_id);
-    
-    // First, get the basic profile
-    let mut result = get_db()
-        .query(r#"
-            SELECT 
-                string::concat('user:', id.id) as id_str,
-                email,
-                username,
-                profile_picture,
-                elo
-            FROM user
-            WHERE id = type::thing($uid)
-            LIMIT 1;
-        "#)
-        .bind(("uid", user_record_id.clone()))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let basic_profiles: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    if basic_profiles.is_empty() {
-        return Err(AppError::Database("User not found".to_string()));
+pub async fn login(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(req): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    if let Some(retry_after) = ip.and_then(login_guard::ip_retry_after) {
+        return Err(AppError::TooManyRequests(retry_after));
     }
-    
-    let basic_profile = &basic_profiles[0];
-    
-    // Get game statistics separately
-    let mut result = get_db()
-        .query(r#"
-            SELECT 
-                count() as total_games
-            FROM game 
-            WHERE status = 'completed' 
-            AND type::thing($uid) IN [player1, player2];
-        "#)
-        .bind(("uid", user_record_id.clone()))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let game_counts: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let total_games = game_counts.get(0)
-        .and_then(|v| v.get("total_games"))
-        .and_then(|v| v.as_i64())
-        .unwrap_or(0) as i32;
-    
-    // Get won games
-    let mut result = get_db()
-        .query(r#"
-            SELECT 
-                count() as won_games
-            FROM game 
-            WHERE status = 'completed' 
-            AND winner = type::thing($uid);
-        "#)
-        .bind(("uid", user_record_id))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let won_counts: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let won_games = won_counts.get(0)
-        .and_then(|v| v.get("won_games"))
-        .and_then(|v| v.as_i64())
-        .unwrap_or(0) as i32;
-    
-    let win_rate = if total_games > 0 {
-        (won_games as f64 * 100.0 / total_games as f64).round()
-    } else {
-        0.0
-    };
-    
-    // Construct the profile
-    let profile = UserProfile {
-        id: claims.user_id.clone(),
-        email: basic_profile.get("email")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        username: basic_profile.get("username")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        profile_picture: basic_profile.get("profile_picture")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string()),
-        elo: basic_profile.get("elo")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(1200) as i32,
-        games_played: total_games,
-        games_won: won_games,
-        win_rate,
+
+    // Guests have no password and can only come back through their token
+    let user = state.users
+        .find_by_email(&req.email)
+        .await?
+        .filter(|user| !user.is_guest);
+
+    let Some(user) = user else {
+        // Spend the same bcrypt time as a real check so unknown emails can't be detected
+        login_guard::verify_dummy(&req.password);
+        if let Some(ip) = ip {
+            login_guard::record_ip_failure(ip);
+        }
+        return Err(AppError::InvalidCredentials);
     };
 
+    let user_id = user.id.clone().unwrap();
+
+    // A locked account answers like a wrong password, after the same bcrypt
+    // time, so the lock doesn't tell anyone the account exists
+    if user.locked_until.is_some_and(|t| t > chrono::Utc::now()) {
+        login_guard::verify_dummy(&req.password);
+        if let Some(ip) = ip {
+            login_guard::record_ip_failure(ip);
+        }
+        return Err(AppError::InvalidCredentials);
+    }
+
+    if !verify(&req.password, &user.password_hash)? {
+        if let Some(ip) = ip {
+            login_guard::record_ip_failure(ip);
+        }
+        if let Err(e) = login_guard::record_account_failure(&state.users, &user_id).await {
+            eprintln!("Failed to record login failure: {}", e);
+        }
+        return Err(AppError::InvalidCredentials);
+    }
+
+    if user.failed_logins > 0 || user.locked_until.is_some() {
+        state.users.reset_lockout(&user_id).await?;
+    }
+
+    let token = create_jwt(&user)?;
+
+    // Get full profile with game statistics using SurrealQL
+    let profile = state.users
+        .profile(&user_id)
+        .await?
+        .ok_or_else(|| AppError::Database("User not found".to_string()))?;
+
+    Ok(Json(LoginResponse { token, user: profile }))
+}
+
+pub async fn jwks() -> Json<jsonwebtoken::jwk::JwkSet> {
+    Json(jwt_keys::jwks())
+}
+
+pub async fn me(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<UserProfile>, AppError> {
+    // Get full profile with stats using SurrealQL
+    let profile = state.users
+        .profile(&user_record(&claims.user_id))
+        .await?
+        .ok_or_else(|| AppError::Database("User not found".to_string()))?;
+
     Ok(Json(profile))
 }
 
 #[derive(Debug)]
 pub enum AppError {
     Database(String),
+    BadRequest(String),
     InvalidCredentials,
+    Forbidden,
+    TooManyRequests(u64),
     NotFound,
     #[allow(dead_code)]
     Bcrypt(bcrypt::BcryptError),
@@ -316,6 +200,12 @@ impl From<bcrypt::BcryptError> for AppError {
     }
 }
 
+impl From<RepoError> for AppError {
+    fn from(err: RepoError) -> Self {
+        AppError::Database(err.to_string())
+    }
+}
+
 impl From<jsonwebtoken::errors::Error> for AppError {
     fn from(err: jsonwebtoken::errors::Error) -> Self {
         AppError::Jwt(err)
@@ -326,7 +216,21 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
             AppError::Database(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
             AppError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()),
+            AppError::TooManyRequests(retry_after) => {
+                let body = Json(json!({
+                    "error": "Too many attempts, try again later",
+                    "retry_after": retry_after,
+                }));
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    body,
+                )
+                    .into_response();
+            }
+            AppError::Forbidden => (StatusCode::FORBIDDEN, "Insufficient permissions".to_string()),
             AppError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
             AppError::Bcrypt(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Password error".to_string()),
             AppError::Jwt(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Token error".to_string()),
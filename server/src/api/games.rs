@@ -1,17 +1,17 @@
 use crate::{
     auth::AuthUser,
-    db::get_db,
-    models::{GameRecord, User},
+    game::GameResult,
+    repo::{record_key, user_record, RepoError},
+    state::AppState,
 };
 use axum::{
-    extract::{Path, Query},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use surrealdb::RecordId;
 
 #[derive(Debug, Deserialize)]
 pub struct MatchHistoryQuery {
@@ -25,7 +25,13 @@ pub struct MatchHistoryItem {
     pub opponent_id: String,
     pub opponent_name: String,
     pub opponent_elo: i32,
+    pub opponent_is_bot: bool,
     pub result: String, // "win", "loss", "draw"
+    pub ended_by: Option<GameResult>,
+    pub reason: Option<String>,
+    pub rated: bool,
+    pub players: usize,
+    pub rank: usize,
     pub my_elo_before: i32,
     pub my_elo_after: i32,
     pub opponent_elo_before: i32,
@@ -43,181 +49,85 @@ pub struct MatchHistoryResponse {
     pub limit: u32,
 }
 
+fn internal_error(context: &str, e: RepoError) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"error": format!("{}: {}", context, e)})),
+    )
+}
+
 pub async fn get_match_history(
+    State(state): State<AppState>,
     auth: AuthUser,
     Query(query): Query<MatchHistoryQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let db = get_db();
-
     let page = query.page.unwrap_or(1).max(1);
     let limit = query.limit.unwrap_or(20).min(100);
     let offset = ((page - 1) * limit) as usize;
 
     // Get the user's record ID
     let user_id = auth.0.user_id;
-    let user_thing = RecordId::from(("user", user_id.as_str()));
+    let user_thing = user_record(&user_id);
 
-    // Query games where the user is either player1 or player2
-    let sql = r#"
-        SELECT * FROM game 
-        WHERE (player1 = $user OR player2 = $user) 
-        AND status = 'completed'
-        ORDER BY ended_at DESC
-        LIMIT $limit
-        START $offset
-    "#;
-
-    let mut result = db
-        .query(sql)
-        .bind(("user", user_thing.clone()))
-        .bind(("limit", limit as i64))
-        .bind(("offset", offset as i64))
+    // Query games the user played in
+    let games = state.games
+        .history(&user_thing, limit as usize, offset)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to fetch games: {}", e)})),
-            )
-        })?;
-
-    // Parse as JSON values first to handle datetime issues
-    let games_json: Vec<serde_json::Value> = result.take(0).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to parse games: {}", e)})),
-        )
-    })?;
+        .map_err(|e| internal_error("Failed to fetch games", e))?;
 
     // Count total games for pagination
-    let count_sql = r#"
-        SELECT count() AS total FROM game 
-        WHERE (player1 = $user OR player2 = $user) 
-        AND status = 'completed'
-    "#;
-
-    let mut count_result = db
-        .query(count_sql)
-        .bind(("user", user_thing.clone()))
+    let total = state.games
+        .history_count(&user_thing)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to count games: {}", e)})),
-            )
-        })?;
-
-    let count_data: Option<serde_json::Value> = count_result.take(0).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to parse count: {}", e)})),
-        )
-    })?;
-
-    let total = count_data
-        .and_then(|v| v.get("total").cloned())
-        .and_then(|v| v.as_u64())
-        .unwrap_or(0) as usize;
+        .map_err(|e| internal_error("Failed to count games", e))? as usize;
 
     // Fetch opponent details for all games
     let mut match_history = Vec::new();
-    
-    for game_json in games_json {
-        // Extract game data from JSON
-        let game_id = game_json.get("id")
-            .and_then(|id| {
-                if let Some(id_str) = id.as_str() {
-                    Some(id_str.to_string())
-                } else if let Some(id_obj) = id.as_object() {
-                    id_obj.get("id").and_then(|i| i.as_str()).map(|s| s.to_string())
-                } else {
-                    None
-                }
-            })
-            .unwrap_or_default();
 
-        // Extract player IDs
-        let player1_id = game_json.get("player1").and_then(|p| p.as_str()).unwrap_or("");
-        let player2_id = game_json.get("player2").and_then(|p| p.as_str()).unwrap_or("");
-        
-        let is_player1 = player1_id.contains(&user_id) || player1_id == format!("user:{}", user_id);
-        let opponent_id_str = if is_player1 { player2_id } else { player1_id };
-        
-        // Convert string ID to RecordId for fetching
-        let opponent_id = if opponent_id_str.starts_with("user:") {
-            RecordId::from(("user", &opponent_id_str[5..]))
-        } else {
-            RecordId::from(("user", opponent_id_str))
+    for game in games {
+        let Some(me) = game.placements.iter().find(|p| p.player == user_thing) else {
+            continue;
+        };
+        // In a free-for-all the opponent shown is whoever of the other teams placed best
+        let Some(opponent) = game.placements.iter().filter(|p| p.team != me.team).min_by_key(|p| p.rank) else {
+            continue;
         };
-        
-        // Fetch opponent user data
-        let opponent: Option<User> = db
-            .select(&opponent_id)
-                .await
-                .map_err(|e| {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(json!({"error": format!("Failed to fetch opponent: {}", e)})),
-                    )
-                })?;
-
-            if let Some(opponent_user) = opponent {
-                // Extract ELO values
-                let player1_elo_before = game_json.get("player1_elo_before")
-                    .and_then(|e| e.as_i64())
-                    .unwrap_or(1200) as i32;
-                let player2_elo_before = game_json.get("player2_elo_before")
-                    .and_then(|e| e.as_i64())
-                    .unwrap_or(1200) as i32;
-                let player1_elo_after = game_json.get("player1_elo_after")
-                    .and_then(|e| e.as_i64())
-                    .map(|e| e as i32)
-                    .unwrap_or(player1_elo_before);
-                let player2_elo_after = game_json.get("player2_elo_after")
-                    .and_then(|e| e.as_i64())
-                    .map(|e| e as i32)
-                    .unwrap_or(player2_elo_before);
-
-                let (my_elo_before, my_elo_after, opponent_elo_before, opponent_elo_after) = 
-                    if is_player1 {
-                        (player1_elo_before, player1_elo_after, player2_elo_before, player2_elo_after)
-                    } else {
-                        (player2_elo_before, player2_elo_after, player1_elo_before, player1_elo_after)
-                    };
-
-                // Determine result
-                let winner_id = game_json.get("winner").and_then(|w| w.as_str()).unwrap_or("");
-                let result = if winner_id.is_empty() {
-                    "draw"
-                } else if winner_id.contains(&user_id) {
-                    "win"
-                } else {
-                    "loss"
-                };
-
-                // Extract datetime strings
-                let created_at = game_json.get("started_at")
-                    .and_then(|dt| dt.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let ended_at = game_json.get("ended_at")
-                    .and_then(|dt| dt.as_str())
-                    .map(|s| s.to_string());
 
-                match_history.push(MatchHistoryItem {
-                    id: game_id,
-                    opponent_id: opponent_id_str.to_string(),
-                    opponent_name: opponent_user.username.clone(),
-                    opponent_elo: opponent_user.elo,
-                    result: result.to_string(),
-                    my_elo_before,
-                    my_elo_after,
-                    opponent_elo_before,
-                    opponent_elo_after,
-                    elo_change: my_elo_after - my_elo_before,
-                    created_at,
-                    ended_at,
-                });
-            }
+        // Fetch opponent user data
+        let opponent_user = state.users
+            .find(&opponent.player)
+            .await
+            .map_err(|e| internal_error("Failed to fetch opponent", e))?;
+
+        if let Some(opponent_user) = opponent_user {
+            // Determine result; everyone sharing first place is a draw
+            let result = match me.rank {
+                1 if game.placements.iter().any(|p| p.rank > 1) => "win",
+                1 => "draw",
+                _ => "loss",
+            };
+
+            match_history.push(MatchHistoryItem {
+                id: game.id.as_ref().map(record_key).unwrap_or_default(),
+                opponent_id: opponent.player.to_string(),
+                opponent_name: opponent_user.username.clone(),
+                opponent_elo: opponent_user.elo,
+                opponent_is_bot: opponent_user.is_bot,
+                result: result.to_string(),
+                ended_by: game.result,
+                reason: game.reason.clone(),
+                rated: game.rated,
+                players: game.placements.len(),
+                rank: me.rank,
+                my_elo_before: me.elo_before,
+                my_elo_after: me.elo_after,
+                opponent_elo_before: opponent.elo_before,
+                opponent_elo_after: opponent.elo_after,
+                elo_change: me.elo_after - me.elo_before,
+                created_at: game.started_at.to_rfc3339(),
+                ended_at: game.ended_at.map(|dt| dt.to_rfc3339()),
+            });
+        }
     }
 
     let response = MatchHistoryResponse {
@@ -231,20 +141,14 @@ pub async fn get_match_history(
 }
 
 pub async fn get_game_details(
+    State(state): State<AppState>,
     _auth: AuthUser,
     Path(game_id): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let db = get_db();
-
-    let game: Option<GameRecord> = db
-        .select(("game", game_id.as_str()))
+    let game = state.games
+        .find(&game_id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to fetch game: {}", e)})),
-            )
-        })?;
+        .map_err(|e| internal_error("Failed to fetch game", e))?;
 
     let game = game.ok_or_else(|| {
         (
@@ -253,30 +157,57 @@ pub async fn get_game_details(
         )
     })?;
 
-    // Fetch player details - game.player1 and player2 are now RecordIds
-    let player1_id = game.player1.clone();
-    let player2_id = game.player2.clone();
-    
-    let player1: Option<User> = db.select(player1_id).await.ok().flatten();
-    let player2: Option<User> = db.select(player2_id).await.ok().flatten();
+    // Fetch player details - game.player1 and player2 are RecordIds
+    let player1 = state.users.find(&game.player1).await.ok().flatten();
+    let player2 = state.users.find(&game.player2).await.ok().flatten();
+
+    // Every player in turn order, with their placement once the game is over
+    let mut players = Vec::new();
+    for (seat, id) in game.players.iter().enumerate() {
+        let user = state.users.find(id).await.ok().flatten();
+        let placement = game.placements.iter().find(|p| p.player == *id);
+        players.push(json!({
+            "id": id.to_string(),
+            "username": user.as_ref().map(|u| u.username.clone()),
+            "is_bot": user.as_ref().is_some_and(|u| u.is_bot),
+            "rank": placement.map(|p| p.rank),
+            "team": placement.map(|p| p.team),
+            "elo_before": placement.map(|p| p.elo_before),
+            "elo_after": placement.map(|p| p.elo_after),
+            // Team mates play one colour and share its accuracy
+            "accuracy": (!game.accuracy.is_empty()).then(|| game.accuracy[seat % game.accuracy.len()]),
+        }));
+    }
 
     let response = json!({
         "id": game.id.as_ref().map(|id| id.to_string()).unwrap_or_default(),
         "status": game.status,
-        "board": game.board,
+        "rated": game.rated,
+        "stones": game.stones,
+        "winners": game.winners.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
         "player1": player1.map(|p| json!({
             "id": p.id.as_ref().unwrap().to_string(),
             "username": p.username,
+            "is_bot": p.is_bot,
             "elo_before": game.player1_elo_before,
             "elo_after": game.player1_elo_after,
         })),
         "player2": player2.map(|p| json!({
             "id": p.id.as_ref().unwrap().to_string(),
             "username": p.username,
+            "is_bot": p.is_bot,
             "elo_before": game.player2_elo_before,
             "elo_after": game.player2_elo_after,
         })),
+        "players": players,
         "winner": game.winner,
+        "result": game.result,
+        "reason": game.reason,
+        "winning_line": game.winning_line,
+        "takebacks": game.takebacks,
+        "moves": game.moves,
+        "annotations": game.annotations,
+        "tags": game.tags,
         "started_at": game.started_at.to_rfc3339(),
         "ended_at": game.ended_at.map(|dt| dt.to_rfc3339()),
     });
@@ -0,0 +1,96 @@
+use crate::{
+    api::auth::AppError,
+    api_keys::{generate_key, hash_key, ApiKey},
+    auth::AuthUser,
+    models::{ApiKeyInfo, Claims, CreateApiKeyRequest, CreateApiKeyResponse},
+    repo::user_record,
+    state::AppState,
+};
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+impl From<ApiKey> for ApiKeyInfo {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id.map(|id| id.key().to_string()).unwrap_or_default(),
+            name: key.name,
+            prefix: key.prefix,
+            scopes: key.scopes,
+            created_at: key.created_at,
+            last_used_at: key.last_used_at,
+            revoked_at: key.revoked_at,
+        }
+    }
+}
+
+// Keys can only be managed from a real login, never by another key
+fn require_session(claims: &Claims) -> Result<(), AppError> {
+    if claims.scopes.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    if claims.is_guest {
+        return Err(AppError::BadRequest("Guests cannot create API keys".to_string()));
+    }
+    Ok(())
+}
+
+pub async fn create_key(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreateApiKeyResponse>, AppError> {
+    require_session(&claims)?;
+
+    if req.name.trim().is_empty() {
+        return Err(AppError::BadRequest("Key name is required".to_string()));
+    }
+    if req.scopes.is_empty() {
+        return Err(AppError::BadRequest("At least one scope is required".to_string()));
+    }
+
+    let (key, prefix) = generate_key();
+
+    let created = state.api_keys
+        .create_key(
+            &user_record(&claims.user_id),
+            req.name.trim(),
+            &prefix,
+            &hash_key(&key),
+            req.scopes,
+        )
+        .await?
+        .ok_or_else(|| AppError::Database("Failed to create API key".to_string()))?;
+
+    Ok(Json(CreateApiKeyResponse {
+        key,
+        info: created.into(),
+    }))
+}
+
+pub async fn list_keys(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Vec<ApiKeyInfo>>, AppError> {
+    require_session(&claims)?;
+
+    let keys = state.api_keys.list_keys(&user_record(&claims.user_id)).await?;
+
+    Ok(Json(keys.into_iter().map(ApiKeyInfo::from).collect()))
+}
+
+pub async fn revoke_key(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(key_id): Path<String>,
+) -> Result<Json<ApiKeyInfo>, AppError> {
+    require_session(&claims)?;
+
+    let revoked = state.api_keys
+        .revoke_key(&user_record(&claims.user_id), &key_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(revoked.into()))
+}
@@ -1,10 +1,11 @@
 use crate::{
     api::auth::AppError,
-    db::get_db,
     models::LeaderboardEntry,
+    repo::user_record,
+    state::AppState,
 };
 use axum::{
-    extract::Query,
+    extract::{Path, Query, State},
     Json,
 };
 use serde::{Deserialize, Serialize};
@@ -35,6 +36,7 @@ pub struct LeaderboardResponse {
 }
 
 pub async fn get_leaderboard(
+    State(state): State<AppState>,
     Query(params): Query<LeaderboardQuery>,
 ) -> Result<Json<LeaderboardResponse>, AppError> {
     // Ensure page is at least 1
@@ -43,27 +45,11 @@ pub async fn get_leaderboard(
     let limit = params.limit.clamp(1, 100);
     let offset = (page - 1) * limit;
     
-    // First, get all eligible players (those who have completed games)
-    let mut count_result = get_db()
-        .query(
-            r#"
-            SELECT DISTINCT player FROM (
-                SELECT player1 as player FROM game WHERE status = 'completed'
-                UNION
-                SELECT player2 as player FROM game WHERE status = 'completed'
-            )
-            "#,
-        )
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let count_data: Vec<serde_json::Value> = count_result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let total = count_data.len();
-    
+    // First, get all eligible players (those who have completed rated games)
+    let total = state.users.ranked_count().await? as usize;
+
     // Calculate total pages
+    #[allow(clippy::manual_div_ceil)]
     let total_pages = if total > 0 {
         (total + limit - 1) / limit
     } else {
@@ -82,34 +68,7 @@ pub async fn get_leaderboard(
     }
     
     // Get users with game stats
-    let mut result = get_db()
-        .query(
-            r#"
-            SELECT 
-                u.*,
-                (SELECT count() FROM game WHERE (player1 = u.id OR player2 = u.id) AND status = 'completed') as games_count,
-                (SELECT count() FROM game WHERE winner = u.id AND status = 'completed') as wins_count
-            FROM user u
-            WHERE u.id IN (
-                SELECT DISTINCT player FROM (
-                    SELECT player1 as player FROM game WHERE status = 'completed'
-                    UNION
-                    SELECT player2 as player FROM game WHERE status = 'completed'
-                )
-            )
-            ORDER BY u.elo DESC 
-            LIMIT $limit 
-            START $offset
-            "#,
-        )
-        .bind(("limit", limit))
-        .bind(("offset", offset))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    let data: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
+    let data = state.users.leaderboard(limit, offset).await?;
 
     let entries: Vec<LeaderboardEntry> = data
         .into_iter()
@@ -132,6 +91,7 @@ pub async fn get_leaderboard(
                 games_played,
                 win_rate,
                 profile_picture: value.get("profile_picture").and_then(|v| v.as_str()).map(String::from),
+                is_bot: value.get("is_bot").and_then(|v| v.as_bool()).unwrap_or(false),
             })
         })
         .collect();
@@ -145,9 +105,11 @@ pub async fn get_leaderboard(
     }))
 }
 
-pub async fn get_top_players() -> Result<Json<Vec<LeaderboardEntry>>, AppError> {
+pub async fn get_top_players(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LeaderboardEntry>>, AppError> {
     // For backward compatibility, return just the entries array
-    let response = get_leaderboard(Query(LeaderboardQuery {
+    let response = get_leaderboard(State(state), Query(LeaderboardQuery {
         page: 1,
         limit: 10,
     }))
@@ -158,38 +120,11 @@ pub async fn get_top_players() -> Result<Json<Vec<LeaderboardEntry>>, AppError>
 
 // New endpoint to get a specific player's rank
 pub async fn get_player_rank(
-    axum::extract::Path(user_id): axum::extract::Path<String>
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let mut result = get_db()
-        .query(
-            r#"
-            SELECT rank FROM (
-                SELECT id, 
-                       row_number() OVER (ORDER BY elo DESC) as rank
-                FROM user 
-                WHERE id IN (
-                    SELECT DISTINCT player FROM (
-                        SELECT player1 as player FROM game WHERE status = 'completed'
-                        UNION
-                        SELECT player2 as player FROM game WHERE status = 'completed'
-                    )
-                )
-                ORDER BY elo DESC
-                LIMIT 1000
-            ) WHERE id = type::thing('user', $user_id)
-            "#,
-        )
-        .bind(("user_id", user_id))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    let rank_data: Vec<serde_json::Value> = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    if let Some(data) = rank_data.first() {
-        Ok(Json(data.clone()))
-    } else {
-        Ok(Json(serde_json::json!({ "rank": null, "message": "Player not in top 1000" })))
+    match state.users.rank(&user_record(&user_id)).await? {
+        Some(rank) if rank <= 1000 => Ok(Json(serde_json::json!({ "rank": rank }))),
+        _ => Ok(Json(serde_json::json!({ "rank": null, "message": "Player not in top 1000" }))),
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,12 @@
-pub mod auth;
-pub mod users;
-pub mod leaderboard;
 pub mod admin;
+pub mod analysis;
+pub mod auth;
+pub mod debug;
 pub mod games;
-pub mod debug;
\ No newline at end of file
+pub mod keys;
+pub mod leaderboard;
+pub mod notation;
+pub mod openings;
+pub mod puzzles;
+pub mod tournaments;
+pub mod users;
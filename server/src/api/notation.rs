@@ -0,0 +1,314 @@
+use crate::{
+    api::auth::AppError,
+    auth::AuthUser,
+    game::{stones_on, GameResult, GameState, RoomRules},
+    models::{GameRecord, ReplayMove, Tag},
+    repo::{games::ImportedGame, user_record},
+    state::AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tictac_engine::{
+    read_psq, read_renlib, write_psq, write_renlib, Move, NotationError, Outcome, Position, Rules, Transcript, Variant,
+};
+
+/// Board Piskvork opens by default, used for games that have no edges
+const PISKVORK_SIZE: usize = 20;
+const RENLIB_SIZE: usize = 15;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// Tags and moves, like PGN
+    #[default]
+    Notation,
+    /// Piskvork's `.psq` files
+    Psq,
+    /// RenLib's move strings, `h8i9j10`
+    Renlib,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Notation => "txt",
+            Format::Psq => "psq",
+            Format::Renlib => "renlib.txt",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: Format,
+}
+
+/// `10x10` or `infinite`, the `Board` tag
+fn board_name(rules: &Rules) -> String {
+    match rules.infinite {
+        true => "infinite".to_string(),
+        false => format!("{}x{}", rules.width, rules.height),
+    }
+}
+
+fn parse_board(value: &str) -> Option<(usize, usize)> {
+    let (width, height) = value.trim().split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// The board the moves of a game are written on. Imported games keep the
+/// board they were read from, though they are played out on one without
+/// edges when it isn't one of ours.
+fn notation_board(game: &GameRecord) -> Rules {
+    let rules = GameState::rules(&game.rules);
+    let tagged = game.tags.iter().find(|tag| tag.name == "Board").and_then(|tag| parse_board(&tag.value));
+    match tagged {
+        Some((width, height)) => Rules { width, height, infinite: false, ..rules },
+        None => rules,
+    }
+}
+
+/// `1-0` when the first colour won, `0-1` the second, `1/2-1/2` for a draw
+/// and `*` for anything unfinished or with more than two colours
+fn result_tag(game: &GameRecord, colours: usize) -> String {
+    if game.status != "completed" || game.result == Some(GameResult::Abort) || colours != 2 {
+        return "*".to_string();
+    }
+    let seat = game.winners.first().and_then(|winner| game.players.iter().position(|id| id == winner));
+    match seat {
+        Some(seat) if seat % colours == 0 => "1-0".to_string(),
+        Some(_) => "0-1".to_string(),
+        None => "1/2-1/2".to_string(),
+    }
+}
+
+async fn game_tags(state: &AppState, game: &GameRecord) -> Vec<(String, String)> {
+    if !game.tags.is_empty() {
+        return game.tags.iter().map(|tag| (tag.name.clone(), tag.value.clone())).collect();
+    }
+    let rules = GameState::rules(&game.rules);
+    let event = if game.rated { "Rated game" } else { "Casual game" };
+    let mut tags = vec![
+        ("Event".to_string(), event.to_string()),
+        ("Date".to_string(), game.started_at.format("%Y.%m.%d").to_string()),
+    ];
+
+    // Black and White in a two-player game, Player1, Player2, ... otherwise
+    for (seat, id) in game.players.iter().enumerate() {
+        let user = state.users.find(id).await.ok().flatten();
+        let name = match (game.players.len(), seat) {
+            (2, 0) => "Black".to_string(),
+            (2, _) => "White".to_string(),
+            _ => format!("Player{}", seat + 1),
+        };
+        let elo = game.placements.iter().find(|p| p.player == *id).map(|p| p.elo_before);
+        tags.push((name.clone(), user.as_ref().map_or_else(|| "?".to_string(), |u| u.username.clone())));
+        if let Some(elo) = elo {
+            tags.push((format!("{}Elo", name), elo.to_string()));
+        }
+    }
+
+    tags.push(("Result".to_string(), result_tag(game, rules.players)));
+    if let Some(reason) = &game.reason {
+        tags.push(("Termination".to_string(), reason.clone()));
+    }
+    tags.push(("Variant".to_string(), rules.variant.name().to_string()));
+    tags.push(("Board".to_string(), board_name(&rules)));
+    if game.rules.players != 2 {
+        tags.push(("Players".to_string(), game.rules.players.to_string()));
+    }
+    if game.rules.teams {
+        tags.push(("Teams".to_string(), "true".to_string()));
+    }
+    tags
+}
+
+/// The moves shifted onto a `width` by `height` board, if they fit on it.
+/// Moves of a smaller board keep their cells.
+fn fit(rules: &Rules, moves: &[Move], width: usize, height: usize) -> Option<Vec<Move>> {
+    if !rules.infinite {
+        return (rules.width <= width && rules.height <= height).then(|| moves.to_vec());
+    }
+    let min_x = moves.iter().map(|mv| mv.x).min().unwrap_or(0);
+    let min_y = moves.iter().map(|mv| mv.y).min().unwrap_or(0);
+    let shifted: Vec<Move> = moves.iter().map(|mv| Move::new(mv.x - min_x, mv.y - min_y)).collect();
+    shifted.iter().all(|mv| (mv.x as usize) < width && (mv.y as usize) < height).then_some(shifted)
+}
+
+/// Whether the user may read the game move by move: anyone a played game,
+/// only the importer an imported one
+pub fn readable_by(game: &GameRecord, user_id: &str) -> bool {
+    game.imported_by.as_ref().is_none_or(|importer| *importer == user_record(user_id))
+}
+
+/// A game as text, in the notation asked for. Played games are anyone's to
+/// export; imported ones only their importer's.
+pub async fn export_game(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(game_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let game = state.games.find(&game_id).await?.ok_or(AppError::NotFound)?;
+    if !readable_by(&game, &claims.user_id) {
+        return Err(AppError::NotFound);
+    }
+    let board = notation_board(&game);
+    let moves: Vec<Move> = game.moves.iter().map(|mv| Move::new(mv.x, mv.y)).collect();
+    let unsupported = |e: NotationError| AppError::BadRequest(format!("Game can't be exported: {}", e));
+
+    let text = match query.format {
+        Format::Notation => {
+            let tags = game_tags(&state, &game).await;
+            Transcript { tags, moves }.write(&board)
+        }
+        Format::Psq => match board.infinite {
+            false => write_psq(&board, &moves).map_err(unsupported)?,
+            true => {
+                let moves = fit(&board, &moves, PISKVORK_SIZE, PISKVORK_SIZE).ok_or_else(|| {
+                    AppError::BadRequest(format!("Game doesn't fit on a {0}x{0} Piskvork board", PISKVORK_SIZE))
+                })?;
+                write_psq(&Rules::new(PISKVORK_SIZE, PISKVORK_SIZE, board.win_length, 2), &moves).map_err(unsupported)?
+            }
+        },
+        Format::Renlib => {
+            let renlib = Rules::new(RENLIB_SIZE, RENLIB_SIZE, board.win_length, 2);
+            let moves = fit(&board, &moves, RENLIB_SIZE, RENLIB_SIZE).ok_or_else(|| {
+                AppError::BadRequest(format!("Game doesn't fit on a {0}x{0} RenLib board", RENLIB_SIZE))
+            })?;
+            write_renlib(&renlib, &moves).map_err(unsupported)? + "\n"
+        }
+    };
+
+    let disposition = format!("attachment; filename=\"game-{}.{}\"", game_id, query.format.extension());
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()), (header::CONTENT_DISPOSITION, disposition)], text))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportRequest {
+    pub text: String,
+    #[serde(default)]
+    pub format: Format,
+    /// Variant of a Piskvork or RenLib game, which the files don't record;
+    /// the notation has a `Variant` tag instead
+    pub variant: Option<Variant>,
+}
+
+/// Room rules and the board the moves are written on, from the tags of a
+/// game in notation
+fn tagged_rules(tags: &[(String, String)]) -> Result<(RoomRules, Rules), NotationError> {
+    let tag = |name: &str| tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.trim());
+    let variant = match tag("Variant") {
+        Some(name) => Variant::from_name(name).ok_or_else(|| NotationError::Tag(format!("Variant \"{}\"", name)))?,
+        None => Variant::default(),
+    };
+    let players = match tag("Players") {
+        Some(players) => players.parse().map_err(|_| NotationError::Tag(format!("Players \"{}\"", players)))?,
+        None => 2,
+    };
+    let teams = tag("Teams") == Some("true");
+    let room = RoomRules { variant, players, teams, ..Default::default() };
+    if !(2..=4).contains(&players) || (teams && players != 4) {
+        return Err(NotationError::Tag(format!("Players \"{}\"", players)));
+    }
+
+    let rules = GameState::rules(&room);
+    let board = match tag("Board") {
+        None => rules,
+        Some("infinite") => Rules { infinite: true, ..rules },
+        Some(board) => {
+            let (width, height) = parse_board(board).ok_or_else(|| NotationError::Board(board.to_string()))?;
+            Rules { width, height, infinite: false, ..rules }
+        }
+    };
+    Ok((room, board))
+}
+
+/// Replay the moves read on `board` under the room rules, played out without
+/// edges when the board isn't the room's own
+fn replay(mut room: RoomRules, board: &Rules, moves: &[Move], tags: Vec<Tag>) -> Result<ImportedGame, AppError> {
+    let own = GameState::rules(&room);
+    room.infinite = board.infinite || (own.width, own.height) != (board.width, board.height);
+
+    let mut position = Position::new(GameState::rules(&room));
+    for (i, &mv) in moves.iter().enumerate() {
+        position.apply(mv).map_err(|e| {
+            AppError::BadRequest(format!("Move {} at ({}, {}) can't be played: {}", i + 1, mv.x, mv.y, e))
+        })?;
+    }
+
+    let rules = *position.rules();
+    let moves = position
+        .history()
+        .iter()
+        .enumerate()
+        .map(|(ply, mv)| ReplayMove { x: mv.x, y: mv.y, player: rules.player_at(ply), captured: position.captured(ply).collect() })
+        .collect();
+    let stones = stones_on(&position);
+    let (result, winning_line) = match position.outcome() {
+        Some(Outcome::Win { line, .. }) => (Some(GameResult::Win), line.cells().collect()),
+        Some(Outcome::Captures { .. }) => (Some(GameResult::Win), Vec::new()),
+        Some(Outcome::Draw) => (Some(GameResult::Draw), Vec::new()),
+        None => (None, Vec::new()),
+    };
+
+    Ok(ImportedGame { rules: room, moves, stones, result, winning_line, tags })
+}
+
+/// Import a game from text, checking every move against the rules. The game
+/// is kept as the importer's, unrated and out of their match history.
+pub async fn import_game(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(req): Json<ImportRequest>,
+) -> Result<Json<Value>, AppError> {
+    let unreadable = |e: NotationError| AppError::BadRequest(format!("Game can't be read: {}", e));
+    let room = RoomRules { variant: req.variant.unwrap_or_default(), ..Default::default() };
+    let rules = GameState::rules(&room);
+
+    let (room, board, mut tags, moves) = match req.format {
+        Format::Notation => {
+            let mut room = room;
+            let (transcript, board) = Transcript::read(&req.text, |tags| {
+                let (tagged, board) = tagged_rules(tags)?;
+                room = tagged;
+                Ok(board)
+            })
+            .map_err(unreadable)?;
+            (room, board, transcript.tags, transcript.moves)
+        }
+        Format::Psq => {
+            let ((width, height), moves) = read_psq(&req.text).map_err(unreadable)?;
+            (room, Rules { width, height, infinite: false, ..rules }, Vec::new(), moves)
+        }
+        Format::Renlib => {
+            let moves = read_renlib(&req.text).map_err(unreadable)?;
+            (room, Rules { width: RENLIB_SIZE, height: RENLIB_SIZE, infinite: false, ..rules }, Vec::new(), moves)
+        }
+    };
+    if moves.is_empty() {
+        return Err(AppError::BadRequest("Game has no moves to import".to_string()));
+    }
+    // The board is kept as a tag so that exports write the moves as read
+    if !tags.iter().any(|(name, _)| name == "Board") {
+        tags.push(("Board".to_string(), board_name(&board)));
+    }
+    if !tags.iter().any(|(name, _)| name == "Variant") {
+        tags.push(("Variant".to_string(), room.variant.name().to_string()));
+    }
+
+    let tags = tags.into_iter().map(|(name, value)| Tag { name, value }).collect();
+    let game = replay(room, &board, &moves, tags)?;
+    let (plies, result, rules) = (game.moves.len(), game.result, game.rules.clone());
+    let game_id = state.games.import(&user_record(&claims.user_id), game).await?;
+
+    Ok(Json(json!({ "id": game_id, "moves": plies, "result": result, "rules": rules })))
+}
+
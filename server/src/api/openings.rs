@@ -0,0 +1,58 @@
+use crate::{
+    api::auth::AppError,
+    game::{GameState, RoomRules},
+    repo::openings::{parse_line, Continuation, OpeningFilter},
+    state::AppState,
+};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tictac_engine::{Position, Variant};
+
+#[derive(Debug, Deserialize)]
+pub struct OpeningQuery {
+    /// The opening so far as `x,y;x,y;...`, the empty board when left out
+    #[serde(default)]
+    moves: String,
+    variant: Option<Variant>,
+    #[serde(default)]
+    infinite: bool,
+    min_rating: Option<i32>,
+    max_rating: Option<i32>,
+}
+
+/// Continuations of an opening in completed two-player games, counting its
+/// mirror images and turns of the board as the same opening
+pub async fn explore_openings(
+    State(state): State<AppState>,
+    Query(query): Query<OpeningQuery>,
+) -> Result<Json<Value>, AppError> {
+    let moves = parse_line(&query.moves)
+        .ok_or_else(|| AppError::BadRequest("Moves must be given as x,y;x,y;...".to_string()))?;
+    let rules = RoomRules { variant: query.variant.unwrap_or_default(), infinite: query.infinite, ..Default::default() };
+    let mut position = Position::new(GameState::rules(&rules));
+    for (i, &mv) in moves.iter().enumerate() {
+        position.apply(mv).map_err(|e| {
+            AppError::BadRequest(format!("Move {} at ({}, {}) can't be played: {}", i + 1, mv.x, mv.y, e))
+        })?;
+    }
+
+    let filter = OpeningFilter {
+        variant: query.variant,
+        infinite: query.infinite,
+        min_rating: query.min_rating,
+        max_rating: query.max_rating,
+    };
+    let continuations: Vec<Continuation> = state.openings.explore(&moves, &filter).await?;
+    let games: u64 = continuations.iter().map(|c| c.games).sum();
+
+    Ok(Json(json!({
+        "moves": moves,
+        "to_move": position.to_move(),
+        "games": games,
+        "continuations": continuations,
+    })))
+}
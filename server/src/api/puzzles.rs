@@ -0,0 +1,105 @@
+use crate::{
+    api::auth::AppError,
+    auth::{AdminUser, AuthUser},
+    game::GameState,
+    models::PuzzleRecord,
+    repo::{puzzle_record, puzzles::MINE_PAGE, user_record},
+    state::AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tictac_engine::{attempt, Attempt, Move, Position};
+
+/// A puzzle as the solver sees it, without the solution
+fn puzzle_json(puzzle: &PuzzleRecord) -> Value {
+    json!({
+        "id": puzzle.id.as_ref().map(|id| id.to_string()),
+        "rules": puzzle.rules,
+        "moves": puzzle.moves,
+        "to_move": puzzle.to_move,
+        "rating": puzzle.rating,
+        // Moves the solver has to find, the winning one included
+        "length": puzzle.solution.len().div_ceil(2),
+        "attempts": puzzle.attempts,
+        "solves": puzzle.solves,
+    })
+}
+
+pub async fn next_puzzle(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<Json<Value>, AppError> {
+    let puzzle = state.puzzles.next_for(&user_record(&claims.user_id)).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(puzzle_json(&puzzle)))
+}
+
+pub async fn get_puzzle(
+    State(state): State<AppState>,
+    _auth: AuthUser,
+    Path(puzzle_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let puzzle = state.puzzles.find(&puzzle_record(&puzzle_id)).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(puzzle_json(&puzzle)))
+}
+
+/// The solver's moves so far, without the opponent's replies
+#[derive(Debug, Deserialize)]
+pub struct AttemptRequest {
+    pub moves: Vec<Move>,
+}
+
+/// Check the solver's moves; the first attempt that solves or fails the
+/// puzzle moves their puzzle rating
+pub async fn attempt_puzzle(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(puzzle_id): Path<String>,
+    Json(req): Json<AttemptRequest>,
+) -> Result<Json<Value>, AppError> {
+    if req.moves.is_empty() {
+        return Err(AppError::BadRequest("An attempt needs at least one move".to_string()));
+    }
+    let puzzle = state.puzzles.find(&puzzle_record(&puzzle_id)).await?.ok_or(AppError::NotFound)?;
+
+    let mut position = Position::new(GameState::rules(&puzzle.rules));
+    for &mv in &puzzle.moves {
+        position.apply(mv).map_err(|e| AppError::Database(format!("Puzzle position is broken: {}", e)))?;
+    }
+    let result = attempt(&position, &puzzle.solution, &req.moves)
+        .map_err(|e| AppError::BadRequest(format!("Move can't be played: {}", e)))?;
+
+    let rating = match result {
+        Attempt::Continue { .. } => None,
+        Attempt::Solved | Attempt::Failed => {
+            let solved = result == Attempt::Solved;
+            state.puzzles.record_attempt(&user_record(&claims.user_id), &puzzle, solved).await?
+        }
+    };
+
+    let mut response = json!(result);
+    response["rating"] = json!(rating.map(|(before, after)| json!({ "before": before, "after": after })));
+    if !matches!(result, Attempt::Continue { .. }) {
+        response["solution"] = json!(puzzle.solution);
+    }
+    Ok(Json(response))
+}
+
+/// Look for puzzles in every completed game that hasn't given one yet, in
+/// the background since every game is searched ply by ply
+pub async fn mine_puzzles(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> (StatusCode, Json<Value>) {
+    tokio::spawn(async move {
+        match state.puzzles.mine_completed(MINE_PAGE).await {
+            Ok((games, mined)) => println!("Mined {} puzzles from {} completed games", mined, games),
+            Err(e) => eprintln!("Mining completed games for puzzles failed: {}", e),
+        }
+    });
+    (StatusCode::ACCEPTED, Json(json!({ "started": true })))
+}
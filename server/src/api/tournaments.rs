@@ -0,0 +1,198 @@
+use crate::{
+    api::auth::AppError,
+    auth::{AuthUser, ManageTournaments, Permitted},
+    game::RoomRules,
+    models::TournamentRecord,
+    pairing::{self, Format, PairingResult},
+    repo::{pairing_record, tournament_record, user_record},
+    state::AppState,
+    tournament,
+};
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+fn tournament_json(tournament: &TournamentRecord) -> Value {
+    json!({
+        "id": tournament.id.as_ref().map(|id| id.to_string()),
+        "name": tournament.name,
+        "format": tournament.format,
+        "rules": tournament.rules,
+        "rounds": tournament.rounds,
+        "round": tournament.round,
+        "status": tournament.status,
+        "player_count": tournament.players.len(),
+        "created_at": tournament.created_at,
+        "started_at": tournament.started_at,
+        "ended_at": tournament.ended_at,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTournamentRequest {
+    pub name: String,
+    pub format: Format,
+    #[serde(default)]
+    pub rules: RoomRules,
+    pub rounds: Option<usize>,  // Swiss only; the other formats work it out
+}
+
+pub async fn create_tournament(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<ManageTournaments>,
+    Json(req): Json<CreateTournamentRequest>,
+) -> Result<Json<Value>, AppError> {
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(AppError::BadRequest(format!("Name must be 1 to {} characters", MAX_NAME_LENGTH)));
+    }
+    req.rules.validate().map_err(AppError::BadRequest)?;
+    if req.rules.players != 2 || req.rules.teams {
+        return Err(AppError::BadRequest("Tournament games are played one against one".to_string()));
+    }
+    if req.rounds == Some(0) {
+        return Err(AppError::BadRequest("A tournament needs at least one round".to_string()));
+    }
+
+    let created = state
+        .tournaments
+        .create(name, req.format, &req.rules, req.rounds, &user_record(&claims.user_id))
+        .await?
+        .ok_or_else(|| AppError::Database("Failed to create tournament".to_string()))?;
+    Ok(Json(tournament_json(&created)))
+}
+
+pub async fn list_tournaments(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let tournaments = state.tournaments.list().await?;
+    Ok(Json(json!({ "tournaments": tournaments.iter().map(tournament_json).collect::<Vec<_>>() })))
+}
+
+/// A tournament with its players, every pairing so far and the standings
+pub async fn get_tournament(
+    State(state): State<AppState>,
+    Path(tournament_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let tournament = state.tournaments.find(&tournament_record(&tournament_id)).await?.ok_or(AppError::NotFound)?;
+    let pairings = match &tournament.id {
+        Some(id) => state.tournaments.pairings(id).await?,
+        None => Vec::new(),
+    };
+
+    let mut players = Vec::new();
+    for (seed, id) in tournament.players.iter().enumerate() {
+        let user = state.users.find(id).await?;
+        players.push(json!({
+            "id": id.to_string(),
+            "username": user.as_ref().map(|u| u.username.clone()),
+            "elo": user.as_ref().map(|u| u.elo),
+            "seed": seed + 1,
+        }));
+    }
+
+    let pairings_json: Vec<Value> = pairings
+        .iter()
+        .map(|p| {
+            json!({
+                "id": p.id.as_ref().map(|id| id.to_string()),
+                "round": p.round,
+                "board": p.board,
+                "black": p.black.to_string(),
+                "white": p.white.as_ref().map(|id| id.to_string()),
+                "result": p.result,
+                "game": p.game.as_ref().map(|id| id.to_string()),
+                // Byes and finished games have no room to play in
+                "room": p.id.as_ref().filter(|_| p.white.is_some() && p.result.is_none()).map(tournament::room_name),
+            })
+        })
+        .collect();
+
+    let history = tournament::history(&tournament, &pairings);
+    let standings: Vec<Value> = pairing::standings(tournament.players.len(), &history)
+        .into_iter()
+        .enumerate()
+        .map(|(rank, standing)| {
+            let mut entry = json!(standing);
+            entry["rank"] = json!(rank + 1);
+            entry["player"] = json!(tournament.players[standing.player].to_string());
+            entry
+        })
+        .collect();
+
+    let mut response = tournament_json(&tournament);
+    response["players"] = json!(players);
+    response["pairings"] = json!(pairings_json);
+    response["standings"] = json!(standings);
+    Ok(Json(response))
+}
+
+pub async fn join_tournament(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(tournament_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    if claims.is_guest {
+        return Err(AppError::Forbidden);
+    }
+    let tournament_id = tournament_record(&tournament_id);
+    let tournament = state.tournaments.find(&tournament_id).await?.ok_or(AppError::NotFound)?;
+    if tournament.status != "registration" {
+        return Err(AppError::BadRequest("Registration is closed".to_string()));
+    }
+    let user_id = user_record(&claims.user_id);
+    if tournament.players.contains(&user_id) {
+        return Err(AppError::BadRequest("Already registered".to_string()));
+    }
+    if !state.tournaments.join(&tournament_id, &user_id).await? {
+        return Err(AppError::BadRequest("Registration is closed".to_string()));
+    }
+
+    Ok(Json(json!({ "joined": true, "player_count": tournament.players.len() + 1 })))
+}
+
+/// Close registration, seed the players and pair the first round
+pub async fn start_tournament(
+    State(state): State<AppState>,
+    _director: Permitted<ManageTournaments>,
+    Path(tournament_id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let tournament = state.tournaments.find(&tournament_record(&tournament_id)).await?.ok_or(AppError::NotFound)?;
+    if tournament.players.len() < 2 {
+        return Err(AppError::BadRequest("A tournament needs at least two players".to_string()));
+    }
+    if !tournament::start(&state, &tournament).await? {
+        return Err(AppError::BadRequest("Tournament has already started".to_string()));
+    }
+
+    let started = state.tournaments.find(&tournament_record(&tournament_id)).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(tournament_json(&started)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultRequest {
+    pub result: PairingResult,
+}
+
+/// Settle a pairing by hand, such as a game that can't be played
+pub async fn adjudicate_pairing(
+    State(state): State<AppState>,
+    _director: Permitted<ManageTournaments>,
+    Path((tournament_id, pairing_id)): Path<(String, String)>,
+    Json(req): Json<ResultRequest>,
+) -> Result<Json<Value>, AppError> {
+    let pairing_id = pairing_record(&pairing_id);
+    let pairing = state.tournaments.find_pairing(&pairing_id).await?.ok_or(AppError::NotFound)?;
+    if pairing.tournament != tournament_record(&tournament_id) {
+        return Err(AppError::NotFound);
+    }
+    if pairing.result.is_some() {
+        return Err(AppError::BadRequest("Pairing already has a result".to_string()));
+    }
+
+    tournament::record_result(&state, &pairing_id, req.result, None).await?;
+    Ok(Json(json!({ "pairing": pairing_id.to_string(), "result": req.result })))
+}
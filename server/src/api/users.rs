@@ -1,138 +1,64 @@
 use crate::{
     api::auth::AppError,
     auth::AuthUser,
-    db::get_db,
-    models::{UpdateProfileRequest, User, UserProfile},
+    models::{UpdateProfileRequest, UserProfile},
+    repo::user_record,
+    state::AppState,
 };
 use axum::{
-    extract::{Multipart, Path}, Json
+    extract::{Multipart, Path, State}, Json
 };
 use base64::{engine::general_purpose::STANDARD, Engine};
 use image::{imageops::FilterType, DynamicImage};
 use std::io::Cursor;
-use surrealdb::{RecordId, Value};
-
-pub async fn get_user_profile(Path(user_id): Path<String>) -> Result<Json<UserProfile>, AppError> {
-    let user_id_clean = user_id.split(':').last().unwrap_or(&user_id).to_string();
 
+pub async fn get_user_profile(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<Json<UserProfile>, AppError> {
     // Get full profile with stats using type-safe query
-    let mut result = get_db()
-        .query(r#"
-            -- Define the user record type
-            LET $uid = type::record('user', $user_id);
-
-            -- Verify user exists
-            IF NOT (SELECT * FROM $uid)[0] THEN
-                THROW "User not found";
-            END;
-
-            -- Get user data
-            LET $user = (SELECT * FROM $uid)[0];
-
-            -- Count games efficiently
-            LET $games = SELECT
-                count() as total,
-                count(winner = $uid) as won
-            FROM game
-            WHERE (player1 = $uid OR player2 = $uid)
-            AND status = 'completed';
-
-            -- Return strongly typed profile
-            RETURN {
-                id: string::concat('user:', $user_id),
-                email: $user.email,
-                username: $user.username,
-                profile_picture: $user.profile_picture,
-                elo: $user.elo,
-                games_played: $games[0].total OR 0,
-                games_won: $games[0].won OR 0,
-                win_rate: IF $games[0].total > 0 THEN
-                    math::round($games[0].won * 100.0 / $games[0].total, 2)
-                ELSE 0.0 END
-            };
-        "#)
-        .bind(("user_id", user_id_clean))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    // Use surrealdb::Value as intermediate type for RETURN statement
-    let value: Value = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    // Convert Value to UserProfile
-    let profile: UserProfile = serde_json::from_value(serde_json::to_value(value).map_err(|e| AppError::Database(e.to_string()))?)
-        .map_err(|e| AppError::Database(format!("Failed to parse profile: {}", e)))?;
+    let profile = state.users
+        .profile(&user_record(&user_id))
+        .await?
+        .ok_or(AppError::NotFound)?;
 
     Ok(Json(profile))
 }
 
 pub async fn update_profile(
+    State(state): State<AppState>,
     AuthUser(claims): AuthUser,
     Json(req): Json<UpdateProfileRequest>,
 ) -> Result<Json<UserProfile>, AppError> {
-    let user_id = RecordId::from(("user", claims.user_id.as_str()));
+    let user_id = user_record(&claims.user_id);
 
     if let Some(username) = &req.username {
-        let _: Option<User> = get_db()
-            .update(user_id.clone())
-            .merge(serde_json::json!({
+        state.users
+            .merge(&user_id, serde_json::json!({
                 "username": username,
-                "updated_at": chrono::Utc::now(),
             }))
-            .await
-            .map_err(|e| AppError::Database(e.to_string()))?;
+            .await?;
     }
 
-    let user_id_clean = claims.user_id.split(':').last().unwrap_or(&claims.user_id).to_string();
+    // Bot operators declare their own accounts so they are labelled as such
+    if let Some(is_bot) = req.is_bot {
+        state.users
+            .merge(&user_id, serde_json::json!({
+                "is_bot": is_bot,
+            }))
+            .await?;
+    }
+
+    let profile = state.users
+        .profile(&user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    // Get full profile with stats using type-safe query
-    let mut result = get_db()
-        .query(r#"
-            -- Define the user record type
-            LET $uid = type::record('user', $user_id);
-
-            -- Get user data
-            LET $user = (SELECT * FROM $uid)[0];
-
-            -- Count games efficiently
-            LET $games = SELECT
-                count() as total,
-                count(winner = $uid) as won
-            FROM game
-            WHERE (player1 = $uid OR player2 = $uid)
-            AND status = 'completed';
-
-            -- Return strongly typed profile
-            RETURN {
-                id: string::concat('user:', $user_id),
-                email: $user.email,
-                username: $user.username,
-                profile_picture: $user.profile_picture,
-                elo: $user.elo,
-                games_played: $games[0].total OR 0,
-                games_won: $games[0].won OR 0,
-                win_rate: IF $games[0].total > 0 THEN
-                    math::round($games[0].won * 100.0 / $games[0].total, 2)
-                ELSE 0.0 END
-            };
-        "#)
-        .bind(("user_id", user_id_clean))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
-
-    // Use surrealdb::Value as intermediate type for RETURN statement
-    let value: Value = result
-        .take(0)
-        .map_err(|e| AppError::Database(e.to_string()))?;
-    
-    // Convert Value to UserProfile
-    let profile: UserProfile = serde_json::from_value(serde_json::to_value(value).map_err(|e| AppError::Database(e.to_string()))?)
-        .map_err(|e| AppError::Database(format!("Failed to parse profile: {}", e)))?;
     Ok(Json(profile))
 }
 
 pub async fn upload_profile_picture(
+    State(state): State<AppState>,
     AuthUser(claims): AuthUser,
     mut multipart: Multipart,
 ) -> Result<Json<serde_json::Value>, AppError> {
@@ -167,16 +93,11 @@ pub async fn upload_profile_picture(
 
     let base64_image = format!("data:image/jpeg;base64,{}", STANDARD.encode(&buf));
 
-    let user_id = RecordId::from(("user", claims.user_id.as_str()));
-
-    let _: Option<User> = get_db()
-        .update(user_id)
-        .merge(serde_json::json!({
+    state.users
+        .merge(&user_record(&claims.user_id), serde_json::json!({
             "profile_picture": base64_image,
-            "updated_at": chrono::Utc::now(),
         }))
-        .await
-        .map_err(|e| AppError::Database(e.to_string()))?;
+        .await?;
 
     Ok(Json(serde_json::json!({
         "success": true,
@@ -0,0 +1,76 @@
+use crate::{models::Claims, state::AppState};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use surrealdb::RecordId;
+
+// Every key starts with this so it can be told apart from a JWT
+pub const API_KEY_PREFIX: &str = "tt_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    Read,   // GET endpoints
+    Write,  // Mutating endpoints
+    Play,   // WebSocket rooms
+    Admin,  // Lets role based permissions apply to the key
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    pub id: Option<RecordId>,
+    pub user: RecordId,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+/// Generate a new plaintext key, returning it with its display prefix
+pub fn generate_key() -> (String, String) {
+    let public = uuid::Uuid::new_v4().simple().to_string();
+    let secret = uuid::Uuid::new_v4().simple().to_string();
+    let prefix = format!("{}{}", API_KEY_PREFIX, &public[..8]);
+    (format!("{}_{}{}", prefix, &public[8..], secret), prefix)
+}
+
+/// Keys carry enough entropy that a fast hash is sufficient at rest
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Look up an unrevoked key, record its use and build the claims it acts with
+pub async fn resolve_key(
+    state: &AppState,
+    key: &str,
+) -> Result<Option<Claims>, Box<dyn std::error::Error>> {
+    let Some(api_key) = state.api_keys.use_key(&hash_key(key)).await? else {
+        return Ok(None);
+    };
+
+    let Some(user) = state.users.find(&api_key.user).await? else {
+        return Ok(None);
+    };
+
+    let user_id = api_key.user.to_string();
+    Ok(Some(Claims {
+        sub: user_id.clone(),
+        user_id,
+        email: user.email,
+        is_admin: user.is_admin,
+        roles: user.roles,
+        is_guest: user.is_guest,
+        exp: usize::MAX,
+        scopes: Some(api_key.scopes),
+    }))
+}
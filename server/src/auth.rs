@@ -1,7 +1,11 @@
+use crate::api_keys::{self, ApiScope};
+use crate::jwt_keys;
 use crate::models::{Claims, User};
+use crate::roles::{self, Permission, Role};
+use crate::state::AppState;
 use axum::{
-    extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    extract::{FromRef, FromRequestParts},
+    http::{request::Parts, Method, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -9,22 +13,8 @@ use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use once_cell::sync::Lazy;
 use serde_json::json;
-use std::env;
-
-pub static JWT_SECRET: Lazy<String> = Lazy::new(|| {
-    env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key-change-in-production".to_string())
-});
-
-pub static DECODING_KEY: Lazy<DecodingKey> = Lazy::new(|| {
-    DecodingKey::from_secret(JWT_SECRET.as_bytes())
-});
-
-pub static ENCODING_KEY: Lazy<EncodingKey> = Lazy::new(|| {
-    EncodingKey::from_secret(JWT_SECRET.as_bytes())
-});
+use std::marker::PhantomData;
 
 pub fn create_jwt(user: &User) -> Result<String, jsonwebtoken::errors::Error> {
     let expiration = chrono::Utc::now()
@@ -37,33 +27,81 @@ pub fn create_jwt(user: &User) -> Result<String, jsonwebtoken::errors::Error> {
         user_id: user.id.as_ref().unwrap().to_string(),
         email: user.email.clone(),
         is_admin: user.is_admin,
+        roles: user.roles.clone(),
+        is_guest: user.is_guest,
         exp: expiration,
+        scopes: None,
     };
 
-    encode(&Header::default(), &claims, &ENCODING_KEY)
+    jwt_keys::sign(&claims)
+}
+
+impl Claims {
+    /// Roles granted by this token; legacy tokens only carry `is_admin`
+    pub fn roles(&self) -> Vec<Role> {
+        let mut roles = self.roles.clone();
+        if self.is_admin && !roles.contains(&Role::Admin) {
+            roles.push(Role::Admin);
+        }
+        roles
+    }
+
+    /// Sessions may do anything, API keys only what they were scoped for
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.as_ref().is_none_or(|scopes| scopes.contains(&scope))
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.has_scope(ApiScope::Admin) && roles::has_permission(&self.roles(), permission)
+    }
+}
+
+/// Authenticate a bearer credential that is either a session JWT or an API key
+pub async fn authenticate(state: &AppState, token: &str) -> Result<Claims, AuthError> {
+    if api_keys::is_api_key(token) {
+        return match api_keys::resolve_key(state, token).await {
+            Ok(Some(claims)) => Ok(claims),
+            Ok(None) => Err(AuthError::InvalidToken),
+            Err(e) => {
+                eprintln!("Failed to resolve API key: {}", e);
+                Err(AuthError::InvalidToken)
+            }
+        };
+    }
+
+    jwt_keys::verify::<Claims>(token).map_err(|_| AuthError::InvalidToken)
 }
 
 pub struct AuthUser(pub Claims);
 
 impl<S> FromRequestParts<S> for AuthUser
 where
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = AuthError;
 
     async fn from_request_parts(
         parts: &mut Parts,
-        _state: &S,
+        state: &S,
     ) -> Result<Self, Self::Rejection> {
         let TypedHeader(Authorization(bearer)) =
-            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, _state)
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
                 .await
                 .map_err(|_| AuthError::MissingToken)?;
 
-        let token_data = decode::<Claims>(bearer.token(), &DECODING_KEY, &Validation::default())
-            .map_err(|_| AuthError::InvalidToken)?;
+        let claims = authenticate(&AppState::from_ref(state), bearer.token()).await?;
+
+        let scope = if parts.method == Method::GET || parts.method == Method::HEAD {
+            ApiScope::Read
+        } else {
+            ApiScope::Write
+        };
+        if !claims.has_scope(scope) {
+            return Err(AuthError::Unauthorized);
+        }
 
-        Ok(AuthUser(token_data.claims))
+        Ok(AuthUser(claims))
     }
 }
 
@@ -72,6 +110,7 @@ pub struct AdminUser(pub Claims);
 
 impl<S> FromRequestParts<S> for AdminUser
 where
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = AuthError;
@@ -82,7 +121,7 @@ where
     ) -> Result<Self, Self::Rejection> {
         let AuthUser(claims) = AuthUser::from_request_parts(parts, state).await?;
         
-        if !claims.is_admin {
+        if !claims.has_scope(ApiScope::Admin) || !claims.roles().contains(&Role::Admin) {
             return Err(AuthError::Unauthorized);
         }
 
@@ -90,6 +129,54 @@ where
     }
 }
 
+/// Marker for a permission that a `Permitted` extractor requires
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+pub struct ManageUsers;
+
+impl RequiredPermission for ManageUsers {
+    const PERMISSION: Permission = Permission::ManageUsers;
+}
+
+pub struct ViewStats;
+
+impl RequiredPermission for ViewStats {
+    const PERMISSION: Permission = Permission::ViewStats;
+}
+
+pub struct ManageTournaments;
+
+impl RequiredPermission for ManageTournaments {
+    const PERMISSION: Permission = Permission::ManageTournaments;
+}
+
+/// Authenticated user whose roles grant the permission `P`
+pub struct Permitted<P: RequiredPermission>(pub Claims, pub PhantomData<P>);
+
+impl<S, P> FromRequestParts<S> for Permitted<P>
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+    P: RequiredPermission,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser(claims) = AuthUser::from_request_parts(parts, state).await?;
+
+        if !claims.has_permission(P::PERMISSION) {
+            return Err(AuthError::Unauthorized);
+        }
+
+        Ok(Permitted(claims, PhantomData))
+    }
+}
+
 #[derive(Debug)]
 pub enum AuthError {
     MissingToken,
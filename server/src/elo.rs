@@ -9,7 +9,6 @@ impl Default for EloRating {
 }
 
 impl EloRating {
-    #[allow(dead_code)]
     pub fn new(k_factor: f64) -> Self {
         Self { k_factor }
     }
@@ -41,9 +40,70 @@ impl EloRating {
     pub fn calculate_for_draw(&self, rating_a: i32, rating_b: i32) -> (i32, i32) {
         self.calculate_new_ratings(rating_a, rating_b, 0.5)
     }
+
+    /// New ratings after a game of any number of players, from each one's
+    /// rank (1 is best, ties share a rank). Every pair of players counts as a
+    /// game won by whoever placed higher, with K split across the opponents,
+    /// so two players get the same result as `calculate_new_ratings`.
+    pub fn calculate_for_placements(&self, ratings: &[i32], ranks: &[usize]) -> Vec<i32> {
+        let opponents = ratings.len().saturating_sub(1).max(1) as f64;
+        ratings
+            .iter()
+            .zip(ranks)
+            .enumerate()
+            .map(|(i, (&rating, &rank))| {
+                let delta: f64 = ratings
+                    .iter()
+                    .zip(ranks)
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, (&other, &other_rank))| {
+                        let score = match rank.cmp(&other_rank) {
+                            std::cmp::Ordering::Less => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Greater => 0.0,
+                        };
+                        score - self.expected_score(rating, other)
+                    })
+                    .sum();
+                rating + (self.k_factor / opponents * delta) as i32
+            })
+            .collect()
+    }
+
+    /// Like `calculate_for_placements`, with players grouped into `teams`.
+    /// Each team plays as its average rating, and all its members move by the
+    /// team's change.
+    pub fn calculate_for_teams(&self, ratings: &[i32], ranks: &[usize], teams: &[usize]) -> Vec<i32> {
+        let mut ids: Vec<usize> = teams.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        let members = |team: usize| (0..ratings.len()).filter(move |&i| teams[i] == team);
+
+        let team_ratings: Vec<i32> = ids
+            .iter()
+            .map(|&team| {
+                let sum: i32 = members(team).map(|i| ratings[i]).sum();
+                sum / members(team).count() as i32
+            })
+            .collect();
+        let team_ranks: Vec<usize> = ids
+            .iter()
+            .map(|&team| members(team).map(|i| ranks[i]).min().unwrap_or(1))
+            .collect();
+        let new_team_ratings = self.calculate_for_placements(&team_ratings, &team_ranks);
+
+        (0..ratings.len())
+            .map(|i| {
+                let t = ids.iter().position(|&team| team == teams[i]).unwrap_or(0);
+                ratings[i] + new_team_ratings[t] - team_ratings[t]
+            })
+            .collect()
+    }
 }
 
 // Helper function for easier use in other modules
+#[allow(dead_code)]
 pub fn calculate_elo_change(player1_elo: i32, player2_elo: i32, player1_wins: bool) -> (i32, i32) {
     let elo = EloRating::default();
     if player1_wins {
@@ -72,4 +132,34 @@ mod tests {
         assert!(new_winner - 1400 < 16);
         assert!(1200 - new_loser < 16);
     }
+
+    #[test]
+    fn test_placements() {
+        let elo = EloRating::default();
+
+        // Two players match the pairwise calculation
+        assert_eq!(elo.calculate_for_placements(&[1400, 1200], &[2, 1]), {
+            let (winner, loser) = elo.calculate_for_game(1200, 1400);
+            vec![loser, winner]
+        });
+
+        // The winner of three takes K/2 from each loser; the tied losers only lose to the winner
+        assert_eq!(elo.calculate_for_placements(&[1200, 1200, 1200], &[1, 2, 2]), vec![1216, 1192, 1192]);
+        assert_eq!(elo.calculate_for_placements(&[1200, 1200, 1200, 1200], &[1, 1, 1, 1]), vec![1200; 4]);
+    }
+
+    #[test]
+    fn test_teams() {
+        let elo = EloRating::default();
+
+        // Teammates move together, as a two-player game between the team averages
+        let ratings = elo.calculate_for_teams(&[1300, 1200, 1100, 1200], &[1, 2, 1, 2], &[0, 1, 0, 1]);
+        assert_eq!(ratings, vec![1316, 1184, 1116, 1184]);
+
+        // One player per team is a free-for-all
+        assert_eq!(
+            elo.calculate_for_teams(&[1200, 1250, 1300], &[2, 1, 2], &[0, 1, 2]),
+            elo.calculate_for_placements(&[1200, 1250, 1300], &[2, 1, 2])
+        );
+    }
 }
\ No newline at end of file
@@ -1,35 +1,245 @@
+use crate::models::{ReplayMove, Stone};
+use serde::{Deserialize, Serialize};
+use tictac_engine::{
+    Choice, Forbidden, Move, MoveError, Opening, OpeningError, OpeningRule, Outcome, Position, Rules, Seat,
+    Stage, Variant,
+};
 
 const BOARD_WIDTH: usize = 10;
 const BOARD_HEIGHT: usize = 10;
 const WINNING_TRAIL: usize = 5;
-const ACTING_PLAYER: usize = 2;
+const CONNECT6_TRAIL: usize = 6;
+const MIN_PLAYERS: usize = 2;
+const MAX_PLAYERS: usize = 4;
+const TEAM_SIZE: usize = 2;
+// Empty cells shown around the stones of an infinite board
+const VIEW_MARGIN: isize = 3;
+// A game may be called off without a result before this many stones are placed
+const ABORT_PLIES: usize = 2;
 
-pub type Board = [[Option<usize>; BOARD_HEIGHT]; BOARD_WIDTH];
+/// Cells indexed `board[x][y]`, holding the index of the player who owns them.
+/// Empty for infinite boards, which are sent as stones and bounds instead.
+pub type Board = Vec<Vec<Option<usize>>>;
+
+/// Stones on the board as stored with a game, from the occupied cells alone
+/// so far-apart stones on an infinite board cost no more than close ones
+pub fn stones_on(position: &Position) -> Vec<Stone> {
+    position.occupied().into_iter().map(|(mv, player)| Stone { x: mv.x, y: mv.y, player }).collect()
+}
+
+/// Corners of the part of the board to show, inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min_x: isize,
+    pub min_y: isize,
+    pub max_x: isize,
+    pub max_y: isize,
+}
 #[derive(Debug, Clone)]
 pub struct GameState {
-    pub board: Board,
-    pub current_turn: usize,
+    pub position: Position,
     pub room_creator: Option<String>,
     pub members: Vec<String>,  // All people in room
     pub player_queue: Vec<String>,  // People who stepped up to play
-    pub active_players: Vec<String>,  // Current 2 players in game
+    pub active_players: Vec<String>,  // Players in the current game, in turn order
     pub phase: GamePhase,
     pub game_id: Option<String>,  // Database game record ID
+    pub muted: Vec<String>,  // Members who may not chat
+    pub banned: Vec<String>,  // Members who may not rejoin
+    pub next_chat_id: usize,
+    pub draw_offer: Option<String>,  // Player with an open draw offer
+    pub takeback_request: Option<String>,  // Player asking to take back their last move
+    pub rules: RoomRules,
+    pub rated: bool,  // Whether the game in progress counts for ratings
+    pub opening: Option<Opening>,  // Opening being played before regular turns
+    pub opening_plies: usize,  // Stones placed by the opening, which takebacks can't revert
+    pub pairing: Option<String>,  // Tournament pairing the room was opened for, until its result is in
+    pub reserved: Vec<String>,  // Players of that pairing, black first; nobody else may step up
+}
+
+/// When players may take back moves with their opponent's consent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TakebackRule {
+    Allowed,
+    #[default]
+    CasualOnly,
+    Disabled,
+}
+
+/// Settings the room creator picks between games
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomRules {
+    #[serde(default)]
+    pub takebacks: TakebackRule,
+    #[serde(default)]
+    pub opening: Option<OpeningRule>,
+    #[serde(default)]
+    pub variant: Variant,
+    #[serde(default = "default_players")]
+    pub players: usize,  // More than two is a free-for-all on a larger board
+    #[serde(default)]
+    pub teams: bool,  // Four players as two teams of two sharing a colour
+    #[serde(default)]
+    pub infinite: bool,  // No edges; the board grows as stones are placed
+}
+
+fn default_players() -> usize {
+    MIN_PLAYERS
+}
+
+impl RoomRules {
+    /// Why a game can't be played under these rules, if it can't
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&self.players) {
+            return Err(format!("Unsupported player count {}", self.players));
+        }
+        // Openings hand colours between exactly two players
+        if self.opening.is_some() && self.players != MIN_PLAYERS {
+            return Err("Openings need two players".to_string());
+        }
+        if self.teams && self.players != 2 * TEAM_SIZE {
+            return Err(format!("Team games need {} players", 2 * TEAM_SIZE));
+        }
+        Ok(())
+    }
+}
+
+impl Default for RoomRules {
+    fn default() -> Self {
+        Self {
+            takebacks: TakebackRule::default(),
+            opening: None,
+            variant: Variant::default(),
+            players: default_players(),
+            teams: false,
+            infinite: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum GamePhase {
     Ready,
+    Opening,  // Placing opening stones and choosing colours
     Action,
     Scoreboard,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameResult {
+    Win,
+    Draw,
+    Forfeit,
+    Timeout,
+    Resign,
+    Abort,
+}
+
+/// How a game finished, as broadcast to the room and stored with the game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameEnding {
+    pub result: GameResult,
+    pub reason: String,
+    pub winner: Option<String>,  // Email of the winning player
+    pub loser: Option<String>,  // Player who resigned or left, placed last
+    pub line: Vec<Move>,  // Cells of the winning line, empty unless won on the board
+    pub placements: Vec<Placement>,  // Every player's finishing place, see `GameEnding::ranked`
+}
+
+/// Where a player finished; 1 is best and tied players share a rank
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Placement {
+    pub player: String,
+    pub rank: usize,
+    pub team: usize,  // The colour played; teammates share one
+}
+
+impl GameEnding {
+    pub fn new(result: GameResult, reason: &str, winner: Option<String>) -> Self {
+        Self {
+            result,
+            reason: reason.to_string(),
+            winner,
+            loser: None,
+            line: Vec::new(),
+            placements: Vec::new(),
+        }
+    }
+
+    /// Fill in the placements of `players`, seated so that player `i` plays
+    /// colour `i % colours`: the winner's team first and everyone else tied
+    /// behind, or everyone tied ahead of the loser's team, or all tied
+    pub fn ranked(mut self, players: &[String], colours: usize) -> Self {
+        let team_of = |member: &String| players.iter().position(|p| p == member).map(|i| i % colours);
+        let (winner, loser) = (self.winner.as_ref().and_then(team_of), self.loser.as_ref().and_then(team_of));
+        self.placements = players
+            .iter()
+            .enumerate()
+            .map(|(i, player)| {
+                let team = i % colours;
+                let rank = match (winner, loser) {
+                    (Some(winner), _) if winner == team => 1,
+                    (Some(_), _) => 2,
+                    (None, Some(loser)) if loser == team => colours,
+                    _ => 1,
+                };
+                Placement { player: player.clone(), rank, team }
+            })
+            .collect();
+        self
+    }
+}
+
 #[derive(Debug)]
 pub enum MoveResult {
     Ok,
-    Err,
-    Win,
-    Draw,
+    Rejected(MoveRejection),
+    Ended(GameEnding),
+}
+
+/// Why a stone was not placed, reported back to the player who tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveRejection {
+    NotPlaying,
+    NotYourTurn,
+    OutOfBounds,
+    Occupied,
+    GameOver,
+    DoubleThree,
+    DoubleFour,
+    Overline,
+}
+
+impl From<MoveError> for MoveRejection {
+    fn from(error: MoveError) -> Self {
+        match error {
+            MoveError::OutOfBounds => MoveRejection::OutOfBounds,
+            MoveError::Occupied => MoveRejection::Occupied,
+            MoveError::GameOver => MoveRejection::GameOver,
+            MoveError::Forbidden(Forbidden::DoubleThree) => MoveRejection::DoubleThree,
+            MoveError::Forbidden(Forbidden::DoubleFour) => MoveRejection::DoubleFour,
+            MoveError::Forbidden(Forbidden::Overline) => MoveRejection::Overline,
+        }
+    }
+}
+
+impl From<OpeningError> for MoveRejection {
+    fn from(error: OpeningError) -> Self {
+        match error {
+            OpeningError::Move(e) => e.into(),
+            _ => MoveRejection::NotYourTurn,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OpeningResult {
+    Rejected,
+    Continue,
+    Done { swapped: bool },  // Regular play starts; `swapped` if the responder took black
 }
 impl Default for GameState {
     fn default() -> Self {
@@ -39,17 +249,38 @@ impl Default for GameState {
 impl GameState {
     pub fn new() -> Self {
         Self {
-            board: [[None; BOARD_HEIGHT]; BOARD_WIDTH],
-            current_turn: usize::MAX,
+            position: Position::new(Self::rules(&RoomRules::default())),
             room_creator: None,
             members: Vec::new(),
             player_queue: Vec::new(),
             active_players: Vec::new(),
             phase: GamePhase::Ready,
             game_id: None,
+            muted: Vec::new(),
+            banned: Vec::new(),
+            next_chat_id: 0,
+            draw_offer: None,
+            takeback_request: None,
+            rules: RoomRules::default(),
+            rated: false,
+            opening: None,
+            opening_plies: 0,
+            pairing: None,
+            reserved: Vec::new(),
         }
     }
 
+    /// A room for a tournament pairing, where only its two players may play
+    /// and the game starts once both have stepped up
+    pub fn for_pairing(pairing: String, rules: RoomRules, players: Vec<String>) -> Self {
+        let mut room = Self::new();
+        room.position = Position::new(Self::rules(&rules));
+        room.rules = rules;
+        room.pairing = Some(pairing);
+        room.reserved = players;
+        room
+    }
+
     pub fn add_member(&mut self, member: String) -> usize {
         let id = self.members.len();
         
@@ -74,56 +305,244 @@ impl GameState {
         }
     }
 
-    fn count_trail(&self, x: usize, y: usize) -> usize {
-        if self.board[x][y].is_none() {
-            return 0;
-        }
-        let traverse = |dx, dy, v| {
-            let mut ret = 0;
-            let mut i = x as i32 + dx;
-            let mut j = y as i32 + dy;
-            while i >= 0
-                && i < self.board.len() as i32
-                && j >= 0
-                && j < self.board[0].len() as i32
-                && self.board[i as usize][j as usize].is_some()
-                && self.board[i as usize][j as usize].unwrap() == v
-            {
-                ret += 1;
-                i += dx;
-                j += dy;
-            }
-            ret
+    pub fn next_chat_id(&mut self) -> usize {
+        let id = self.next_chat_id;
+        self.next_chat_id += 1;
+        id
+    }
+
+    pub fn is_muted(&self, member: &str) -> bool {
+        self.muted.iter().any(|m| m == member)
+    }
+
+    pub fn is_banned(&self, member: &str) -> bool {
+        self.banned.iter().any(|m| m == member)
+    }
+
+    pub fn mute_member(&mut self, member_id: usize) -> Option<String> {
+        let member = self.members.get(member_id)?.clone();
+        if !self.is_muted(&member) {
+            self.muted.push(member.clone());
+        }
+        Some(member)
+    }
+
+    pub fn unmute_member(&mut self, member_id: usize) -> Option<String> {
+        let member = self.members.get(member_id)?.clone();
+        self.muted.retain(|m| m != &member);
+        Some(member)
+    }
+
+    pub fn ban_member(&mut self, member_id: usize) -> Option<String> {
+        let member = self.members.get(member_id)?.clone();
+        if !self.is_banned(&member) {
+            self.banned.push(member.clone());
+        }
+        self.remove_member(member.clone());
+        Some(member)
+    }
+
+    pub fn rules(room: &RoomRules) -> Rules {
+        let win_length = match room.variant {
+            Variant::Connect6 => CONNECT6_TRAIL,
+            _ => WINNING_TRAIL,
+        };
+        let colours = if room.teams { room.players / TEAM_SIZE } else { room.players };
+        if room.infinite {
+            return Rules::infinite(win_length, colours).with_variant(room.variant);
+        }
+        // Free-for-all games need room to get around each other's blocks
+        let (width, height) = match colours {
+            3 => (15, 15),
+            4.. => (19, 19),
+            _ => (BOARD_WIDTH, BOARD_HEIGHT),
+        };
+        Rules::new(width, height, win_length, colours).with_variant(room.variant)
+    }
+
+    /// Seat in `active_players` whose turn it is. Teammates sit one round of
+    /// colours apart and take their colour's turns in rotation.
+    fn seat_to_move(&self) -> usize {
+        let rules = self.position.rules();
+        let plies = self.position.history().len();
+        let colour = rules.player_at(plies);
+        // Turns this colour has started, counting the one under way
+        let turns = (0..=plies)
+            .filter(|&ply| rules.player_at(ply) == colour && (ply == 0 || rules.player_at(ply - 1) != colour))
+            .count();
+        let team_size = self.active_players.len().max(1).div_ceil(rules.players);
+        colour + rules.players * ((turns - 1) % team_size)
+    }
+
+    /// Everyone in the game playing the same colour as `member`, themselves included
+    pub fn team_of(&self, member: &str) -> Vec<String> {
+        let colours = self.position.rules().players;
+        let Some(seat) = self.active_players.iter().position(|p| p == member) else {
+            return Vec::new();
         };
-        let v = self.board[x][y].unwrap();
-        const MOVES: [(i32, i32); 4] = [
-            (1, 1),  // diagonal 1
-            (1, -1), // diagonal 2
-            (0, 1),  // vertical
-            (1, 0),  // horizontal
-        ];
-        MOVES
-            .into_iter()
-            .map(|(dx, dy)| traverse(dx, dy, v) + traverse(-dx, -dy, v) + 1)
-            .max()
-            .unwrap_or(1)
-    }
-
-    fn is_board_full(&self) -> bool {
-        for row in &self.board {
-            for cell in row {
-                if cell.is_none() {
-                    return false;
-                }
+        self.active_players
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % colours == seat % colours)
+            .map(|(_, p)| p.clone())
+            .collect()
+    }
+
+    /// Stones the player to move still places this turn, zero outside of regular play
+    pub fn stones_left(&self) -> usize {
+        match self.phase {
+            GamePhase::Action => self.position.stones_left(),
+            _ => 0,
+        }
+    }
+
+    /// Player index to move, or `usize::MAX` outside of a game. During an
+    /// opening this is whoever places the opening stones, whatever their colour.
+    pub fn current_turn(&self) -> usize {
+        match (&self.phase, &self.opening) {
+            (GamePhase::Action, _) => self.seat_to_move(),
+            (GamePhase::Opening, Some(opening)) => match opening.stage() {
+                Stage::Place { seat, .. } => Self::seat_index(seat),
+                _ => usize::MAX,
+            },
+            _ => usize::MAX,
+        }
+    }
+
+    pub fn in_game(&self) -> bool {
+        matches!(self.phase, GamePhase::Opening | GamePhase::Action)
+    }
+
+    // The first active player proposes the opening, the second answers it
+    fn seat_index(seat: Seat) -> usize {
+        match seat {
+            Seat::Proposer => 0,
+            Seat::Responder => 1,
+        }
+    }
+
+    fn seat_of(&self, member: &str) -> Option<Seat> {
+        match self.active_players.iter().position(|p| p == member)? {
+            0 => Some(Seat::Proposer),
+            1 => Some(Seat::Responder),
+            _ => None,
+        }
+    }
+
+    /// Member expected to act in the opening, what is left to place and what they may choose
+    pub fn opening_turn(&self) -> Option<(String, usize, &'static [Choice])> {
+        if !matches!(self.phase, GamePhase::Opening) {
+            return None;
+        }
+        let opening = self.opening.as_ref()?;
+        let seat = opening.to_act()?;
+        let remaining = match opening.stage() {
+            Stage::Place { remaining, .. } => remaining,
+            _ => 0,
+        };
+        let member = self.active_players.get(Self::seat_index(seat))?.clone();
+        Some((member, remaining, opening.choices()))
+    }
+
+    fn place_opening(&mut self, member: &str, x: isize, y: isize) -> MoveResult {
+        let (Some(seat), Some(opening)) = (self.seat_of(member), self.opening.as_mut()) else {
+            return MoveResult::Rejected(MoveRejection::NotPlaying);
+        };
+        match opening.place(seat, &mut self.position, Move::new(x, y)) {
+            Ok(()) => MoveResult::Ok,
+            Err(e) => {
+                eprintln!("Invalid opening move - {}", e);
+                MoveResult::Rejected(e.into())
             }
         }
-        true
     }
 
-    pub fn place(&mut self, x: usize, y: usize, member_id: usize) -> MoveResult {
+    pub fn choose_opening(&mut self, member_id: usize, choice: Choice) -> OpeningResult {
+        if !matches!(self.phase, GamePhase::Opening) || member_id >= self.members.len() {
+            return OpeningResult::Rejected;
+        }
+        let (Some(seat), Some(opening)) = (self.seat_of(&self.members[member_id]), self.opening.as_mut()) else {
+            return OpeningResult::Rejected;
+        };
+        if let Err(e) = opening.choose(seat, choice) {
+            eprintln!("Invalid opening choice - {}", e);
+            return OpeningResult::Rejected;
+        }
+
+        let Stage::Done { black } = opening.stage() else {
+            return OpeningResult::Continue;
+        };
+        // Stone colours follow player order, so whoever took black plays first
+        let swapped = black == Seat::Responder;
+        if swapped {
+            self.active_players.swap(0, 1);
+        }
+        self.opening_plies = self.position.history().len();
+        self.phase = GamePhase::Action;
+        OpeningResult::Done { swapped }
+    }
+
+    pub fn board(&self) -> Board {
+        let rules = self.position.rules();
+        (0..rules.width as isize)
+            .map(|x| (0..rules.height as isize).map(|y| self.position.get(x, y)).collect())
+            .collect()
+    }
+
+    /// Every stone on the board
+    pub fn stones(&self) -> Vec<Stone> {
+        stones_on(&self.position)
+    }
+
+    /// The whole of a bounded board; for an infinite one the stones with
+    /// some room around them to play into
+    pub fn bounds(&self) -> Bounds {
+        let rules = self.position.rules();
+        if !rules.infinite {
+            return Bounds { min_x: 0, min_y: 0, max_x: rules.width as isize - 1, max_y: rules.height as isize - 1 };
+        }
+        let origin = Move::new(0, 0);
+        let (min, max) = self.position.bounds().unwrap_or((origin, origin));
+        Bounds {
+            min_x: min.x - VIEW_MARGIN,
+            min_y: min.y - VIEW_MARGIN,
+            max_x: max.x + VIEW_MARGIN,
+            max_y: max.y + VIEW_MARGIN,
+        }
+    }
+
+    /// Pairs captured by each player, in player order
+    pub fn captures(&self) -> Vec<usize> {
+        (0..self.position.rules().players).map(|p| self.position.captures(p)).collect()
+    }
+
+    /// Stones taken off the board by the last move
+    pub fn last_captured(&self) -> Vec<Move> {
+        match self.position.history().len() {
+            0 => Vec::new(),
+            plies => self.position.captured(plies - 1).collect(),
+        }
+    }
+
+    /// Moves of the game so far, as stored for replays
+    pub fn replay(&self) -> Vec<ReplayMove> {
+        self.position
+            .history()
+            .iter()
+            .enumerate()
+            .map(|(ply, mv)| ReplayMove {
+                x: mv.x,
+                y: mv.y,
+                player: self.position.rules().player_at(ply),
+                captured: self.position.captured(ply).collect(),
+            })
+            .collect()
+    }
+
+    pub fn place(&mut self, x: isize, y: isize, member_id: usize) -> MoveResult {
         if member_id >= self.members.len() {
             eprintln!("Invalid member id {}", member_id);
-            return MoveResult::Err;
+            return MoveResult::Rejected(MoveRejection::NotPlaying);
         }
         
         let member = &self.members[member_id];
@@ -133,43 +552,60 @@ impl GameState {
             Some(idx) => idx,
             None => {
                 eprintln!("Member {} is not an active player", member);
-                return MoveResult::Err;
+                return MoveResult::Rejected(MoveRejection::NotPlaying);
             }
         };
         
-        if self.board[x][y].is_some() {
-            eprintln!("Invalid move - position occupied");
-            return MoveResult::Err;
+        if matches!(self.phase, GamePhase::Opening) {
+            let member = member.clone();
+            return self.place_opening(&member, x, y);
         }
-        
-        if player_index != self.current_turn {
-            eprintln!("Not this player's turn: player_index={}, current_turn={}, member={}", 
-                player_index, self.current_turn, member);
-            return MoveResult::Err;
+
+        if !matches!(self.phase, GamePhase::Action) {
+            eprintln!("Invalid move - no game in progress");
+            return MoveResult::Rejected(MoveRejection::NotPlaying);
         }
-        
-        self.board[x][y] = Some(player_index);
-        
-        // Check for win
-        if self.count_trail(x, y) >= WINNING_TRAIL {
-            self.current_turn = usize::MAX;
-            self.phase = GamePhase::Scoreboard;
-            return MoveResult::Win;
+
+        if player_index != self.seat_to_move() {
+            eprintln!("Not this player's turn: player_index={}, current_turn={}, member={}", 
+                player_index, self.seat_to_move(), member);
+            return MoveResult::Rejected(MoveRejection::NotYourTurn);
         }
         
-        // Check for draw
-        if self.is_board_full() {
-            self.current_turn = usize::MAX;
-            self.phase = GamePhase::Scoreboard;
-            return MoveResult::Draw;
+        match self.position.apply(Move::new(x, y)) {
+            Ok(None) => {
+                // Moving on instead of answering turns down the opponent's requests
+                self.draw_offer = None;
+                self.takeback_request = None;
+                MoveResult::Ok
+            }
+            Ok(Some(Outcome::Win { player, line })) => {
+                self.phase = GamePhase::Scoreboard;
+                let winner = self.active_players.get(player).cloned();
+                MoveResult::Ended(GameEnding {
+                    line: line.cells().collect(),
+                    ..GameEnding::new(GameResult::Win, &format!("{} in a row", line.len), winner)
+                })
+            }
+            Ok(Some(Outcome::Captures { player })) => {
+                self.phase = GamePhase::Scoreboard;
+                let winner = self.active_players.get(player).cloned();
+                let captures = self.position.captures(player);
+                MoveResult::Ended(GameEnding::new(GameResult::Win, &format!("{} captures", captures), winner))
+            }
+            Ok(Some(Outcome::Draw)) => {
+                self.phase = GamePhase::Scoreboard;
+                MoveResult::Ended(GameEnding::new(GameResult::Draw, "board full", None))
+            }
+            Err(e) => {
+                eprintln!("Invalid move - {}", e);
+                MoveResult::Rejected(e.into())
+            }
         }
-        
-        self.current_turn = (self.current_turn + 1) % ACTING_PLAYER;
-        MoveResult::Ok
     }
 
     pub fn get_acting_players(&mut self) -> Vec<usize> {
-        (0..ACTING_PLAYER).collect()
+        (0..self.rules.players).collect()
     }
 
     pub fn step_up(&mut self, member_id: usize) -> bool {
@@ -184,6 +620,9 @@ impl GameState {
         if self.player_queue.contains(&member) {
             return false;
         }
+        if !self.reserved.is_empty() && !self.reserved.contains(&member) {
+            return false;
+        }
         
         self.player_queue.push(member);
         true
@@ -209,19 +648,45 @@ impl GameState {
             eprintln!("Only room creator can start the game");
             return false;
         }
+
+        // Tournament games start by themselves
+        if self.pairing.is_some() {
+            return false;
+        }
         
-        // Need at least 2 players in queue
-        if self.player_queue.len() < 2 {
-            eprintln!("Need at least 2 players in queue");
+        let players = self.rules.players;
+        if self.player_queue.len() < players {
+            eprintln!("Need at least {} players in queue", players);
             return false;
         }
         
-        // Take first 2 from queue as active players
-        self.active_players = self.player_queue.drain(..2).collect();
+        // Take the first ones from the queue as active players
+        self.active_players = self.player_queue.drain(..players).collect();
+        self.begin();
+        true
+    }
+
+    /// Start the pairing's game once both of its players are queued, with
+    /// the colours they were paired with
+    pub fn start_pairing(&mut self) -> bool {
+        let queued = self.reserved.iter().all(|p| self.player_queue.contains(p));
+        if self.pairing.is_none() || self.in_game() || self.reserved.is_empty() || !queued {
+            return false;
+        }
+        self.player_queue.retain(|p| !self.reserved.contains(p));
+        self.active_players = self.reserved.clone();
+        self.begin();
+        true
+    }
+
+    fn begin(&mut self) {
         eprintln!("Game starting with players: {:?}", self.active_players);
         self.reset();
-        self.phase = GamePhase::Action;
-        true
+        self.opening = self.rules.opening.map(Opening::new);
+        self.phase = match self.opening {
+            Some(_) => GamePhase::Opening,
+            None => GamePhase::Action,
+        };
     }
     
     pub fn is_room_creator(&self, member_id: usize) -> bool {
@@ -238,12 +703,167 @@ impl GameState {
             .collect()
     }
 
-    fn reset(&mut self) {
-        for i in 0..BOARD_WIDTH {
-            for j in 0..BOARD_HEIGHT {
-                self.board[i][j] = None;
-            }
+    // The playing member, if `member_id` is in the game currently being played
+    fn playing_member(&self, member_id: usize) -> Option<String> {
+        if !self.in_game() || !self.is_active_player(member_id) {
+            return None;
         }
-        self.current_turn = 0;  // First player's turn
+        Some(self.members[member_id].clone())
+    }
+
+    fn finish(&mut self, ending: GameEnding) -> Option<GameEnding> {
+        self.phase = GamePhase::Scoreboard;
+        self.draw_offer = None;
+        self.takeback_request = None;
+        Some(ending)
+    }
+
+    pub fn resign(&mut self, member_id: usize) -> Option<GameEnding> {
+        let member = self.playing_member(member_id)?;
+        let reason = format!("{} resigned", member);
+        self.finish(self.ending_without(member, GameResult::Resign, &reason))
+    }
+
+    /// End the game because `member_id` left it
+    pub fn forfeit(&mut self, member_id: usize) -> Option<GameEnding> {
+        let member = self.playing_member(member_id)?;
+        self.finish(self.ending_without(member, GameResult::Forfeit, "opponent disconnected"))
+    }
+
+    // The game ends for everyone when one player drops out; a lone opposing
+    // colour wins, more than one share first place
+    fn ending_without(&self, member: String, result: GameResult, reason: &str) -> GameEnding {
+        let colours = self.position.rules().players;
+        let team = self.team_of(&member);
+        let mut others = self.active_players.iter().take(colours).filter(|p| !team.contains(p));
+        let winner = match (others.next(), others.next()) {
+            (Some(winner), None) => Some(winner.clone()),
+            _ => None,
+        };
+        GameEnding { loser: Some(member), ..GameEnding::new(result, reason, winner) }
+    }
+
+    pub fn offer_draw(&mut self, member_id: usize) -> bool {
+        let Some(member) = self.playing_member(member_id) else {
+            return false;
+        };
+        // Agreeing between more than two isn't supported
+        if self.draw_offer.is_some() || self.active_players.len() != 2 {
+            return false;
+        }
+        self.draw_offer = Some(member);
+        true
+    }
+
+    /// Accept the opponent's open draw offer
+    pub fn accept_draw(&mut self, member_id: usize) -> Option<GameEnding> {
+        let member = self.playing_member(member_id)?;
+        if self.draw_offer.as_ref().is_none_or(|offer| *offer == member) {
+            return None;
+        }
+        self.finish(GameEnding::new(GameResult::Draw, "draw agreed", None))
+    }
+
+    pub fn decline_draw(&mut self, member_id: usize) -> bool {
+        let Some(member) = self.playing_member(member_id) else {
+            return false;
+        };
+        if self.draw_offer.as_ref().is_none_or(|offer| *offer == member) {
+            return false;
+        }
+        self.draw_offer = None;
+        true
+    }
+
+    /// Call the game off before it properly started; aborted games are never rated
+    pub fn abort(&mut self, member_id: usize) -> Option<GameEnding> {
+        let member = self.playing_member(member_id)?;
+        // An opening can be walked away from at any point before regular play
+        if matches!(self.phase, GamePhase::Action) && self.position.history().len() - self.opening_plies >= ABORT_PLIES {
+            eprintln!("Too late to abort: {} stones placed", self.position.history().len());
+            return None;
+        }
+        self.finish(GameEnding::new(GameResult::Abort, &format!("{} aborted the game", member), None))
+    }
+
+    /// Change the room rules; only the creator may, and not during a game or
+    /// in a tournament room
+    pub fn set_rules(&mut self, member_id: usize, rules: RoomRules) -> bool {
+        if !self.is_room_creator(member_id) || self.in_game() || self.pairing.is_some() {
+            return false;
+        }
+        if let Err(e) = rules.validate() {
+            eprintln!("Invalid rules - {}", e);
+            return false;
+        }
+        self.rules = rules;
+        true
+    }
+
+    pub fn takebacks_allowed(&self) -> bool {
+        if self.active_players.len() != 2 {
+            return false;
+        }
+        match self.rules.takebacks {
+            TakebackRule::Allowed => true,
+            TakebackRule::CasualOnly => !self.rated,
+            TakebackRule::Disabled => false,
+        }
+    }
+
+    pub fn request_takeback(&mut self, member_id: usize) -> bool {
+        let Some(member) = self.playing_member(member_id) else {
+            return false;
+        };
+        if !matches!(self.phase, GamePhase::Action) || !self.takebacks_allowed() || self.takeback_request.is_some() {
+            return false;
+        }
+        if self.takeback_plies(&member).is_none() {
+            return false;
+        }
+        self.takeback_request = Some(member);
+        true
+    }
+
+    // Plies to revert so it is `requester`'s turn again before their last turn,
+    // if they have played one since the opening
+    fn takeback_plies(&self, requester: &str) -> Option<usize> {
+        let index = self.active_players.iter().position(|p| p == requester)?;
+        let rules = self.position.rules();
+        let plies = self.position.history().len();
+        // Undo the reply too if one was played since, and every stone of a two-stone turn
+        let last = (self.opening_plies..plies).rev().find(|&ply| rules.player_at(ply) == index)?;
+        let start = (self.opening_plies..=last)
+            .rev()
+            .take_while(|&ply| rules.player_at(ply) == index)
+            .last()?;
+        Some(plies - start)
+    }
+
+    /// Grant the opponent's takeback, returning how many plies were reverted
+    pub fn accept_takeback(&mut self, member_id: usize) -> Option<usize> {
+        let member = self.playing_member(member_id)?;
+        let requester = self.takeback_request.take_if(|requester| *requester != member)?;
+        let plies = self.takeback_plies(&requester)?;
+        for _ in 0..plies {
+            self.position.undo();
+        }
+        self.draw_offer = None;
+        Some(plies)
+    }
+
+    pub fn decline_takeback(&mut self, member_id: usize) -> bool {
+        let Some(member) = self.playing_member(member_id) else {
+            return false;
+        };
+        self.takeback_request.take_if(|requester| *requester != member).is_some()
+    }
+
+    fn reset(&mut self) {
+        self.position = Position::new(Self::rules(&self.rules));
+        self.draw_offer = None;
+        self.takeback_request = None;
+        self.opening = None;
+        self.opening_plies = 0;
     }
 }
@@ -0,0 +1,78 @@
+use crate::{models::User, repo::UserRepository};
+use surrealdb::RecordId;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Guests that have not been seen for this long are purged
+const GUEST_TTL_HOURS: i64 = 30 * 24;
+// A guest nobody came back to is purged much sooner
+const UNUSED_GUEST_TTL_HOURS: i64 = 24;
+// Guests one address may create per window
+const GUESTS_PER_IP: u32 = 20;
+const GUEST_WINDOW: Duration = Duration::from_secs(60 * 60);
+// Reserved domain so guest emails never collide with real registrations
+const GUEST_EMAIL_DOMAIN: &str = "guest.invalid";
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Create a persisted guest account with a unique generated name
+pub async fn create_guest(users: &UserRepository) -> Result<User, Box<dyn std::error::Error>> {
+    let tag = uuid::Uuid::new_v4().simple().to_string();
+    let username = format!("Guest_{}", &tag[..10]);
+    let email = format!("guest_{}@{}", tag, GUEST_EMAIL_DOMAIN);
+
+    let guest = users
+        .create_guest(&email, &username, UNUSED_GUEST_TTL_HOURS)
+        .await?
+        .ok_or("Failed to create guest user")?;
+
+    println!("Created guest user {}", guest.username);
+    Ok(guest)
+}
+
+/// Guests created per address in the current window, so anonymous
+/// connections can't grow the user table without bound
+#[derive(Clone, Default)]
+pub struct GuestThrottle {
+    created: Arc<Mutex<HashMap<IpAddr, (u32, Instant)>>>,
+}
+
+impl GuestThrottle {
+    /// Count a new guest for the address, or the seconds until it may create one
+    pub fn claim(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut created = self.created.lock().unwrap();
+        created.retain(|_, (_, since)| now.duration_since(*since) < GUEST_WINDOW);
+
+        let (count, since) = created.entry(ip).or_insert((0, now));
+        if *count >= GUESTS_PER_IP {
+            let remaining = GUEST_WINDOW.saturating_sub(now.duration_since(*since));
+            return Err(remaining.as_secs().max(1));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+/// Push back the expiry of a guest that is still active
+pub async fn touch_guest(users: &UserRepository, user_id: &RecordId) -> Result<(), Box<dyn std::error::Error>> {
+    users.touch_guest(user_id, GUEST_TTL_HOURS).await?;
+    Ok(())
+}
+
+/// Delete guest accounts whose expiry has passed.
+/// Their games stay in the database; match history skips missing opponents.
+pub fn spawn_guest_purge(users: UserRepository) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match users.purge_expired_guests().await {
+                Ok(0) => {}
+                Ok(count) => println!("Purged {} expired guest accounts", count),
+                Err(e) => eprintln!("Failed to purge expired guests: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,132 @@
+pub mod api;
+pub mod api_keys;
+pub mod auth;
+pub mod db;
+pub mod elo;
+pub mod game;
+pub mod guest;
+pub mod jwt_keys;
+pub mod login_guard;
+pub mod migrations;
+pub mod models;
+pub mod netcode;
+pub mod pairing;
+pub mod protocol;
+pub mod repo;
+pub mod roles;
+pub mod room;
+pub mod state;
+pub mod tournament;
+
+use axum::routing::{get, post, put, delete};
+use axum::Extension;
+use axum::Router;
+use netcode::handle_http;
+use room::GameRooms;
+use state::AppState;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::env;
+use tokio::sync::broadcast;
+use tokio::sync::Mutex;
+use tower_http::cors::{CorsLayer, AllowOrigin};
+use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use http::{HeaderValue, Method};
+use tower_http::services::ServeDir;
+
+/// Every HTTP and WebSocket route of the server, backed by the given storage
+pub fn app(state: AppState) -> Router {
+    let game_rooms: GameRooms = Arc::new(Mutex::new(HashMap::new()));
+    let (tx, _) = broadcast::channel::<String>(1024);
+
+    // API routes
+    let api_routes = Router::new()
+        // Health check
+        .route("/health", get(|| async { "OK" }))
+        // Public keys for validating our tokens
+        .route("/.well-known/jwks.json", get(api::auth::jwks))
+        // Auth routes
+        .route("/auth/register", post(api::auth::register))
+        .route("/auth/login", post(api::auth::login))
+        .route("/auth/me", get(api::auth::me))
+        .route("/auth/guest", post(api::auth::guest_login))
+        .route("/auth/upgrade", post(api::auth::upgrade_guest))
+        // API key routes
+        .route("/keys", get(api::keys::list_keys).post(api::keys::create_key))
+        .route("/keys/{id}", delete(api::keys::revoke_key))
+        // User routes
+        .route("/users/{id}", get(api::users::get_user_profile))
+        .route("/users/profile", put(api::users::update_profile))
+        .route("/users/profile/picture", post(api::users::upload_profile_picture))
+        // Leaderboard routes
+        .route("/leaderboard", get(api::leaderboard::get_leaderboard))
+        .route("/leaderboard/top", get(api::leaderboard::get_top_players))
+        .route("/leaderboard/rank/{id}", get(api::leaderboard::get_player_rank))
+        // Game routes
+        .route("/games/history", get(api::games::get_match_history))
+        .route("/games/import", post(api::notation::import_game))
+        .route("/games/{id}", get(api::games::get_game_details))
+        .route("/games/{id}/export", get(api::notation::export_game))
+        // Analysis routes
+        .route("/analysis", post(api::analysis::analyse_position))
+        // Opening explorer
+        .route("/openings", get(api::openings::explore_openings))
+        // Puzzle routes
+        .route("/puzzles/next", get(api::puzzles::next_puzzle))
+        .route("/puzzles/{id}", get(api::puzzles::get_puzzle))
+        .route("/puzzles/{id}/attempt", post(api::puzzles::attempt_puzzle))
+        // Tournament routes
+        .route("/tournaments", get(api::tournaments::list_tournaments).post(api::tournaments::create_tournament))
+        .route("/tournaments/{id}", get(api::tournaments::get_tournament))
+        .route("/tournaments/{id}/join", post(api::tournaments::join_tournament))
+        .route("/tournaments/{id}/start", post(api::tournaments::start_tournament))
+        .route("/tournaments/{id}/pairings/{pairing}/result", post(api::tournaments::adjudicate_pairing))
+        // Admin routes
+        .route("/admin/users", get(api::admin::list_users))
+        .route("/admin/users/{id}", put(api::admin::update_user))
+        .route("/admin/users/{id}", delete(api::admin::delete_user))
+        .route("/admin/users/{id}/unlock", post(api::admin::unlock_user))
+        .route("/admin/locked", get(api::admin::list_locked_users))
+        .route("/admin/stats", get(api::admin::get_stats))
+        .route("/admin/puzzles/mine", post(api::puzzles::mine_puzzles))
+        // Debug routes
+        .route("/debug/db", get(api::debug::get_database_info));
+
+    Router::new()
+        .route("/ws/{room}", get(handle_http))
+        .nest("/api", api_routes)
+        .nest_service("/uploads", ServeDir::new("uploads"))
+        .layer(Extension(game_rooms.clone()))
+        .layer(Extension(tx.clone()))
+        .layer({
+            let mut cors_origins = Vec::new();
+            
+            // Get allowed origins from environment variable
+            // Default to common development URLs if not specified
+            let origins_str = env::var("CORS_ALLOWED_ORIGINS")
+                .unwrap_or_else(|_| "http://localhost:3000,http://localhost:5173,http://localhost:30030".to_string());
+            
+            for origin in origins_str.split(',') {
+                let origin = origin.trim();
+                if !origin.is_empty() {
+                    if let Ok(header_value) = origin.parse::<HeaderValue>() {
+                        cors_origins.push(header_value);
+                        println!("Added CORS origin: {}", origin);
+                    } else {
+                        eprintln!("Invalid CORS origin: {}", origin);
+                    }
+                }
+            }
+            
+            if cors_origins.is_empty() {
+                panic!("No valid CORS origins configured!");
+            }
+            
+            CorsLayer::new()
+                .allow_origin(AllowOrigin::list(cors_origins))
+                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
+                .allow_headers([AUTHORIZATION, CONTENT_TYPE])
+                .allow_credentials(true)
+        })
+        .with_state(state)
+}
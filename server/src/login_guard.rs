@@ -0,0 +1,133 @@
+use crate::repo::UserRepository;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use bcrypt::{hash, verify, DEFAULT_COST};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use surrealdb::RecordId;
+
+// Failures allowed before back-off kicks in
+const ACCOUNT_FREE_ATTEMPTS: u32 = 3;
+// Failures after which the account is locked for the full duration
+const ACCOUNT_LOCKOUT_THRESHOLD: u32 = 10;
+// An IP may front many users (NAT, proxies), so it gets more room
+const IP_FREE_ATTEMPTS: u32 = 10;
+const IP_LOCKOUT_THRESHOLD: u32 = 50;
+const LOCKOUT_SECS: u64 = 15 * 60;
+// IP failure counters are forgotten after this much quiet time; a successful
+// login doesn't clear them, or logging into any account between guesses
+// would dodge the throttle
+const IP_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+// Verified against when the account does not exist so both paths cost one bcrypt
+static DUMMY_HASH: Lazy<String> = Lazy::new(|| {
+    hash("dummy-password-for-timing", DEFAULT_COST).expect("bcrypt hash")
+});
+
+struct IpAttempts {
+    failures: u32,
+    blocked_until: Option<Instant>,
+    last_failure: Instant,
+}
+
+static IP_ATTEMPTS: Lazy<Mutex<HashMap<IpAddr, IpAttempts>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Seconds to wait after the given number of consecutive failures:
+/// nothing for the free attempts, then doubling, then a flat lockout
+pub fn backoff_secs(failures: u32, free_attempts: u32, lockout_threshold: u32) -> u64 {
+    if failures < free_attempts {
+        0
+    } else if failures >= lockout_threshold {
+        LOCKOUT_SECS
+    } else {
+        (1u64 << (failures - free_attempts).min(16)).min(LOCKOUT_SECS)
+    }
+}
+
+/// Run a bcrypt verification that cannot be told apart from a real one
+pub fn verify_dummy(password: &str) {
+    let _ = verify(password, &DUMMY_HASH);
+}
+
+/// Remaining seconds this IP must wait, if it is currently blocked
+pub fn ip_retry_after(ip: IpAddr) -> Option<u64> {
+    let attempts = IP_ATTEMPTS.lock().unwrap();
+    let blocked_until = attempts.get(&ip)?.blocked_until?;
+    let remaining = blocked_until.checked_duration_since(Instant::now())?;
+    Some(remaining.as_secs().max(1))
+}
+
+pub fn record_ip_failure(ip: IpAddr) {
+    let now = Instant::now();
+    let mut attempts = IP_ATTEMPTS.lock().unwrap();
+    attempts.retain(|_, a| now.duration_since(a.last_failure) < IP_WINDOW);
+
+    let entry = attempts.entry(ip).or_insert(IpAttempts {
+        failures: 0,
+        blocked_until: None,
+        last_failure: now,
+    });
+    entry.failures += 1;
+    entry.last_failure = now;
+
+    let wait = backoff_secs(entry.failures, IP_FREE_ATTEMPTS, IP_LOCKOUT_THRESHOLD);
+    entry.blocked_until = (wait > 0).then(|| now + Duration::from_secs(wait));
+}
+
+/// Count a failed password for the account and push out its lock accordingly.
+/// The count goes up in the database, so parallel guesses all add to it.
+pub async fn record_account_failure(
+    users: &UserRepository,
+    user_id: &RecordId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let waits = (0..=ACCOUNT_LOCKOUT_THRESHOLD)
+        .map(|failures| backoff_secs(failures, ACCOUNT_FREE_ATTEMPTS, ACCOUNT_LOCKOUT_THRESHOLD))
+        .collect();
+    let failures = users.record_login_failure(user_id, waits).await?;
+
+    if failures >= ACCOUNT_LOCKOUT_THRESHOLD {
+        println!("Account {} locked after {} failed logins", user_id, failures);
+    }
+    Ok(())
+}
+
+/// Peer address of the request, when the server was started with connect info
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(ClientIp(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_policy() {
+        assert_eq!(backoff_secs(0, 3, 10), 0);
+        assert_eq!(backoff_secs(2, 3, 10), 0);
+        assert_eq!(backoff_secs(3, 3, 10), 1);
+        assert_eq!(backoff_secs(4, 3, 10), 2);
+        assert_eq!(backoff_secs(9, 3, 10), 64);
+        assert_eq!(backoff_secs(10, 3, 10), LOCKOUT_SECS);
+        assert_eq!(backoff_secs(40, 10, 50), LOCKOUT_SECS);
+    }
+}
@@ -0,0 +1,518 @@
+use crate::{
+    db::Db,
+    repo::{RepoError, RepoResult},
+    roles::Role,
+};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use surrealdb::RecordId;
+
+/// How a migration moves the schema or data forward
+pub enum Step {
+    Sql(&'static str),
+    Rust(fn(&Db) -> BoxFuture<'_, RepoResult<()>>),
+}
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub step: Step,
+}
+
+/// Every migration in the order it is applied. Never edit or reorder an entry
+/// once it has shipped; add a new one instead.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        step: Step::Sql(include_str!("0001_initial_schema.surql")),
+    },
+    Migration {
+        version: 2,
+        name: "admin_roles",
+        step: Step::Rust(admin_roles),
+    },
+    Migration {
+        version: 3,
+        name: "game_results",
+        step: Step::Sql(include_str!("0003_game_results.surql")),
+    },
+    Migration {
+        version: 4,
+        name: "game_takebacks",
+        step: Step::Sql(include_str!("0004_game_takebacks.surql")),
+    },
+    Migration {
+        version: 5,
+        name: "game_moves",
+        step: Step::Sql(include_str!("0005_game_moves.surql")),
+    },
+    Migration {
+        version: 6,
+        name: "move_players",
+        step: Step::Rust(move_players),
+    },
+    Migration {
+        version: 7,
+        name: "game_players",
+        step: Step::Sql(include_str!("0007_game_players.surql")),
+    },
+    Migration {
+        version: 8,
+        name: "game_teams",
+        step: Step::Sql(include_str!("0008_game_teams.surql")),
+    },
+    Migration {
+        version: 9,
+        name: "game_stones",
+        step: Step::Rust(game_stones),
+    },
+    Migration {
+        version: 10,
+        name: "game_rules",
+        step: Step::Sql(include_str!("0010_game_rules.surql")),
+    },
+    Migration {
+        version: 11,
+        name: "game_annotations",
+        step: Step::Sql(include_str!("0011_game_annotations.surql")),
+    },
+    Migration {
+        version: 12,
+        name: "puzzles",
+        step: Step::Sql(include_str!("0012_puzzles.surql")),
+    },
+    Migration {
+        version: 13,
+        name: "opening_index",
+        // Games played before the index are added by `tictac-server openings index`
+        step: Step::Sql(include_str!("0013_opening_index.surql")),
+    },
+    Migration {
+        version: 14,
+        name: "game_imports",
+        step: Step::Sql(include_str!("0014_game_imports.surql")),
+    },
+    Migration {
+        version: 15,
+        name: "tournaments",
+        step: Step::Sql(include_str!("0015_tournaments.surql")),
+    },
+    Migration {
+        version: 16,
+        name: "drop_sessions",
+        step: Step::Sql(include_str!("0016_drop_sessions.surql")),
+    },
+];
+
+impl Migration {
+    /// Detects edits to a migration after it was applied
+    fn checksum(&self) -> String {
+        match self.step {
+            Step::Sql(sql) => Sha256::digest(sql.as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            Step::Rust(_) => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+async fn ensure_migration_table(db: &Db) -> RepoResult<()> {
+    db.query(r#"
+        DEFINE TABLE IF NOT EXISTS migration SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS version ON TABLE migration TYPE int;
+        DEFINE FIELD IF NOT EXISTS name ON TABLE migration TYPE string;
+        DEFINE FIELD IF NOT EXISTS checksum ON TABLE migration TYPE string;
+        DEFINE FIELD IF NOT EXISTS applied_at ON TABLE migration TYPE datetime DEFAULT time::now();
+        DEFINE INDEX IF NOT EXISTS version_idx ON TABLE migration COLUMNS version UNIQUE;
+    "#)
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+pub async fn applied(db: &Db) -> RepoResult<Vec<AppliedMigration>> {
+    ensure_migration_table(db).await?;
+
+    let mut result = db
+        .query("SELECT version, name, checksum, applied_at FROM migration ORDER BY version")
+        .await?;
+
+    Ok(result.take(0)?)
+}
+
+/// Migrations that have not been applied yet, in order
+pub async fn pending(db: &Db) -> RepoResult<Vec<&'static Migration>> {
+    let applied = applied(db).await?;
+
+    for record in &applied {
+        match MIGRATIONS.iter().find(|m| m.version == record.version) {
+            None => {
+                return Err(RepoError::Invalid(format!(
+                    "Database has migration {} ({}) which this build does not know about",
+                    record.version, record.name
+                )));
+            }
+            Some(migration) if migration.checksum() != record.checksum => {
+                eprintln!(
+                    "Warning: migration {} ({}) changed after it was applied",
+                    migration.version, migration.name
+                );
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .collect())
+}
+
+/// Apply all pending migrations, or with `dry_run` only report what would run
+pub async fn run(db: &Db, dry_run: bool) -> RepoResult<Vec<&'static Migration>> {
+    let pending = pending(db).await?;
+
+    for migration in &pending {
+        if dry_run {
+            println!("Would apply migration {} ({})", migration.version, migration.name);
+            if let Step::Sql(sql) = migration.step {
+                println!("{}", sql);
+            }
+            continue;
+        }
+
+        println!("Applying migration {} ({})...", migration.version, migration.name);
+        apply(db, migration).await?;
+    }
+
+    Ok(pending)
+}
+
+async fn apply(db: &Db, migration: &Migration) -> RepoResult<()> {
+    let record = RecordId::from(("migration", migration.version as i64));
+
+    match migration.step {
+        // The schema change and its bookkeeping land together or not at all
+        Step::Sql(sql) => {
+            db.query("BEGIN TRANSACTION")
+                .query(sql)
+                .query(RECORD_MIGRATION)
+                .query("COMMIT TRANSACTION")
+                .bind(("record", record))
+                .bind(("version", migration.version))
+                .bind(("name", migration.name))
+                .bind(("checksum", migration.checksum()))
+                .await?
+                .check()?;
+        }
+        Step::Rust(step) => {
+            step(db).await?;
+            db.query(RECORD_MIGRATION)
+                .bind(("record", record))
+                .bind(("version", migration.version))
+                .bind(("name", migration.name))
+                .bind(("checksum", migration.checksum()))
+                .await?
+                .check()?;
+        }
+    }
+
+    Ok(())
+}
+
+const RECORD_MIGRATION: &str = r#"
+    CREATE $record CONTENT {
+        version: $version,
+        name: $name,
+        checksum: $checksum,
+        applied_at: time::now()
+    };
+"#;
+
+/// Admins created before roles existed keep their access, without losing roles
+/// they were granted since
+fn admin_roles(db: &Db) -> BoxFuture<'_, RepoResult<()>> {
+    Box::pin(async move {
+        #[derive(Deserialize)]
+        struct Admin {
+            id: RecordId,
+            #[serde(default)]
+            roles: Option<Vec<Role>>,
+        }
+
+        let mut result = db
+            .query("SELECT id, roles FROM user WHERE is_admin = true")
+            .await?;
+        let admins: Vec<Admin> = result.take(0)?;
+
+        for admin in admins {
+            let mut roles = admin.roles.unwrap_or_default();
+            if roles.contains(&Role::Admin) {
+                continue;
+            }
+            roles.push(Role::Admin);
+            db.query("UPDATE $uid SET roles = $roles")
+                .bind(("uid", admin.id))
+                .bind(("roles", roles))
+                .await?
+                .check()?;
+        }
+
+        Ok(())
+    })
+}
+
+// Record who placed each stone, since a turn can place more than one. Games
+// stored before that alternated one stone at a time.
+fn move_players(db: &Db) -> BoxFuture<'_, RepoResult<()>> {
+    Box::pin(async move {
+        #[derive(Deserialize)]
+        struct Game {
+            id: RecordId,
+            moves: Vec<serde_json::Map<String, serde_json::Value>>,
+        }
+
+        db.query("DEFINE FIELD IF NOT EXISTS moves.*.player ON TABLE game TYPE option<int>")
+            .await?
+            .check()?;
+
+        let mut result = db
+            .query("SELECT id, moves FROM game WHERE moves != NONE")
+            .await?;
+        let games: Vec<Game> = result.take(0)?;
+
+        for game in games {
+            let moves: Vec<_> = game
+                .moves
+                .into_iter()
+                .enumerate()
+                .map(|(ply, mut mv)| {
+                    mv.entry("player").or_insert((ply % 2).into());
+                    mv
+                })
+                .collect();
+            db.query("UPDATE $gid SET moves = $moves")
+                .bind(("gid", game.id))
+                .bind(("moves", moves))
+                .await?
+                .check()?;
+        }
+
+        Ok(())
+    })
+}
+
+// Store the stones left on the board as coordinates, which fit infinite boards
+// too. The old two-dimensional `board` never kept its nested arrays, so the
+// stones are worked out again from the moves.
+fn game_stones(db: &Db) -> BoxFuture<'_, RepoResult<()>> {
+    Box::pin(async move {
+        #[derive(Deserialize)]
+        struct Cell {
+            x: i64,
+            y: i64,
+        }
+        #[derive(Deserialize)]
+        struct Move {
+            x: i64,
+            y: i64,
+            #[serde(default)]
+            player: i64,
+            #[serde(default)]
+            captured: Vec<Cell>,
+        }
+        #[derive(Deserialize)]
+        struct Game {
+            id: RecordId,
+            moves: Vec<Move>,
+        }
+
+        db.query(r#"
+            DEFINE FIELD IF NOT EXISTS stones ON TABLE game TYPE option<array<object>>;
+            DEFINE FIELD IF NOT EXISTS stones.*.x ON TABLE game TYPE int;
+            DEFINE FIELD IF NOT EXISTS stones.*.y ON TABLE game TYPE int;
+            DEFINE FIELD IF NOT EXISTS stones.*.player ON TABLE game TYPE int;
+            DEFINE FIELD OVERWRITE board ON TABLE game TYPE option<array>;
+            UPDATE game UNSET board;
+        "#)
+        .await?
+        .check()?;
+
+        let mut result = db
+            .query("SELECT id, moves FROM game WHERE moves != NONE AND stones = NONE")
+            .await?;
+        let games: Vec<Game> = result.take(0)?;
+
+        for game in games {
+            let mut stones: Vec<(i64, i64, i64)> = Vec::new();
+            for mv in game.moves {
+                stones.retain(|&(x, y, _)| !mv.captured.iter().any(|c| (c.x, c.y) == (x, y)));
+                stones.push((mv.x, mv.y, mv.player));
+            }
+            let stones: Vec<_> = stones
+                .into_iter()
+                .map(|(x, y, player)| serde_json::json!({ "x": x, "y": y, "player": player }))
+                .collect();
+            db.query("UPDATE $gid SET stones = $stones")
+                .bind(("gid", game.id))
+                .bind(("stones", stones))
+                .await?
+                .check()?;
+        }
+
+        Ok(())
+    })
+}
+
+/// `tictac-server migrate [status | up [--dry-run]]`
+pub async fn cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let db = crate::db::connect().await?;
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+
+    match args.first().map(String::as_str) {
+        None | Some("status") => {
+            let applied = applied(&db).await?;
+            for migration in MIGRATIONS {
+                match applied.iter().find(|a| a.version == migration.version) {
+                    Some(record) => println!(
+                        "{:>4}  {:<24} applied {}",
+                        migration.version, migration.name, record.applied_at
+                    ),
+                    None => println!("{:>4}  {:<24} pending", migration.version, migration.name),
+                }
+            }
+        }
+        Some("up") => {
+            let pending = run(&db, dry_run).await?;
+            match (pending.len(), dry_run) {
+                (0, _) => println!("Database is up to date"),
+                (count, true) => println!("{} migrations pending", count),
+                (count, false) => println!("Applied {} migrations", count),
+            }
+        }
+        Some(other) => {
+            return Err(format!("Unknown migrate command {}, expected status or up", other).into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn memory_db() -> Db {
+        let db = surrealdb::engine::any::connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        db
+    }
+
+    #[test]
+    fn test_versions_strictly_increase() {
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].version < pair[1].version));
+    }
+
+    #[tokio::test]
+    async fn test_migrations_apply_once() {
+        let db = memory_db().await;
+
+        assert_eq!(run(&db, true).await.unwrap().len(), MIGRATIONS.len());
+        assert!(applied(&db).await.unwrap().is_empty());
+
+        assert_eq!(run(&db, false).await.unwrap().len(), MIGRATIONS.len());
+        assert!(run(&db, false).await.unwrap().is_empty());
+        assert_eq!(applied(&db).await.unwrap().len(), MIGRATIONS.len());
+    }
+
+    #[tokio::test]
+    async fn test_two_player_games_get_placements() {
+        let db = memory_db().await;
+        run(&db, false).await.unwrap();
+
+        // A game stored before players and placements existed
+        db.query(r#"
+            CREATE game:old CONTENT {
+                player1: user:a, player2: user:b, winner: user:b, board: [], status: 'completed',
+                player1_elo_before: 1200, player2_elo_before: 1200,
+                player1_elo_after: 1184, player2_elo_after: 1216,
+            };
+        "#)
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+        db.query(include_str!("0007_game_players.surql")).await.unwrap().check().unwrap();
+
+        let mut result = db
+            .query("SELECT VALUE [<string> players, placements.rank, placements.elo_after] FROM game:old")
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = result.take(0).unwrap();
+        assert_eq!(rows[0], serde_json::json!(["[user:a, user:b]", [2, 1], [1184, 1216]]));
+
+        db.query(include_str!("0008_game_teams.surql")).await.unwrap().check().unwrap();
+        let mut result = db
+            .query("SELECT VALUE [placements.team, <string> winners] FROM game:old")
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = result.take(0).unwrap();
+        assert_eq!(rows[0], serde_json::json!([[0, 1], "[user:b]"]));
+
+        db.query(include_str!("0010_game_rules.surql")).await.unwrap().check().unwrap();
+        let mut result = db.query("SELECT VALUE rules FROM game:old").await.unwrap();
+        let rows: Vec<serde_json::Value> = result.take(0).unwrap();
+        assert_eq!(rows[0]["players"], 2);
+        assert_eq!(rows[0]["variant"], "freestyle");
+    }
+
+    #[tokio::test]
+    async fn test_stones_come_from_the_moves() {
+        let db = memory_db().await;
+        run(&db, false).await.unwrap();
+
+        // Black's last stone captured the two white ones between
+        db.query(r#"
+            CREATE game:old CONTENT {
+                player1: user:a, player2: user:b, status: 'completed',
+                player1_elo_before: 1200, player2_elo_before: 1200,
+                moves: [
+                    { x: 0, y: 0, player: 0 },
+                    { x: 1, y: 0, player: 1 },
+                    { x: 5, y: 5, player: 0 },
+                    { x: 2, y: 0, player: 1 },
+                    { x: 3, y: 0, player: 0, captured: [{ x: 2, y: 0 }, { x: 1, y: 0 }] },
+                ],
+            };
+        "#)
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+        game_stones(&db).await.unwrap();
+
+        let mut result = db.query("SELECT VALUE stones FROM game:old").await.unwrap();
+        let rows: Vec<serde_json::Value> = result.take(0).unwrap();
+        assert_eq!(
+            rows[0],
+            serde_json::json!([
+                { "x": 0, "y": 0, "player": 0 },
+                { "x": 5, "y": 5, "player": 0 },
+                { "x": 3, "y": 0, "player": 0 },
+            ])
+        );
+    }
+}
@@ -1,6 +1,11 @@
+use crate::api_keys::ApiScope;
+use crate::game::{GameResult, RoomRules};
+use crate::pairing::{Format, PairingResult};
+use crate::roles::Role;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use surrealdb::RecordId;
+use tictac_engine::{Annotation, Move};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -11,6 +16,20 @@ pub struct User {
     pub profile_picture: Option<String>,
     pub elo: i32,
     pub is_admin: bool,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub is_guest: bool,
+    #[serde(default)]
+    pub guest_expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub is_bot: bool,
+    #[serde(default)]
+    pub failed_logins: u32,
+    #[serde(default)]
+    pub locked_until: Option<DateTime<Utc>>,
+    #[serde(default = "default_puzzle_rating")]
+    pub puzzle_rating: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -22,9 +41,17 @@ pub struct UserProfile {
     pub username: String,
     pub profile_picture: Option<String>,
     pub elo: i32,
+    #[serde(default = "default_puzzle_rating")]
+    pub puzzle_rating: i32,
     pub games_played: i32,
     pub games_won: i32,
     pub win_rate: f64,
+    #[serde(default)]
+    pub is_guest: bool,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub is_bot: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,7 +79,14 @@ pub struct Claims {
     pub user_id: String,
     pub email: String,
     pub is_admin: bool,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub is_guest: bool,
     pub exp: usize,
+    // Only present when the request was authenticated with an API key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<ApiScope>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -64,27 +98,161 @@ pub struct LeaderboardEntry {
     pub games_played: i32,
     pub win_rate: f64,
     pub profile_picture: Option<String>,
+    pub is_bot: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GameRecord {
     pub id: Option<RecordId>,
+    // The first two players; `players` and `placements` cover games of more
     pub player1: RecordId,
     pub player2: RecordId,
+    #[serde(default)]
+    pub players: Vec<RecordId>,
+    #[serde(default)]
+    pub placements: Vec<PlacementRecord>,
+    #[serde(default)]
+    pub winners: Vec<RecordId>,  // Everyone credited with the win, a whole team in team games
     pub winner: Option<RecordId>,
-    pub board: Vec<Vec<Option<i32>>>,
+    #[serde(default)]
+    pub stones: Vec<Stone>,  // Left on the board when the game ended
+    #[serde(default)]
+    pub rules: RoomRules,
     pub status: String,
+    #[serde(default = "default_rated")]
+    pub rated: bool,
     pub player1_elo_before: i32,
     pub player2_elo_before: i32,
     pub player1_elo_after: Option<i32>,
     pub player2_elo_after: Option<i32>,
+    #[serde(default)]
+    pub result: Option<GameResult>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub winning_line: Vec<Move>,
+    #[serde(default)]
+    pub takebacks: u32,
+    #[serde(default)]
+    pub plies_taken_back: u32,
+    #[serde(default)]
+    pub moves: Vec<ReplayMove>,
+    #[serde(default)]
+    pub annotations: Option<Vec<Annotation>>,  // Unset until the annotation job has run
+    #[serde(default)]
+    pub accuracy: Vec<f64>,  // Per colour, in percent
+    #[serde(default)]
+    pub tags: Vec<Tag>,  // Header of an imported game, as it was read
+    #[serde(default)]
+    pub imported_by: Option<RecordId>,
     pub started_at: DateTime<Utc>,
     pub ended_at: Option<DateTime<Utc>>,
 }
 
+/// A `[Name "Value"]` header line of a game in text notation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag {
+    pub name: String,
+    pub value: String,
+}
+
+/// Where a player finished a game and how their rating moved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlacementRecord {
+    pub player: RecordId,
+    pub rank: usize,
+    #[serde(default)]
+    pub team: usize,
+    pub elo_before: i32,
+    pub elo_after: i32,
+}
+
+/// One stone of a game replay, with the stones it captured. Consecutive
+/// stones of the same player were placed in one turn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayMove {
+    pub x: isize,
+    pub y: isize,
+    #[serde(default)]
+    pub player: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub captured: Vec<Move>,
+}
+
+/// A stone on the board, by coordinates so that boards of any size and
+/// infinite ones are stored alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stone {
+    pub x: isize,
+    pub y: isize,
+    pub player: usize,
+}
+
+fn default_rated() -> bool {
+    true
+}
+
+fn default_puzzle_rating() -> i32 {
+    1200
+}
+
+/// A position from a finished game where the side to move has exactly one
+/// forced win
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PuzzleRecord {
+    pub id: Option<RecordId>,
+    pub game: RecordId,
+    pub ply: usize,
+    pub rules: RoomRules,
+    pub moves: Vec<Move>,  // Leading to the puzzle position
+    pub to_move: usize,
+    pub solution: Vec<Move>,
+    pub rating: i32,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub solves: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A tournament; its players are in order of registration until it starts
+/// and in seed order after
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TournamentRecord {
+    pub id: Option<RecordId>,
+    pub name: String,
+    pub format: Format,
+    pub rules: RoomRules,
+    pub rounds: Option<usize>,  // Asked for until the start, then the number played
+    #[serde(default)]
+    pub round: usize,  // Round under way, 0 before the start
+    pub status: String,  // "registration", "running" or "finished"
+    #[serde(default)]
+    pub players: Vec<RecordId>,
+    pub created_by: RecordId,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+/// Two players of a tournament round at one board; `white` is unset for a bye
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingRecord {
+    pub id: Option<RecordId>,
+    pub tournament: RecordId,
+    pub round: usize,
+    pub board: usize,
+    pub black: RecordId,
+    pub white: Option<RecordId>,
+    pub game: Option<RecordId>,
+    pub result: Option<PairingResult>,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateProfileRequest {
     pub username: Option<String>,
+    pub is_bot: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -93,4 +261,39 @@ pub struct AdminUpdateUserRequest {
     pub username: Option<String>,
     pub elo: Option<i32>,
     pub is_admin: Option<bool>,
+    pub roles: Option<Vec<Role>>,
+    pub is_bot: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LockedAccount {
+    pub id: String,
+    pub email: String,
+    pub username: String,
+    pub failed_logins: u32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyResponse {
+    // The plaintext key is only ever returned here
+    pub key: String,
+    pub info: ApiKeyInfo,
 }
\ No newline at end of file
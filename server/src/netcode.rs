@@ -1,25 +1,31 @@
-use super::game::GameState;
-use super::game::MoveResult;
+use super::game::{GameEnding, GamePhase, GameResult, GameState, MoveResult, OpeningResult};
 use super::protocol::ClientMessage;
 use super::protocol::ServerMessage;
 use super::room::GameRoom;
 use super::room::GameRooms;
-use crate::auth::{DECODING_KEY};
-use crate::models::Claims;
-use crate::game_db;
+use crate::api_keys::ApiScope;
+use crate::auth::{authenticate, create_jwt};
+use crate::roles::{self, Permission, Role};
+use crate::guest;
+use crate::login_guard::ClientIp;
+use crate::pairing::PairingResult;
+use crate::repo::{pairing_record, record_key, user_record, GameRepository};
+use crate::tournament;
+use crate::state::AppState;
 use axum::debug_handler;
 use axum::extract::{
     ws::{Message, WebSocket},
-    Extension, Path, WebSocketUpgrade,
+    Extension, Path, State, WebSocketUpgrade,
 };
 use axum::response::IntoResponse;
-use axum::http::StatusCode;
-use jsonwebtoken::{decode, Validation};
+use axum::http::{header, StatusCode};
 use futures::{
     sink::SinkExt,
     stream::{SplitSink, SplitStream, StreamExt},
 };
 use std::sync::Arc;
+use surrealdb::RecordId;
+use tictac_engine::{accuracy, annotate, Limits, Position};
 use tokio::sync::broadcast::{Receiver, Sender};
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
@@ -41,6 +47,7 @@ async fn enter_room(
         room_creator: game_room.room_creator.clone().unwrap_or_default(),
         members: game_room.members.clone(),
         player_queue: game_room.player_queue.clone(),
+        rules: game_room.rules.clone(),
     });
     
     if !message.is_empty() && sender.send(Message::Text(message.clone().into())).await.is_err() {
@@ -60,9 +67,13 @@ async fn enter_room(
 fn handle_send(
     mut sender: SplitSink<WebSocket, Message>,
     mut rx: Receiver<String>,
+    player: String,
 ) -> JoinHandle<()> {
     tokio::spawn(async move {
         while let Ok(msg) = rx.recv().await {
+            if !ServerMessage::visible_to(&msg, &player) {
+                continue;
+            }
             if sender.send(Message::Text(msg.clone().into())).await.is_err() {
                 eprintln!("can't response to client with {}", msg);
             }
@@ -75,14 +86,21 @@ fn handle_receive(
     tx: Sender<String>,
     game_room: GameRoom,
     player_id: usize,
+    player: String,
+    roles: Vec<Role>,
+    state: AppState,
 ) -> JoinHandle<()> {
     tokio::spawn(async move {
+        let games = &state.games;
         let mut player_name = DEFAULT_PLAYER_NAME.to_string();
         while let Some(msg) = receiver.next().await {
             if let Err(e) = msg {
                 eprintln!("Error receiving WebSocket message: {:?}", e);
                 continue;
             }
+            if game_room.lock().await.is_banned(&player) {
+                break;
+            }
             if let Message::Text(text) = msg.unwrap() {
                 let message = ClientMessage::from(text.to_string());
                 println!("Server received: {:?}", message);
@@ -96,105 +114,104 @@ fn handle_receive(
                                 {
                                     eprintln!("Server error while sending message: {}", e);
                                 }
+                                if let Some(stage) = ServerMessage::opening_stage(&game_room) {
+                                    let _ = tx.send(String::from(stage));
+                                }
                             }
-                            MoveResult::Win => {
+                            MoveResult::Ended(ending) => {
                                 if let Err(e) =
                                     tx.send(String::from(ServerMessage::from(game_room.clone())))
                                 {
                                     eprintln!("Server error while sending message: {}", e);
                                 }
-                                let winner_name = game_room.members[player_id].clone();
-                                
-                                // Get winner's email from active players
-                                let winner_email = if player_id < game_room.active_players.len() {
-                                    game_room.active_players[player_id].clone()
-                                } else {
-                                    winner_name.clone() // Fallback, though this shouldn't happen
-                                };
-                                
-                                // Update game in database
-                                if let Some(game_id) = &game_room.game_id {
-                                    // Convert board to database format
-                                    let board: Vec<Vec<Option<i32>>> = game_room.board
-                                        .iter()
-                                        .map(|row| row.iter().map(|&cell| cell.map(|p| p as i32)).collect())
-                                        .collect();
-                                    
-                                    if let Err(e) = game_db::update_game_board(game_id, board).await {
-                                        eprintln!("Failed to update game board: {}", e);
-                                    }
-                                    
-                                    if let Err(e) = game_db::end_game(game_id, Some(&winner_email)).await {
-                                        eprintln!("Failed to end game in database: {}", e);
-                                    }
-                                }
-                                
-                                if let Err(e) = tx.send(String::from(ServerMessage::GameEnd {
-                                    winner: winner_email.clone(),
-                                    winner_x: x,
-                                    winner_y: y,
-                                })) {
-                                    eprintln!("Server error while sending message: {}", e);
-                                }
-                                // Move back to preparation phase
-                                game_room.phase = super::game::GamePhase::Ready;
-                                game_room.active_players.clear();
-                                game_room.game_id = None;
-                                
-                                // Send room state update
-                                let _ = tx.send(String::from(ServerMessage::RoomStateUpdate {
-                                    members: game_room.members.clone(),
-                                    player_queue: game_room.player_queue.clone(),
-                                    room_creator: game_room.room_creator.clone().unwrap_or_default(),
-                                }));
+                                end_game(&mut game_room, &state, &tx, ending).await;
                             }
-                            MoveResult::Draw => {
-                                if let Err(e) =
-                                    tx.send(String::from(ServerMessage::from(game_room.clone())))
-                                {
-                                    eprintln!("Server error while sending message: {}", e);
+                            MoveResult::Rejected(code) => {
+                                let rejected = ServerMessage::MoveRejected { player: player.clone(), x, y, code };
+                                let _ = tx.send(String::from(rejected));
+                            }
+                        }
+                    }
+                    ClientMessage::ChooseOpening { choice } => {
+                        let mut game_room = game_room.lock().await;
+                        match game_room.choose_opening(player_id, choice) {
+                            OpeningResult::Continue => {
+                                if let Some(stage) = ServerMessage::opening_stage(&game_room) {
+                                    let _ = tx.send(String::from(stage));
                                 }
-                                
-                                // Update game in database as a draw
-                                if let Some(game_id) = &game_room.game_id {
-                                    // Convert board to database format
-                                    let board: Vec<Vec<Option<i32>>> = game_room.board
-                                        .iter()
-                                        .map(|row| row.iter().map(|&cell| cell.map(|p| p as i32)).collect())
-                                        .collect();
-                                    
-                                    if let Err(e) = game_db::update_game_board(game_id, board).await {
-                                        eprintln!("Failed to update game board: {}", e);
-                                    }
-                                    
-                                    // End game with no winner (draw)
-                                    if let Err(e) = game_db::end_game(game_id, None).await {
-                                        eprintln!("Failed to end game in database: {}", e);
+                            }
+                            OpeningResult::Done { swapped } => {
+                                if let (true, Some(game_id)) = (swapped, &game_room.game_id) {
+                                    if let Err(e) = games.swap_players(game_id).await {
+                                        eprintln!("Failed to swap players in database: {}", e);
                                     }
                                 }
-                                
-                                // Send draw message (reuse GameEnd with empty winner)
-                                if let Err(e) = tx.send(String::from(ServerMessage::GameEnd {
-                                    winner: String::new(),
-                                    winner_x: 0,
-                                    winner_y: 0,
-                                })) {
-                                    eprintln!("Server error while sending message: {}", e);
-                                }
-                                
-                                // Move back to preparation phase
-                                game_room.phase = super::game::GamePhase::Ready;
-                                game_room.active_players.clear();
-                                game_room.game_id = None;
-                                
-                                // Send room state update
-                                let _ = tx.send(String::from(ServerMessage::RoomStateUpdate {
-                                    members: game_room.members.clone(),
-                                    player_queue: game_room.player_queue.clone(),
-                                    room_creator: game_room.room_creator.clone().unwrap_or_default(),
+                                let _ = tx.send(String::from(ServerMessage::ColorsChosen {
+                                    players: game_room.active_players.clone(),
                                 }));
+                                let _ = tx.send(String::from(ServerMessage::from(game_room.clone())));
+                            }
+                            OpeningResult::Rejected => {}
+                        }
+                    }
+                    ClientMessage::Resign => {
+                        let mut game_room = game_room.lock().await;
+                        if let Some(ending) = game_room.resign(player_id) {
+                            end_game(&mut game_room, &state, &tx, ending).await;
+                        }
+                    }
+                    ClientMessage::OfferDraw => {
+                        let mut game_room = game_room.lock().await;
+                        if game_room.offer_draw(player_id) {
+                            let _ = tx.send(String::from(ServerMessage::DrawOffered { by: player.clone() }));
+                        }
+                    }
+                    ClientMessage::AcceptDraw => {
+                        let mut game_room = game_room.lock().await;
+                        if let Some(ending) = game_room.accept_draw(player_id) {
+                            end_game(&mut game_room, &state, &tx, ending).await;
+                        }
+                    }
+                    ClientMessage::DeclineDraw => {
+                        let mut game_room = game_room.lock().await;
+                        if game_room.decline_draw(player_id) {
+                            let _ = tx.send(String::from(ServerMessage::DrawDeclined { by: player.clone() }));
+                        }
+                    }
+                    ClientMessage::Abort => {
+                        let mut game_room = game_room.lock().await;
+                        if let Some(ending) = game_room.abort(player_id) {
+                            end_game(&mut game_room, &state, &tx, ending).await;
+                        }
+                    }
+                    ClientMessage::RequestTakeback => {
+                        let mut game_room = game_room.lock().await;
+                        if game_room.request_takeback(player_id) {
+                            let _ = tx.send(String::from(ServerMessage::TakebackRequested { by: player.clone() }));
+                        }
+                    }
+                    ClientMessage::AcceptTakeback => {
+                        let mut game_room = game_room.lock().await;
+                        if let Some(plies) = game_room.accept_takeback(player_id) {
+                            if let Some(game_id) = &game_room.game_id {
+                                if let Err(e) = games.record_takeback(game_id, plies).await {
+                                    eprintln!("Failed to record takeback: {}", e);
+                                }
                             }
-                            _ => {}
+                            let _ = tx.send(String::from(ServerMessage::TakebackAccepted { plies }));
+                            let _ = tx.send(String::from(ServerMessage::from(game_room.clone())));
+                        }
+                    }
+                    ClientMessage::DeclineTakeback => {
+                        let mut game_room = game_room.lock().await;
+                        if game_room.decline_takeback(player_id) {
+                            let _ = tx.send(String::from(ServerMessage::TakebackDeclined { by: player.clone() }));
+                        }
+                    }
+                    ClientMessage::UpdateRules { rules } => {
+                        let mut game_room = game_room.lock().await;
+                        if game_room.set_rules(player_id, rules) {
+                            let _ = tx.send(String::from(ServerMessage::RulesUpdated { rules: game_room.rules.clone() }));
                         }
                     }
                     ClientMessage::StepUp => {
@@ -205,6 +222,10 @@ fn handle_receive(
                                 player_queue: game_room.player_queue.clone(),
                                 room_creator: game_room.room_creator.clone().unwrap_or_default(),
                             }));
+                            // Tournament games start once both players are ready
+                            if game_room.start_pairing() {
+                                begin_game(&mut game_room, &state, &tx).await;
+                            }
                         }
                     }
                     ClientMessage::StepDown => {
@@ -220,36 +241,18 @@ fn handle_receive(
                     ClientMessage::StartGame => {
                         let mut game_room = game_room.lock().await;
                         if game_room.start_game(player_id) {
-                            // Create game in database
-                            if game_room.active_players.len() == 2 {
-                                match game_db::create_game(&game_room.active_players[0], &game_room.active_players[1]).await {
-                                    Ok(game_id) => {
-                                        game_room.game_id = Some(game_id);
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Failed to create game in database: {}", e);
-                                    }
-                                }
-                            }
-                            
-                            if let Err(e) = tx.send(String::from(ServerMessage::GameStarted {
-                                players: game_room.active_players.clone(),
-                            })) {
-                                eprintln!("Server error while sending message: {}", e);
-                            }
-                            if let Err(e) =
-                                tx.send(String::from(ServerMessage::from(game_room.clone())))
-                            {
-                                eprintln!("Server error while sending message: {}", e);
-                            }
+                            begin_game(&mut game_room, &state, &tx).await;
                         }
                     }
                     ClientMessage::KickMember { member_id } => {
                         let mut game_room = game_room.lock().await;
-                        if game_room.is_room_creator(player_id) && member_id < game_room.members.len() {
+                        let allowed = game_room.is_room_creator(player_id)
+                            || roles::has_permission(&roles, Permission::KickMembers);
+                        if allowed && member_id < game_room.members.len() {
                             let kicked_member = game_room.members[member_id].clone();
                             game_room.remove_member(kicked_member.clone());
                             let _ = tx.send(String::from(ServerMessage::Chat {
+                                id: game_room.next_chat_id(),
                                 who: "system".to_string(),
                                 content: format!("{} was kicked from the room", kicked_member),
                             }));
@@ -260,14 +263,87 @@ fn handle_receive(
                             }));
                         }
                     }
+                    ClientMessage::MuteMember { member_id } => {
+                        if !roles::has_permission(&roles, Permission::MuteMembers) {
+                            continue;
+                        }
+                        let mut game_room = game_room.lock().await;
+                        if let Some(muted_member) = game_room.mute_member(member_id) {
+                            let _ = tx.send(String::from(ServerMessage::Chat {
+                                id: game_room.next_chat_id(),
+                                who: "system".to_string(),
+                                content: format!("{} was muted", muted_member),
+                            }));
+                        }
+                    }
+                    ClientMessage::UnmuteMember { member_id } => {
+                        if !roles::has_permission(&roles, Permission::MuteMembers) {
+                            continue;
+                        }
+                        let mut game_room = game_room.lock().await;
+                        if let Some(unmuted_member) = game_room.unmute_member(member_id) {
+                            let _ = tx.send(String::from(ServerMessage::Chat {
+                                id: game_room.next_chat_id(),
+                                who: "system".to_string(),
+                                content: format!("{} was unmuted", unmuted_member),
+                            }));
+                        }
+                    }
+                    ClientMessage::BanMember { member_id } => {
+                        if !roles::has_permission(&roles, Permission::BanMembers) {
+                            continue;
+                        }
+                        let mut game_room = game_room.lock().await;
+                        if let Some(banned_member) = game_room.ban_member(member_id) {
+                            let _ = tx.send(String::from(ServerMessage::Chat {
+                                id: game_room.next_chat_id(),
+                                who: "system".to_string(),
+                                content: format!("{} was banned from the room", banned_member),
+                            }));
+                            let _ = tx.send(String::from(ServerMessage::RoomStateUpdate {
+                                members: game_room.members.clone(),
+                                player_queue: game_room.player_queue.clone(),
+                                room_creator: game_room.room_creator.clone().unwrap_or_default(),
+                            }));
+                        }
+                    }
+                    ClientMessage::HideChat { chat_id } => {
+                        if !roles::has_permission(&roles, Permission::HideChat) {
+                            continue;
+                        }
+                        let _ = tx.send(String::from(ServerMessage::ChatHidden { id: chat_id }));
+                    }
                     ClientMessage::Chat { content } => {
+                        let mut game_room = game_room.lock().await;
+                        if game_room.is_muted(&player) {
+                            continue;
+                        }
                         if let Err(e) = tx.send(String::from(ServerMessage::Chat {
+                            id: game_room.next_chat_id(),
                             who: player_name.clone(),
                             content,
                         })) {
                             eprintln!("Server error while sending chat message: {}", e);
                         }
                     }
+                    ClientMessage::TeamChat { content } => {
+                        let mut game_room = game_room.lock().await;
+                        if game_room.is_muted(&player) || !game_room.in_game() {
+                            continue;
+                        }
+                        let team = game_room.team_of(&player);
+                        if team.len() < 2 {
+                            continue;
+                        }
+                        if let Err(e) = tx.send(String::from(ServerMessage::TeamChat {
+                            id: game_room.next_chat_id(),
+                            who: player_name.clone(),
+                            team,
+                            content,
+                        })) {
+                            eprintln!("Server error while sending chat message: {}", e);
+                        }
+                    }
                     ClientMessage::Register { name } => {
                         player_name = name;
                         println!("player {} registered with name {}", player_id, player_name);
@@ -277,21 +353,168 @@ fn handle_receive(
             }
         }
         let _ = tx.send(String::from(ServerMessage::Chat {
+            id: game_room.lock().await.next_chat_id(),
             who: "system: ".to_string(),
             content: format!("{} has left the room", player_id),
         }));
     })
 }
 
-async fn handle_ws(socket: WebSocket, player: String, game_room: GameRoom, tx: Sender<String>) {
+/// Store the game that just started and announce it
+async fn begin_game(game_room: &mut GameState, state: &AppState, tx: &Sender<String>) {
+    // Create game in database
+    match state.games.create(&game_room.active_players, &game_room.rules).await {
+        Ok((game_id, rated)) => {
+            game_room.game_id = Some(game_id);
+            game_room.rated = rated;
+        }
+        Err(e) => {
+            eprintln!("Failed to create game in database: {}", e);
+        }
+    }
+
+    if let Err(e) = tx.send(String::from(ServerMessage::GameStarted {
+        players: game_room.active_players.clone(),
+    })) {
+        eprintln!("Server error while sending message: {}", e);
+    }
+    if let Err(e) = tx.send(String::from(ServerMessage::from(game_room.clone()))) {
+        eprintln!("Server error while sending message: {}", e);
+    }
+    if let Some(stage) = ServerMessage::opening_stage(game_room) {
+        let _ = tx.send(String::from(stage));
+    }
+}
+
+/// Record the result, announce it and send the room back to waiting for players
+async fn end_game(
+    game_room: &mut GameState,
+    state: &AppState,
+    tx: &Sender<String>,
+    ending: GameEnding,
+) {
+    let games = &state.games;
+    let ending = ending.ranked(&game_room.active_players, game_room.position.rules().players);
+    if let Some(game_id) = &game_room.game_id {
+        if let Err(e) = games.update_stones(game_id, game_room.stones()).await {
+            eprintln!("Failed to update game board: {}", e);
+        }
+
+        if let Err(e) = games.record_moves(game_id, game_room.replay()).await {
+            eprintln!("Failed to record game moves: {}", e);
+        }
+
+        if let Err(e) = games.end(game_id, &ending).await {
+            eprintln!("Failed to end game in database: {}", e);
+        }
+
+        if state.annotate {
+            spawn_annotation(games.clone(), game_id.clone(), game_room.position.clone());
+        }
+
+        let puzzles = state.puzzles.clone();
+        let openings = state.openings.clone();
+        let game = RecordId::from(("game", game_id.as_str()));
+        let rules = game_room.rules.clone();
+        let moves = game_room.position.history().to_vec();
+        tokio::spawn(async move {
+            if let Err(e) = openings.index_game(&record_key(&game)).await {
+                eprintln!("Failed to index the opening of game {}: {}", game, e);
+            }
+            if let Err(e) = puzzles.mine(&game, &rules, moves).await {
+                eprintln!("Failed to mine game {} for puzzles: {}", game, e);
+            }
+        });
+    }
+
+    // A tournament pairing is settled by any ending but an abort, which
+    // leaves the room open for the game to be played again
+    if let Some(pairing) = game_room.pairing.clone().filter(|_| ending.result != GameResult::Abort) {
+        let result = match ending.winner.as_deref() {
+            None => PairingResult::Draw,
+            Some(winner) if game_room.reserved.first().is_some_and(|black| black == winner) => PairingResult::Black,
+            Some(_) => PairingResult::White,
+        };
+        let game = game_room.game_id.as_ref().map(|id| RecordId::from(("game", id.as_str())));
+        game_room.pairing = None;
+        game_room.reserved.clear();
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = tournament::record_result(&state, &pairing_record(&pairing), result, game).await {
+                eprintln!("Failed to record the result of pairing {}: {}", pairing, e);
+            }
+        });
+    }
+
+    if let Err(e) = tx.send(String::from(ServerMessage::from(ending))) {
+        eprintln!("Server error while sending message: {}", e);
+    }
+
+    // Move back to preparation phase
+    game_room.phase = GamePhase::Ready;
+    game_room.active_players.clear();
+    game_room.game_id = None;
+
+    let _ = tx.send(String::from(ServerMessage::RoomStateUpdate {
+        members: game_room.members.clone(),
+        player_queue: game_room.player_queue.clone(),
+        room_creator: game_room.room_creator.clone().unwrap_or_default(),
+    }));
+}
+
+/// Judge every move of a finished game off the request path and store the
+/// annotations once done
+fn spawn_annotation(games: GameRepository, game_id: String, position: Position) {
+    tokio::spawn(async move {
+        let review = tokio::task::spawn_blocking(move || {
+            let annotations = annotate(*position.rules(), position.history(), Limits::default());
+            let accuracy = accuracy(&annotations, position.rules().players);
+            (annotations, accuracy)
+        })
+        .await;
+        match review {
+            Ok((annotations, accuracy)) => {
+                if let Err(e) = games.annotate(&game_id, annotations, accuracy).await {
+                    eprintln!("Failed to store annotations of game {}: {}", game_id, e);
+                }
+            }
+            Err(e) => eprintln!("Annotating game {} failed: {}", game_id, e),
+        }
+    });
+}
+
+async fn handle_ws(
+    socket: WebSocket,
+    player: String,
+    roles: Vec<Role>,
+    guest_session: Option<ServerMessage>,
+    game_room: GameRoom,
+    tx: Sender<String>,
+    state: AppState,
+) {
     let (mut sender, receiver) = socket.split();
+    // Hand freshly created guests their credentials so they can reconnect as the same account
+    if let Some(session) = guest_session {
+        let message = String::from(session);
+        if sender.send(Message::Text(message.into())).await.is_err() {
+            return;
+        }
+    }
     let player_id = match enter_room(game_room.clone(), player.clone(), &mut sender, &tx).await {
         Some(id) => id,
         None => return,
     };
     let rx = tx.subscribe();
-    let mut send_task = handle_send(sender, rx);
-    let mut recv_task = handle_receive(receiver, tx.clone(), game_room.clone(), player_id);
+    let mut send_task = handle_send(sender, rx, player.clone());
+    let mut recv_task = handle_receive(
+        receiver,
+        tx.clone(),
+        game_room.clone(),
+        player_id,
+        player.clone(),
+        roles,
+        state.clone(),
+    );
     // If any one of the tasks run to completion, we abort the other.
     tokio::select! {
         _ = (&mut send_task) => recv_task.abort(),
@@ -303,51 +526,17 @@ async fn handle_ws(socket: WebSocket, player: String, game_room: GameRoom, tx: S
     let member_name = game_room.members.get(player_id).cloned().unwrap_or_default();
     
     // Check if disconnected player was in an active game
-    if game_room.is_active_player(player_id) && matches!(game_room.phase, super::game::GamePhase::Action) {
-        // Find the other player's email
-        let disconnected_email = game_room.active_players.get(player_id).cloned();
-        let winner_email = game_room.active_players.iter()
-            .find(|&p| disconnected_email.as_ref() != Some(p))
-            .cloned();
-        
-        if let Some(winner) = winner_email {
-            // Update game in database
-            if let Some(game_id) = &game_room.game_id {
-                // Convert board to database format
-                let board: Vec<Vec<Option<i32>>> = game_room.board
-                    .iter()
-                    .map(|row| row.iter().map(|&cell| cell.map(|p| p as i32)).collect())
-                    .collect();
-                
-                if let Err(e) = game_db::update_game_board(game_id, board).await {
-                    eprintln!("Failed to update game board: {}", e);
-                }
-                
-                if let Err(e) = game_db::end_game(game_id, Some(&winner)).await {
-                    eprintln!("Failed to end game in database: {}", e);
-                }
-            }
-            
-            let _ = tx.send(String::from(ServerMessage::GameEnd {
-                winner: winner.clone(),
-                winner_x: 0,
-                winner_y: 0,
-            }));
-            let _ = tx.send(String::from(ServerMessage::Chat {
-                who: "system".to_string(),
-                content: format!("{} wins by default - opponent disconnected", winner),
-            }));
-            game_room.phase = super::game::GamePhase::Ready;
-            game_room.active_players.clear();
-            game_room.game_id = None;
-            
-            // Send room state update after game ends by disconnect
-            let _ = tx.send(String::from(ServerMessage::RoomStateUpdate {
-                members: game_room.members.clone(),
-                player_queue: game_room.player_queue.clone(),
-                room_creator: game_room.room_creator.clone().unwrap_or_default(),
-            }));
-        }
+    if let Some(ending) = game_room.forfeit(player_id) {
+        let content = match &ending.winner {
+            Some(winner) => format!("{} wins by default - opponent disconnected", winner),
+            None => format!("{} forfeits - disconnected", member_name),
+        };
+        let _ = tx.send(String::from(ServerMessage::Chat {
+            id: game_room.next_chat_id(),
+            who: "system".to_string(),
+            content,
+        }));
+        end_game(&mut game_room, &state, &tx, ending).await;
     }
     
     game_room.remove_member(player);
@@ -360,6 +549,7 @@ async fn handle_ws(socket: WebSocket, player: String, game_room: GameRoom, tx: S
     }));
     
     let _ = tx.send(String::from(ServerMessage::Chat {
+        id: game_room.next_chat_id(),
         who: "system".to_string(),
         content: format!("{} has left the room", member_name),
     }));
@@ -367,41 +557,81 @@ async fn handle_ws(socket: WebSocket, player: String, game_room: GameRoom, tx: S
 
 #[derive(serde::Deserialize)]
 pub struct EnterRoomRequest {
-    user: Option<String>,
     token: Option<String>,
 }
 #[debug_handler]
 pub async fn handle_http(
     ws: WebSocketUpgrade,
     Path(room_name): Path<String>,
-    Extension(state): Extension<GameRooms>,
+    State(state): State<AppState>,
+    Extension(game_rooms): Extension<GameRooms>,
     Extension(tx): Extension<Sender<String>>,
+    ClientIp(ip): ClientIp,
     axum::extract::Query(params): axum::extract::Query<EnterRoomRequest>,
 ) -> impl IntoResponse {
-    // Verify JWT token if provided
-    let user = if let Some(token) = params.token {
-        match decode::<Claims>(&token, &DECODING_KEY, &Validation::default()) {
-            Ok(token_data) => token_data.claims.email,
+    // Verify the JWT or API key if provided, otherwise enter as a new guest account
+    let mut guest_session = None;
+    let mut roles = Vec::new();
+    let user = if let Some(token) = params.token.filter(|t| !t.is_empty()) {
+        let claims = match authenticate(&state, &token).await {
+            Ok(claims) if claims.has_scope(ApiScope::Play) => claims,
+            Ok(_) => return StatusCode::FORBIDDEN.into_response(),
             Err(_) => return StatusCode::UNAUTHORIZED.into_response(),
+        };
+        if claims.is_guest {
+            if let Err(e) = guest::touch_guest(&state.users, &user_record(&claims.user_id)).await {
+                eprintln!("Failed to refresh guest expiry: {}", e);
+            }
         }
-    } else if let Some(user) = params.user {
-        user
+        roles = claims.roles();
+        claims.email
     } else {
-        use std::time::{SystemTime, UNIX_EPOCH};
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-        format!("Guest_{}", timestamp % 10000)
+        if let Some(Err(retry_after)) = ip.map(|ip| state.guests.claim(ip)) {
+            return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after.to_string())]).into_response();
+        }
+        let guest = match guest::create_guest(&state.users).await {
+            Ok(guest) => guest,
+            Err(e) => {
+                eprintln!("Failed to create guest user: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        let token = match create_jwt(&guest) {
+            Ok(token) => token,
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+        guest_session = Some(ServerMessage::GuestSession {
+            token,
+            email: guest.email.clone(),
+            username: guest.username,
+        });
+        guest.email
     };
     
-    let game_rooms = state.clone();
-    let tx = tx.clone();
-    let mut game_rooms = game_rooms.lock().await;
+    // Rooms of tournament pairings are set up for their players on first
+    // entry, and only while the pairing is still to be played. The lookup is
+    // made before taking the lock that every room shares.
+    let mut pairing = None;
+    if tournament::is_pairing_room(&room_name) && !game_rooms.lock().await.contains_key(&room_name) {
+        pairing = match tournament::pairing_room(&state, &room_name).await {
+            Ok(Some(room)) => Some(room),
+            Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+            Err(e) => {
+                eprintln!("Failed to look up the pairing of room {}: {}", room_name, e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+    }
     let game_room = game_rooms
+        .lock()
+        .await
         .entry(room_name.clone())
-        .or_insert_with(|| Arc::new(Mutex::new(GameState::new())))
+        .or_insert_with(|| Arc::new(Mutex::new(pairing.unwrap_or_default())))
         .clone();
+
+    if game_room.lock().await.is_banned(&user) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
     
-    ws.on_upgrade(move |ws| handle_ws(ws, user, game_room, tx))
+    ws.on_upgrade(move |ws| handle_ws(ws, user, roles, guest_session, game_room, tx, state))
 }
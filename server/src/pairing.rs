@@ -0,0 +1,490 @@
+//! Who plays whom in a tournament and how the players stand. Players are
+//! known by their seed, 0 being the highest rated at the start; rounds count
+//! from 1 and boards from 1 within a round.
+
+use serde::{Deserialize, Serialize};
+use std::{cell::Cell, collections::HashSet};
+
+// Partial pairings a Swiss round tries before settling for a repeat. Late in
+// a long event so few pairings are left that finding one can take forever.
+const SWISS_BUDGET: usize = 10_000;
+
+/// How a tournament pairs its players from round to round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// Everyone plays everyone once
+    RoundRobin,
+    /// Players on equal scores meet, never twice
+    Swiss,
+    /// The loser of each match is out; drawn games are replayed
+    SingleElimination,
+}
+
+/// Who won a paired game, by the colour they played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairingResult {
+    Black,
+    White,
+    Draw,
+}
+
+/// Two players at one board. A player without an opponent has a bye, which
+/// counts as a win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pairing {
+    pub board: usize,
+    pub black: usize,
+    pub white: Option<usize>,
+    pub result: Option<PairingResult>,
+}
+
+impl Pairing {
+    fn new(board: usize, (black, white): (usize, usize)) -> Self {
+        Self { board, black, white: Some(white), result: None }
+    }
+
+    fn bye(board: usize, player: usize) -> Self {
+        Self { board, black: player, white: None, result: Some(PairingResult::Black) }
+    }
+
+    /// Points the player scored in this game, if they played it and it's over
+    pub fn points(&self, player: usize) -> Option<f64> {
+        let black = match self.result? {
+            PairingResult::Black => 1.0,
+            PairingResult::White => 0.0,
+            PairingResult::Draw => 0.5,
+        };
+        match player {
+            p if p == self.black => Some(black),
+            p if Some(p) == self.white => Some(1.0 - black),
+            _ => None,
+        }
+    }
+
+    /// The other player at the board, `None` for a bye or someone not playing
+    pub fn opponent(&self, player: usize) -> Option<usize> {
+        match player {
+            p if p == self.black => self.white,
+            p if Some(p) == self.white => Some(self.black),
+            _ => None,
+        }
+    }
+}
+
+/// Rounds a tournament of `players` lasts. Swiss tournaments play `requested`
+/// rounds if given, as many as a knockout would otherwise, and never more
+/// than a round robin.
+pub fn rounds(format: Format, players: usize, requested: Option<usize>) -> usize {
+    let round_robin = players - 1 + players % 2;
+    let knockout = players.next_power_of_two().trailing_zeros() as usize;
+    match format {
+        Format::RoundRobin => round_robin,
+        Format::Swiss => requested.unwrap_or(knockout).clamp(1, round_robin),
+        Format::SingleElimination => knockout,
+    }
+}
+
+/// Pairings of the round after `history`, byes listed last and already scored
+pub fn pair_round(format: Format, players: usize, history: &[Vec<Pairing>]) -> Vec<Pairing> {
+    match format {
+        Format::RoundRobin => round_robin(players, history),
+        Format::Swiss => swiss(players, history),
+        Format::SingleElimination => knockout(players, history),
+    }
+}
+
+/// Whether every game of a round is over and, in a knockout, every match has
+/// a winner
+pub fn round_complete(format: Format, round: &[Pairing]) -> bool {
+    let decided = |p: &Pairing| match format {
+        Format::SingleElimination => matches!(p.result, Some(PairingResult::Black | PairingResult::White)),
+        _ => p.result.is_some(),
+    };
+    // A knockout match is decided by its last game, after any replays
+    latest(round).iter().all(decided) && round.iter().all(|p| p.result.is_some())
+}
+
+/// The replay a drawn knockout game needs, colours reversed
+pub fn rematch(format: Format, pairing: &Pairing) -> Option<Pairing> {
+    match (format, pairing.result, pairing.white) {
+        (Format::SingleElimination, Some(PairingResult::Draw), Some(white)) => {
+            Some(Pairing::new(pairing.board, (white, pairing.black)))
+        }
+        _ => None,
+    }
+}
+
+// The last game at each board, in board order
+fn latest(round: &[Pairing]) -> Vec<Pairing> {
+    let mut boards: Vec<Pairing> = Vec::new();
+    for pairing in round {
+        match boards.iter_mut().find(|p| p.board == pairing.board) {
+            Some(last) => *last = *pairing,
+            None => boards.push(*pairing),
+        }
+    }
+    boards.sort_by_key(|p| p.board);
+    boards
+}
+
+// Blacks minus whites so far, and whether the last game was played with black
+fn colour_history(players: usize, history: &[Vec<Pairing>]) -> Vec<(isize, Option<bool>)> {
+    let mut colours = vec![(0, None); players];
+    for pairing in history.iter().flatten() {
+        let Some(white) = pairing.white else {
+            continue;
+        };
+        colours[pairing.black].0 += 1;
+        colours[pairing.black].1 = Some(true);
+        colours[white].0 -= 1;
+        colours[white].1 = Some(false);
+    }
+    colours
+}
+
+// Black and white for two players, `a` ranked higher: black goes to whoever
+// has had it less, then to whoever had white last, then to `a`
+fn seat(a: usize, b: usize, colours: &[(isize, Option<bool>)]) -> (usize, usize) {
+    let ((balance_a, last_a), (balance_b, last_b)) = (colours[a], colours[b]);
+    match (balance_a.cmp(&balance_b), last_a, last_b) {
+        (std::cmp::Ordering::Less, _, _) => (a, b),
+        (std::cmp::Ordering::Greater, _, _) => (b, a),
+        (_, Some(true), Some(false)) => (b, a),
+        _ => (a, b),
+    }
+}
+
+// The circle method: the last player stays put while the rest turn round,
+// an odd player out getting the bye
+fn round_robin(players: usize, history: &[Vec<Pairing>]) -> Vec<Pairing> {
+    let seats = players + players % 2;
+    let turning = seats - 1;
+    let round = history.len() % turning;
+
+    // The first of each pair plays black, which keeps everyone's colours
+    // within one of even; the fixed player alternates
+    let fixed = if round.is_multiple_of(2) { (round, turning) } else { (turning, round) };
+    let pairs = std::iter::once(fixed)
+        .chain((1..seats / 2).map(|i| ((round + i) % turning, (round + turning - i) % turning)));
+    let (games, byes): (Vec<_>, Vec<_>) = pairs.partition(|&(a, b)| a < players && b < players);
+
+    let mut pairings: Vec<Pairing> =
+        games.into_iter().enumerate().map(|(i, pair)| Pairing::new(i + 1, pair)).collect();
+    for (a, b) in byes {
+        pairings.push(Pairing::bye(pairings.len() + 1, a.min(b)));
+    }
+    pairings
+}
+
+// Players by score and then seed, the top half of each score group paired
+// with the bottom half, skipping anyone already met and, where it can,
+// anyone due the same colour. The bye goes to the lowest placed player who
+// hasn't had one.
+fn swiss(players: usize, history: &[Vec<Pairing>]) -> Vec<Pairing> {
+    let table = standings(players, history);
+    let mut order: Vec<usize> = table.iter().map(|s| s.player).collect();
+    let mut points = vec![0.0; players];
+    for standing in &table {
+        points[standing.player] = standing.points;
+    }
+    let met: HashSet<(usize, usize)> = history
+        .iter()
+        .flatten()
+        .filter_map(|p| Some((p.black.min(p.white?), p.black.max(p.white?))))
+        .collect();
+    let had_bye: HashSet<usize> = history.iter().flatten().filter(|p| p.white.is_none()).map(|p| p.black).collect();
+    let colours = colour_history(players, history);
+
+    let bye = (order.len() % 2 == 1).then(|| {
+        let at = order.iter().rposition(|p| !had_bye.contains(p)).unwrap_or(order.len() - 1);
+        order.remove(at)
+    });
+    let swiss = Swiss { points: &points, colours: &colours, met: &met, budget: Cell::new(SWISS_BUDGET) };
+    // Everyone may have met everyone they could be paired with, or the search
+    // ran out of budget looking; repeat then
+    let pairs = swiss.match_up(&order).unwrap_or_else(|| order.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+
+    let mut pairings: Vec<Pairing> = pairs
+        .into_iter()
+        .enumerate()
+        .map(|(i, (a, b))| {
+            let (black, white) = seat(a, b, &colours);
+            // Nobody has a colour yet in the first round; alternate by board
+            match history.is_empty() && i % 2 == 1 {
+                true => Pairing::new(i + 1, (white, black)),
+                false => Pairing::new(i + 1, (black, white)),
+            }
+        })
+        .collect();
+    if let Some(player) = bye {
+        pairings.push(Pairing::bye(pairings.len() + 1, player));
+    }
+    pairings
+}
+
+// What the Swiss pairing of one round goes by
+struct Swiss<'a> {
+    points: &'a [f64],
+    colours: &'a [(isize, Option<bool>)],
+    met: &'a HashSet<(usize, usize)>,
+    budget: Cell<usize>,
+}
+
+impl Swiss<'_> {
+    // Both players are due the same colour
+    fn clash(&self, a: usize, b: usize) -> bool {
+        // 1 for black, -1 for white, 0 for either
+        let due = |p: usize| match self.colours[p] {
+            (0, None) => 0,
+            (0, Some(true)) => -1,
+            (0, Some(false)) => 1,
+            (balance, _) => -balance.signum(),
+        };
+        due(a) != 0 && due(a) == due(b)
+    }
+
+    // Pair off `order` top down, backtracking when the players left can't all
+    // be paired without a repeat, until the budget runs out
+    fn match_up(&self, order: &[usize]) -> Option<Vec<(usize, usize)>> {
+        let Some((&first, rest)) = order.split_first() else {
+            return Some(Vec::new());
+        };
+        self.budget.set(self.budget.get().checked_sub(1)?);
+        // Half way down the first player's score group is their ideal
+        // opponent; after them come the ones below, then the ones above
+        let group = rest.iter().filter(|&&p| self.points[p] == self.points[first]).count();
+        let ideal = group.div_ceil(2).saturating_sub(1);
+        let mut candidates: Vec<usize> = (0..rest.len()).collect();
+        candidates.sort_by_key(|&i| {
+            let other = rest[i];
+            (self.points[other] != self.points[first], self.clash(first, other), (i + rest.len() - ideal) % rest.len())
+        });
+
+        candidates.into_iter().find_map(|i| {
+            let other = rest[i];
+            if self.met.contains(&(first.min(other), first.max(other))) {
+                return None;
+            }
+            let mut remaining = rest.to_vec();
+            remaining.remove(i);
+            let mut pairs = self.match_up(&remaining)?;
+            pairs.insert(0, (first, other));
+            Some(pairs)
+        })
+    }
+}
+
+// Seeds in bracket order, so that the top seeds meet as late as possible:
+// 0 3 1 2 for four players
+fn bracket(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let mirror = order.len() * 2 - 1;
+        order = order.iter().flat_map(|&seed| [seed, mirror - seed]).collect();
+    }
+    order
+}
+
+// The first round by bracket, byes going to the top seeds; later rounds pair
+// the winners of neighbouring matches
+fn knockout(players: usize, history: &[Vec<Pairing>]) -> Vec<Pairing> {
+    let colours = colour_history(players, history);
+    let entrants: Vec<Option<usize>> = match history.last() {
+        None => bracket(players.next_power_of_two()).into_iter().map(|seed| (seed < players).then_some(seed)).collect(),
+        Some(round) => latest(round)
+            .iter()
+            .map(|p| match p.result {
+                Some(PairingResult::Black) => Some(p.black),
+                Some(PairingResult::White) => p.white,
+                _ => None,
+            })
+            .collect(),
+    };
+
+    entrants
+        .chunks(2)
+        .enumerate()
+        .filter_map(|(i, pair)| match pair {
+            [Some(a), Some(b)] => Some(Pairing::new(i + 1, seat(*a.min(b), *a.max(b), &colours))),
+            [Some(a), None] | [None, Some(a)] | [Some(a)] => Some(Pairing::bye(i + 1, *a)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A player's score and tie-breaks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Standing {
+    pub player: usize,
+    pub points: f64,
+    /// Points of every opponent faced
+    pub buchholz: f64,
+    /// Points of the opponents beaten, and half those of the ones drawn
+    pub sonneborn_berger: f64,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+/// Everyone by points, then Buchholz, then Sonneborn-Berger, then seed.
+/// Byes score a point but add nothing to the tie-breaks.
+pub fn standings(players: usize, history: &[Vec<Pairing>]) -> Vec<Standing> {
+    let games: Vec<&Pairing> = history.iter().flatten().filter(|p| p.result.is_some()).collect();
+    let points: Vec<f64> =
+        (0..players).map(|player| games.iter().filter_map(|p| p.points(player)).sum()).collect();
+
+    let mut standings: Vec<Standing> = (0..players)
+        .map(|player| {
+            let mut standing = Standing {
+                player,
+                points: points[player],
+                buchholz: 0.0,
+                sonneborn_berger: 0.0,
+                wins: 0,
+                draws: 0,
+                losses: 0,
+            };
+            for game in &games {
+                let (Some(score), Some(opponent)) = (game.points(player), game.opponent(player)) else {
+                    continue;
+                };
+                standing.buchholz += points[opponent];
+                standing.sonneborn_berger += score * points[opponent];
+                match score {
+                    1.0 => standing.wins += 1,
+                    0.5 => standing.draws += 1,
+                    _ => standing.losses += 1,
+                }
+            }
+            standing
+        })
+        .collect();
+
+    standings.sort_by(|a, b| {
+        b.points
+            .total_cmp(&a.points)
+            .then(b.buchholz.total_cmp(&a.buchholz))
+            .then(b.sonneborn_berger.total_cmp(&a.sonneborn_berger))
+            .then(a.player.cmp(&b.player))
+    });
+    standings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Play a whole tournament, the lower seed winning every game
+    fn play(format: Format, players: usize) -> Vec<Vec<Pairing>> {
+        let mut history: Vec<Vec<Pairing>> = Vec::new();
+        for _ in 0..rounds(format, players, None) {
+            let mut round = pair_round(format, players, &history);
+            for pairing in &mut round {
+                if let Some(white) = pairing.white {
+                    let black_wins = pairing.black < white;
+                    pairing.result = Some(if black_wins { PairingResult::Black } else { PairingResult::White });
+                }
+            }
+            history.push(round);
+        }
+        history
+    }
+
+    #[test]
+    fn test_round_robin_meets_everyone_once() {
+        for players in 2..=9 {
+            let history = play(Format::RoundRobin, players);
+            let mut met = HashSet::new();
+            for pairing in history.iter().flatten() {
+                if let Some(white) = pairing.white {
+                    assert!(met.insert((pairing.black.min(white), pairing.black.max(white))), "{} players", players);
+                }
+            }
+            assert_eq!(met.len(), players * (players - 1) / 2);
+            for (player, (balance, _)) in colour_history(players, &history).into_iter().enumerate() {
+                assert!(balance.abs() <= 1, "player {} of {} has colours {}", player, players, balance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_swiss_never_repeats_and_balances_colours() {
+        let history = play(Format::Swiss, 8);
+        assert_eq!(history.len(), 3);
+        let games: Vec<(usize, usize)> = history.iter().flatten().filter_map(|p| Some((p.black, p.white?))).collect();
+        let unique: HashSet<(usize, usize)> = games.iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+        assert_eq!(unique.len(), games.len());
+        assert!(colour_history(8, &history).iter().all(|(balance, _)| balance.abs() <= 1));
+        // Three wins only for the top seed
+        assert_eq!(standings(8, &history)[0].player, 0);
+        assert_eq!(standings(8, &history)[0].points, 3.0);
+
+        // Odd fields give the bye to a different player each round
+        let history = play(Format::Swiss, 5);
+        let byes: HashSet<usize> = history.iter().flatten().filter(|p| p.white.is_none()).map(|p| p.black).collect();
+        assert_eq!(byes.len(), history.len());
+    }
+
+    #[test]
+    fn test_long_swiss_pairs_every_round_in_time() {
+        // As many rounds as a round robin leaves few pairings to find late on
+        let players = 50;
+        let mut history: Vec<Vec<Pairing>> = Vec::new();
+        for _ in 0..rounds(Format::Swiss, players, Some(players)) {
+            let started = std::time::Instant::now();
+            let mut round = pair_round(Format::Swiss, players, &history);
+            assert!(started.elapsed() < std::time::Duration::from_secs(2), "round {}", history.len() + 1);
+            let seated: HashSet<usize> = round.iter().flat_map(|p| [Some(p.black), p.white]).flatten().collect();
+            assert_eq!(seated.len(), players);
+            for pairing in &mut round {
+                pairing.result = pairing.white.map(|white| match pairing.black < white {
+                    true => PairingResult::Black,
+                    false => PairingResult::White,
+                });
+            }
+            history.push(round);
+        }
+        assert_eq!(history.len(), players - 1);
+    }
+
+    #[test]
+    fn test_knockout_seeds_byes_and_replays() {
+        let first = pair_round(Format::SingleElimination, 6, &[]);
+        let seats: Vec<(usize, Option<usize>)> = first.iter().map(|p| (p.black, p.white)).collect();
+        assert_eq!(seats, vec![(0, None), (3, Some(4)), (1, None), (2, Some(5))]);
+
+        let history = play(Format::SingleElimination, 6);
+        assert_eq!(history.len(), 3);
+        let last = history.last().unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!((last[0].black, last[0].white), (0, Some(1)));
+
+        let drawn = Pairing { result: Some(PairingResult::Draw), ..Pairing::new(2, (3, 4)) };
+        let replay = rematch(Format::SingleElimination, &drawn).unwrap();
+        assert_eq!((replay.board, replay.black, replay.white), (2, 4, Some(3)));
+        assert!(!round_complete(Format::SingleElimination, &[drawn]));
+        let decided = Pairing { result: Some(PairingResult::Black), ..replay };
+        assert!(round_complete(Format::SingleElimination, &[drawn, decided]));
+        assert!(rematch(Format::Swiss, &drawn).is_none());
+    }
+
+    #[test]
+    fn test_tie_breaks() {
+        // 0 beats 1 and draws with 2, 1 beats 3, 2 draws with 3
+        let result = |black, white, result| Pairing { result: Some(result), ..Pairing::new(1, (black, white)) };
+        let history = vec![
+            vec![result(0, 1, PairingResult::Black), result(2, 3, PairingResult::Draw)],
+            vec![result(0, 2, PairingResult::Draw), result(1, 3, PairingResult::Black)],
+        ];
+        let table = standings(4, &history);
+        let order: Vec<usize> = table.iter().map(|s| s.player).collect();
+        // 1 and 2 tie on points and Buchholz; 2 drew with the leader, 1 only beat the last
+        assert_eq!(order, vec![0, 2, 1, 3]);
+        assert_eq!((table[0].points, table[0].buchholz, table[0].sonneborn_berger), (1.5, 2.0, 1.5));
+        assert_eq!((table[1].points, table[1].buchholz, table[1].sonneborn_berger), (1.0, 2.0, 1.0));
+        assert_eq!((table[2].points, table[2].buchholz, table[2].sonneborn_berger), (1.0, 2.0, 0.5));
+        assert_eq!((table[3].draws, table[3].losses), (1, 1));
+    }
+}
@@ -1,6 +1,8 @@
-use super::game::Board;
-use super::game::GameState;
+use super::game::{Board, Bounds};
+use super::game::{GameEnding, GameResult, GameState, MoveRejection, Placement, RoomRules};
+use super::models::Stone;
 use serde::{Deserialize, Serialize};
+use tictac_engine::{Choice, Move};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -11,16 +13,51 @@ pub enum ServerMessage {
         room_creator: String,
         members: Vec<String>,
         player_queue: Vec<String>,
+        rules: RoomRules,
     },
+    RulesUpdated { rules: RoomRules },
     GameStarted { players: Vec<String> },
-    GameState { board: Box<Board>, turn: usize },
-    GameEnd { winner: String, winner_x: usize, winner_y: usize },
+    /// `player` places `remaining` opening stones, or picks one of `choices`
+    OpeningStage { player: String, remaining: usize, choices: Vec<Choice> },
+    /// The opening is over; `players` are in colour order, black first
+    ColorsChosen { players: Vec<String> },
+    /// `turn` places `stones_left` more stones before the turn passes;
+    /// `captures` counts pairs taken by each player; `removed` are the
+    /// stones the last move captured. `stones` and `bounds` describe the
+    /// board whatever its size; `board` is only filled in for bounded boards
+    GameState {
+        board: Board,
+        stones: Vec<Stone>,
+        bounds: Bounds,
+        turn: usize,
+        stones_left: usize,
+        captures: Vec<usize>,
+        removed: Vec<Move>,
+    },
+    /// `player` tried to place a stone at (`x`, `y`) and may not
+    MoveRejected { player: String, x: isize, y: isize, code: MoveRejection },
+    GameEnd {
+        result: GameResult,
+        reason: String,
+        winner: Option<String>,
+        line: Vec<Move>,
+        placements: Vec<Placement>,
+    },
+    DrawOffered { by: String },
+    DrawDeclined { by: String },
+    TakebackRequested { by: String },
+    TakebackAccepted { plies: usize },
+    TakebackDeclined { by: String },
     RoomStateUpdate {
         members: Vec<String>,
         player_queue: Vec<String>,
         room_creator: String,
     },
-    Chat { who: String, content: String },
+    Chat { id: usize, who: String, content: String },
+    /// Chat only delivered to the members of `team`
+    TeamChat { id: usize, who: String, team: Vec<String>, content: String },
+    ChatHidden { id: usize },
+    GuestSession { token: String, email: String, username: String },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,9 +66,24 @@ pub enum ClientMessage {
     StepUp,
     StepDown,
     StartGame,
-    Place { x: usize, y: usize },
+    Place { x: isize, y: isize },
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Abort,
+    RequestTakeback,
+    AcceptTakeback,
+    DeclineTakeback,
+    UpdateRules { rules: RoomRules },
+    ChooseOpening { choice: Choice },
     Chat { content: String },
+    TeamChat { content: String },
     KickMember { member_id: usize },
+    MuteMember { member_id: usize },
+    UnmuteMember { member_id: usize },
+    BanMember { member_id: usize },
+    HideChat { chat_id: usize },
     Register { name: String },
     Unknown,
 }
@@ -46,8 +98,48 @@ impl From<ServerMessage> for String {
 impl From<GameState> for ServerMessage {
     fn from(input: GameState) -> Self {
         Self::GameState {
-            board: Box::new(input.board),
-            turn: input.current_turn,
+            board: input.board(),
+            stones: input.stones(),
+            bounds: input.bounds(),
+            turn: input.current_turn(),
+            stones_left: input.stones_left(),
+            captures: input.captures(),
+            removed: input.last_captured(),
+        }
+    }
+}
+
+// How a serialized team chat message starts, so others can be passed on unparsed
+const TEAM_CHAT_PREFIX: &str = r#"{"type":"TeamChat","#;
+
+impl ServerMessage {
+    /// Whether the serialized `message` may be sent to `member`; everything
+    /// but team chat goes to the whole room
+    pub fn visible_to(message: &str, member: &str) -> bool {
+        if !message.starts_with(TEAM_CHAT_PREFIX) {
+            return true;
+        }
+        match serde_json::from_str(message) {
+            Ok(ServerMessage::TeamChat { team, .. }) => team.iter().any(|m| m == member),
+            _ => false,
+        }
+    }
+
+    /// What the room is waiting for in the opening, if one is being played
+    pub fn opening_stage(state: &GameState) -> Option<Self> {
+        let (player, remaining, choices) = state.opening_turn()?;
+        Some(Self::OpeningStage { player, remaining, choices: choices.to_vec() })
+    }
+}
+
+impl From<GameEnding> for ServerMessage {
+    fn from(input: GameEnding) -> Self {
+        Self::GameEnd {
+            result: input.result,
+            reason: input.reason,
+            winner: input.winner,
+            line: input.line,
+            placements: input.placements,
         }
     }
 }
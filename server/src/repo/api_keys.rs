@@ -0,0 +1,90 @@
+use super::RepoResult;
+use crate::{
+    api_keys::{ApiKey, ApiScope},
+    db::Db,
+};
+use surrealdb::RecordId;
+
+/// Long lived credentials: API keys issued to users
+#[derive(Clone)]
+pub struct ApiKeyRepository {
+    db: Db,
+}
+
+impl ApiKeyRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    pub async fn create_key(
+        &self,
+        user_id: &RecordId,
+        name: &str,
+        prefix: &str,
+        key_hash: &str,
+        scopes: Vec<ApiScope>,
+    ) -> RepoResult<Option<ApiKey>> {
+        let mut result = self.db
+            .query(r#"
+                CREATE api_key CONTENT {
+                    user: $uid,
+                    name: $name,
+                    prefix: $prefix,
+                    key_hash: $key_hash,
+                    scopes: $scopes,
+                    created_at: time::now(),
+                    last_used_at: NONE,
+                    revoked_at: NONE
+                };
+            "#)
+            .bind(("uid", user_id.clone()))
+            .bind(("name", name.to_string()))
+            .bind(("prefix", prefix.to_string()))
+            .bind(("key_hash", key_hash.to_string()))
+            .bind(("scopes", scopes))
+            .await?
+            .check()?;
+
+        let created: Vec<ApiKey> = result.take(0)?;
+        Ok(created.into_iter().next())
+    }
+
+    /// Find an unrevoked key by hash and record that it was used
+    pub async fn use_key(&self, key_hash: &str) -> RepoResult<Option<ApiKey>> {
+        let mut result = self.db
+            .query(r#"
+                UPDATE api_key SET last_used_at = time::now()
+                WHERE key_hash = $key_hash AND revoked_at = NONE
+                RETURN AFTER;
+            "#)
+            .bind(("key_hash", key_hash.to_string()))
+            .await?;
+
+        let keys: Vec<ApiKey> = result.take(0)?;
+        Ok(keys.into_iter().next())
+    }
+
+    pub async fn list_keys(&self, user_id: &RecordId) -> RepoResult<Vec<ApiKey>> {
+        let mut result = self.db
+            .query("SELECT * FROM api_key WHERE user = $uid ORDER BY created_at DESC")
+            .bind(("uid", user_id.clone()))
+            .await?;
+
+        Ok(result.take(0)?)
+    }
+
+    pub async fn revoke_key(&self, user_id: &RecordId, key_id: &str) -> RepoResult<Option<ApiKey>> {
+        let mut result = self.db
+            .query(r#"
+                UPDATE type::thing('api_key', $key_id) SET revoked_at = time::now()
+                WHERE user = $uid AND revoked_at = NONE
+                RETURN AFTER;
+            "#)
+            .bind(("key_id", key_id.to_string()))
+            .bind(("uid", user_id.clone()))
+            .await?;
+
+        let revoked: Vec<ApiKey> = result.take(0)?;
+        Ok(revoked.into_iter().next())
+    }
+}
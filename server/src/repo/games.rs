@@ -0,0 +1,380 @@
+use super::{retryable, total, RepoError, RepoResult, STALE_READ, WRITE_RETRIES};
+use crate::{
+    db::Db,
+    elo::EloRating,
+    game::{GameEnding, GameResult, RoomRules},
+    models::{GameRecord, PlacementRecord, ReplayMove, Stone, Tag, User},
+};
+use surrealdb::RecordId;
+use tictac_engine::{Annotation, Move};
+
+/// A game read from text notation, replayed and ready to be stored
+#[derive(Debug, Clone)]
+pub struct ImportedGame {
+    pub rules: RoomRules,
+    pub moves: Vec<ReplayMove>,
+    pub stones: Vec<Stone>,
+    pub result: Option<GameResult>,
+    pub winning_line: Vec<Move>,
+    pub tags: Vec<Tag>,
+}
+
+/// Game records and the rating changes they cause
+#[derive(Clone)]
+pub struct GameRepository {
+    db: Db,
+}
+
+impl GameRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    /// Start a game record for players in turn order, returning its id and whether it is rated
+    pub async fn create(&self, emails: &[String], rules: &RoomRules) -> RepoResult<(String, bool)> {
+        println!("Creating game between {}", emails.join(", "));
+        let mut result = self.db
+            .query("SELECT * FROM user WHERE email IN $emails")
+            .bind(("emails", emails.to_vec()))
+            .await?;
+
+        let users: Vec<User> = result.take(0)?;
+        let players: Vec<&User> = emails
+            .iter()
+            .filter_map(|email| users.iter().find(|u| u.email == *email))
+            .collect();
+
+        if players.len() < 2 || players.len() != emails.len() {
+            return Err(RepoError::Invalid("Could not find every player".to_string()));
+        }
+
+        // Games involving a guest are casual and never move ratings
+        let rated = players.iter().all(|p| !p.is_guest);
+
+        // Generate a game ID that we control
+        let game_id = format!("{}", chrono::Utc::now().timestamp_nanos_opt().unwrap());
+
+        self.db
+            .query(r#"
+                CREATE type::thing('game', $game_id) CONTENT {
+                    player1: $player1,
+                    player2: $player2,
+                    players: $players,
+                    winner: NONE,
+                    stones: [],
+                    rules: $rules,
+                    status: "active",
+                    rated: $rated,
+                    player1_elo_before: $elo1,
+                    player2_elo_before: $elo2,
+                    player1_elo_after: NONE,
+                    player2_elo_after: NONE,
+                    started_at: time::now(),
+                    ended_at: NONE
+                };
+            "#)
+            .bind(("game_id", game_id.clone()))
+            .bind(("player1", players[0].id.clone()))
+            .bind(("player2", players[1].id.clone()))
+            .bind(("players", players.iter().map(|p| p.id.clone()).collect::<Vec<_>>()))
+            .bind(("rules", rules.clone()))
+            .bind(("elo1", players[0].elo))
+            .bind(("elo2", players[1].elo))
+            .bind(("rated", rated))
+            .await?
+            .check()?;
+
+        println!("Game created successfully with ID: {}", game_id);
+        Ok((game_id, rated))
+    }
+
+    /// Store an imported game under the user who imported it, returning its
+    /// id. Imported games are never rated and stay out of match histories.
+    pub async fn import(&self, user_id: &RecordId, game: ImportedGame) -> RepoResult<String> {
+        let game_id = format!("{}", chrono::Utc::now().timestamp_nanos_opt().unwrap());
+
+        self.db
+            .query(r#"
+                LET $elo = (SELECT VALUE elo FROM ONLY $user);
+                CREATE type::thing('game', $game_id) CONTENT {
+                    player1: $user,
+                    player2: $user,
+                    players: [$user],
+                    winner: NONE,
+                    stones: $stones,
+                    rules: $rules,
+                    status: "imported",
+                    rated: false,
+                    result: $result,
+                    reason: "Imported",
+                    winning_line: $line,
+                    moves: $moves,
+                    tags: $tags,
+                    imported_by: $user,
+                    player1_elo_before: $elo,
+                    player2_elo_before: $elo,
+                    player1_elo_after: NONE,
+                    player2_elo_after: NONE,
+                    started_at: time::now(),
+                    ended_at: time::now()
+                };
+            "#)
+            .bind(("game_id", game_id.clone()))
+            .bind(("user", user_id.clone()))
+            .bind(("stones", game.stones))
+            .bind(("rules", game.rules))
+            .bind(("result", game.result))
+            .bind(("line", game.winning_line))
+            .bind(("moves", game.moves))
+            .bind(("tags", game.tags))
+            .await?
+            .check()?;
+
+        println!("Game {} imported by {}", game_id, user_id);
+        Ok(game_id)
+    }
+
+    pub async fn update_stones(&self, game_id: &str, stones: Vec<Stone>) -> RepoResult<()> {
+        let _: Option<GameRecord> = self.db
+            .update(RecordId::from(("game", game_id)))
+            .merge(serde_json::json!({
+                "stones": stones,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Store the moves played, for replaying the game later
+    pub async fn record_moves(&self, game_id: &str, moves: Vec<ReplayMove>) -> RepoResult<()> {
+        self.db
+            .query("UPDATE type::thing('game', $game_id) SET moves = $moves")
+            .bind(("game_id", game_id.to_string()))
+            .bind(("moves", moves))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    /// Store the review of a finished game's moves
+    pub async fn annotate(&self, game_id: &str, annotations: Vec<Annotation>, accuracy: Vec<f64>) -> RepoResult<()> {
+        self.db
+            .query("UPDATE type::thing('game', $game_id) SET annotations = $annotations, accuracy = $accuracy")
+            .bind(("game_id", game_id.to_string()))
+            .bind(("annotations", annotations))
+            .bind(("accuracy", accuracy))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    /// Exchange the players' sides, once an opening hands black to the second
+    /// player. `players` is in colour order too, so it turns with them.
+    pub async fn swap_players(&self, game_id: &str) -> RepoResult<()> {
+        self.db
+            .query(r#"
+                LET $game = (SELECT * FROM type::thing('game', $game_id))[0];
+                UPDATE $game.id SET
+                    player1 = $game.player2,
+                    player2 = $game.player1,
+                    players = [$game.player2, $game.player1],
+                    player1_elo_before = $game.player2_elo_before,
+                    player2_elo_before = $game.player1_elo_before;
+            "#)
+            .bind(("game_id", game_id.to_string()))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    /// Note that moves were taken back, so the game can be told apart from one played straight
+    pub async fn record_takeback(&self, game_id: &str, plies: usize) -> RepoResult<()> {
+        self.db
+            .query("UPDATE type::thing('game', $game_id) SET takebacks += 1, plies_taken_back += $plies")
+            .bind(("game_id", game_id.to_string()))
+            .bind(("plies", plies))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    /// Record how the game ended and move every player's rating by their placement
+    pub async fn end(&self, game_id: &str, ending: &GameEnding) -> RepoResult<()> {
+        println!("Ending game {} with {:?} ({}), winner: {:?}", game_id, ending.result, ending.reason, ending.winner);
+
+        // The players' other games may end at the same time and move their
+        // ratings under us; the write only lands on the ratings that were read
+        for _ in 0..WRITE_RETRIES {
+            let game = self.find(game_id).await?
+                .ok_or_else(|| RepoError::Invalid(format!("Game {} not found", game_id)))?;
+            let mut result = self.db
+                .query("SELECT * FROM user WHERE id IN $players")
+                .bind(("players", game.players.clone()))
+                .await?;
+            let users: Vec<User> = result.take(0)?;
+            let players: Vec<&User> = game.players
+                .iter()
+                .filter_map(|id| users.iter().find(|u| u.id.as_ref() == Some(id)))
+                .collect();
+
+            // Aborted games are never rated
+            let rated = game.rated && ending.result != GameResult::Abort;
+            let ratings: Vec<i32> = players.iter().map(|p| p.elo).collect();
+            // Players missing from the placements count as a team of their own, tied first
+            let (ranks, teams): (Vec<usize>, Vec<usize>) = players
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let placement = ending.placements.iter().find(|pl| pl.player == p.email);
+                    placement.map_or((1, i), |pl| (pl.rank, pl.team))
+                })
+                .unzip();
+            let new_ratings = if rated {
+                EloRating::default().calculate_for_teams(&ratings, &ranks, &teams)
+            } else {
+                ratings.clone()
+            };
+
+            let placements: Vec<PlacementRecord> = players
+                .iter()
+                .zip(ranks.iter().zip(teams))
+                .zip(ratings.iter().zip(new_ratings))
+                .filter_map(|((player, (&rank, team)), (&elo_before, elo_after))| {
+                    Some(PlacementRecord { player: player.id.clone()?, rank, team, elo_before, elo_after })
+                })
+                .collect();
+            let winner = ending.winner
+                .as_ref()
+                .and_then(|email| players.iter().find(|p| p.email == *email))
+                .and_then(|p| p.id.clone());
+            // Everyone in first place, unless all of them are
+            let winners: Vec<RecordId> = match ranks.iter().all(|&rank| rank == 1) {
+                true => Vec::new(),
+                false => placements.iter().filter(|p| p.rank == 1).map(|p| p.player.clone()).collect(),
+            };
+
+            // The transaction either completes fully or rolls back
+            let written = self.db
+                .query(r#"
+                    BEGIN TRANSACTION;
+
+                    UPDATE type::thing('game', $game_id) SET
+                        status = 'completed',
+                        winner = $winner,
+                        winners = $winners,
+                        rated = $rated,
+                        result = $result,
+                        reason = $reason,
+                        winning_line = $line,
+                        placements = $placements,
+                        player1_elo_after = $placements[0].elo_after,
+                        player2_elo_after = $placements[1].elo_after,
+                        ended_at = time::now();
+
+                    FOR $placement IN $placements {
+                        IF array::len(UPDATE $placement.player SET
+                                elo = $placement.elo_after,
+                                updated_at = time::now()
+                            WHERE elo = $placement.elo_before) = 0 {
+                            THROW $stale;
+                        };
+                    };
+
+                    COMMIT TRANSACTION;
+                "#)
+                .bind(("game_id", game_id.to_string()))
+                .bind(("winner", winner))
+                .bind(("winners", winners))
+                .bind(("rated", rated))
+                .bind(("result", ending.result))
+                .bind(("reason", ending.reason.clone()))
+                .bind(("line", ending.line.clone()))
+                .bind(("placements", placements))
+                .bind(("stale", STALE_READ))
+                .await;
+
+            let error = match written.map(|response| response.check().err()) {
+                Ok(None) => {
+                    println!("Game {} ended successfully!", game_id);
+                    return Ok(());
+                }
+                Ok(Some(e)) | Err(e) => e,
+            };
+            if !retryable(&error) {
+                return Err(error.into());
+            }
+        }
+        Err(RepoError::Invalid(format!("Ratings of the players of game {} kept changing", game_id)))
+    }
+
+    pub async fn find(&self, game_id: &str) -> RepoResult<Option<GameRecord>> {
+        Ok(self.db.select(("game", game_id)).await?)
+    }
+
+    /// Completed games of a player, most recent first
+    pub async fn history(
+        &self,
+        user_id: &RecordId,
+        limit: usize,
+        offset: usize,
+    ) -> RepoResult<Vec<GameRecord>> {
+        let mut result = self.db
+            .query(r#"
+                SELECT * FROM game
+                WHERE $user IN players
+                AND status = 'completed'
+                ORDER BY ended_at DESC
+                LIMIT $limit
+                START $offset
+            "#)
+            .bind(("user", user_id.clone()))
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?;
+
+        Ok(result.take(0)?)
+    }
+
+    pub async fn history_count(&self, user_id: &RecordId) -> RepoResult<u64> {
+        let mut result = self.db
+            .query(r#"
+                SELECT count() AS total FROM game
+                WHERE $user IN players
+                AND status = 'completed'
+                GROUP ALL
+            "#)
+            .bind(("user", user_id.clone()))
+            .await?;
+
+        Ok(total(result.take(0)?))
+    }
+
+    pub async fn count(&self) -> RepoResult<u64> {
+        let mut result = self.db
+            .query("SELECT count() as total FROM game GROUP ALL")
+            .await?;
+
+        Ok(total(result.take(0)?))
+    }
+
+    pub async fn count_completed(&self) -> RepoResult<u64> {
+        let mut result = self.db
+            .query("SELECT count() as total FROM game WHERE status = 'completed' GROUP ALL")
+            .await?;
+
+        Ok(total(result.take(0)?))
+    }
+
+    pub async fn recent(&self, limit: usize) -> RepoResult<Vec<serde_json::Value>> {
+        let mut result = self.db
+            .query("SELECT <string> id AS id, status, started_at FROM game ORDER BY started_at DESC LIMIT $limit")
+            .bind(("limit", limit))
+            .await?;
+
+        Ok(result.take(0)?)
+    }
+}
@@ -0,0 +1,58 @@
+pub mod api_keys;
+pub mod games;
+pub mod openings;
+pub mod puzzles;
+pub mod tournaments;
+pub mod users;
+
+pub use api_keys::ApiKeyRepository;
+pub use games::GameRepository;
+pub use openings::OpeningRepository;
+pub use puzzles::{puzzle_record, PuzzleRepository};
+pub use tournaments::{pairing_record, tournament_record, TournamentRepository};
+pub use users::UserRepository;
+
+use surrealdb::RecordId;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepoError {
+    #[error(transparent)]
+    Database(#[from] surrealdb::Error),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+pub type RepoResult<T> = Result<T, RepoError>;
+
+// Times a guarded write is retried when a value it read has changed since,
+// or a parallel transaction got in the way
+const WRITE_RETRIES: usize = 5;
+// Thrown by a guarded write that found a value changed since it was read
+const STALE_READ: &str = "changed since it was read";
+// End of SurrealDB's message for a transaction that lost to another
+const CONFLICT: &str = "can be retried";
+
+// Whether a guarded write failed only because it raced another write, and
+// may succeed from a fresh read
+fn retryable(error: &surrealdb::Error) -> bool {
+    let message = error.to_string();
+    message.contains(STALE_READ) || message.contains(CONFLICT)
+}
+
+/// Build a user record id from either `user:abc` or a bare `abc`
+pub fn user_record(id: &str) -> RecordId {
+    RecordId::from(("user", id.strip_prefix("user:").unwrap_or(id)))
+}
+
+/// The bare key of a record id, without the table or escaping brackets
+pub fn record_key(id: &RecordId) -> String {
+    String::try_from(id.key().clone()).unwrap_or_else(|_| id.key().to_string())
+}
+
+// Reads the `total` of a `count() ... GROUP ALL` query, which has no rows when nothing matched
+fn total(rows: Vec<serde_json::Value>) -> u64 {
+    rows.first()
+        .and_then(|v| v.get("total"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0)
+}
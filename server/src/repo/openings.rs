@@ -0,0 +1,258 @@
+use super::{RepoError, RepoResult};
+use crate::{
+    db::Db,
+    game::{GameResult, GameState, RoomRules},
+    models::GameRecord,
+};
+use serde::{Deserialize, Serialize};
+use surrealdb::RecordId;
+use tictac_engine::{canonical, transfer, Move, Variant};
+
+/// Plies of every game that go into the opening index
+pub const OPENING_PLIES: usize = 12;
+/// Completed games read at a time by `tictac-server openings index`
+pub const INDEX_PAGE: usize = 200;
+
+/// A move seen after an opening, with how the games went for whoever played it
+#[derive(Debug, Clone, Serialize)]
+pub struct Continuation {
+    #[serde(rename = "move")]
+    pub mv: Move,
+    pub games: u64,
+    pub win_percent: f64,
+    pub draw_percent: f64,
+    pub loss_percent: f64,
+    /// Of the players who made the move, and of their opponents
+    pub average_rating: f64,
+    pub average_opponent_rating: f64,
+}
+
+/// Which games an opening is looked up in
+#[derive(Debug, Clone, Default)]
+pub struct OpeningFilter {
+    pub variant: Option<Variant>,
+    pub infinite: bool,
+    /// Band the average rating of both players falls in
+    pub min_rating: Option<i32>,
+    pub max_rating: Option<i32>,
+}
+
+/// One indexed move: the opening before it and after it, both in the form
+/// shared by all of their mirror images
+#[derive(Debug, Serialize)]
+struct OpeningMove {
+    game: RecordId,
+    ply: usize,
+    position: String,
+    line: String,
+    variant: Variant,
+    infinite: bool,
+    win: u8,
+    draw: u8,
+    rating: i32,
+    opponent_rating: i32,
+    game_rating: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct LineStats {
+    line: String,
+    games: u64,
+    wins: u64,
+    draws: u64,
+    rating: f64,
+    opponent_rating: f64,
+}
+
+/// `x,y;x,y;...`, the index key of a line of moves
+pub fn line_key(moves: &[Move]) -> String {
+    moves.iter().map(|mv| format!("{},{}", mv.x, mv.y)).collect::<Vec<_>>().join(";")
+}
+
+/// Moves back from a `line_key`, or a `x,y;x,y` list given by a client
+pub fn parse_line(key: &str) -> Option<Vec<Move>> {
+    key.split(';')
+        .filter(|cell| !cell.trim().is_empty())
+        .map(|cell| {
+            let (x, y) = cell.split_once(',')?;
+            Some(Move::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Index of the first moves of completed games, for the opening explorer
+#[derive(Clone)]
+pub struct OpeningRepository {
+    db: Db,
+}
+
+impl OpeningRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    /// Index the opening of a completed two-player game, replacing whatever
+    /// was indexed for it before. Returns the moves indexed.
+    pub async fn index(&self, game: &GameRecord) -> RepoResult<usize> {
+        let game_id = game.id.clone().ok_or_else(|| RepoError::Invalid("Game has no id".to_string()))?;
+        let rows = Self::rows(&game_id, game);
+        let count = rows.len();
+
+        self.db
+            .query(r#"
+                BEGIN TRANSACTION;
+                DELETE opening_move WHERE game = $game;
+                IF array::len($rows) > 0 {
+                    INSERT INTO opening_move $rows;
+                };
+                COMMIT TRANSACTION;
+            "#)
+            .bind(("game", game_id))
+            .bind(("rows", rows))
+            .await?
+            .check()?;
+
+        Ok(count)
+    }
+
+    /// Index the game with this id, once it has been recorded as completed
+    pub async fn index_game(&self, game_id: &str) -> RepoResult<usize> {
+        let game: Option<GameRecord> = self.db.select(("game", game_id)).await?;
+        match game {
+            Some(game) => self.index(&game).await,
+            None => Ok(0),
+        }
+    }
+
+    /// Index every completed game, `page` games at a time in order of id,
+    /// returning how many moves went in
+    pub async fn index_completed(&self, page: usize) -> RepoResult<usize> {
+        let mut indexed = 0;
+        let mut after: Option<RecordId> = None;
+        loop {
+            let mut result = self.db
+                .query(r#"
+                    SELECT * FROM game
+                        WHERE status = 'completed' AND ($after = NONE OR id > $after)
+                        ORDER BY id
+                        LIMIT $page;
+                "#)
+                .bind(("after", after))
+                .bind(("page", page))
+                .await?;
+            let games: Vec<GameRecord> = result.take(0)?;
+            for game in &games {
+                indexed += self.index(game).await?;
+            }
+            if games.len() < page {
+                return Ok(indexed);
+            }
+            after = games.last().and_then(|game| game.id.clone());
+        }
+    }
+
+    fn rows(game_id: &RecordId, game: &GameRecord) -> Vec<OpeningMove> {
+        let rules: &RoomRules = &game.rules;
+        let playable = game.status == "completed"
+            && game.result != Some(GameResult::Abort)
+            && rules.players == 2
+            && !rules.teams
+            && game.placements.len() == 2;
+        if !playable {
+            return Vec::new();
+        }
+
+        let board = GameState::rules(rules);
+        let seat = |colour: usize| game.players.get(colour).and_then(|id| game.placements.iter().find(|p| p.player == *id));
+        let moves: Vec<Move> = game.moves.iter().take(OPENING_PLIES).map(|mv| Move::new(mv.x, mv.y)).collect();
+        let game_rating = game.placements.iter().map(|p| p.elo_before).sum::<i32>() / 2;
+        let drawn = game.placements.iter().all(|p| p.rank == 1);
+
+        (0..moves.len())
+            .filter_map(|ply| {
+                let colour = board.player_at(ply);
+                let (mover, opponent) = (seat(colour)?, seat(1 - colour)?);
+                Some(OpeningMove {
+                    game: game_id.clone(),
+                    ply,
+                    position: line_key(&canonical(&board, &moves[..ply])),
+                    line: line_key(&canonical(&board, &moves[..=ply])),
+                    variant: rules.variant,
+                    infinite: rules.infinite,
+                    win: (!drawn && mover.rank == 1) as u8,
+                    draw: drawn as u8,
+                    rating: mover.elo_before,
+                    opponent_rating: opponent.elo_before,
+                    game_rating,
+                })
+            })
+            .collect()
+    }
+
+    /// The moves played after `moves` in indexed games, mirror images of the
+    /// opening included, turned to match `moves` and most played first
+    pub async fn explore(&self, moves: &[Move], filter: &OpeningFilter) -> RepoResult<Vec<Continuation>> {
+        let board = GameState::rules(&RoomRules { infinite: filter.infinite, ..Default::default() });
+        let mut result = self.db
+            .query(r#"
+                SELECT
+                    line,
+                    count() AS games,
+                    math::sum(win) AS wins,
+                    math::sum(draw) AS draws,
+                    math::mean(rating) AS rating,
+                    math::mean(opponent_rating) AS opponent_rating
+                FROM opening_move
+                WHERE position = $position
+                    AND infinite = $infinite
+                    AND ($variant = NONE OR variant = $variant)
+                    AND ($min_rating = NONE OR game_rating >= $min_rating)
+                    AND ($max_rating = NONE OR game_rating <= $max_rating)
+                GROUP BY line;
+            "#)
+            .bind(("position", line_key(&canonical(&board, moves))))
+            .bind(("infinite", filter.infinite))
+            .bind(("variant", filter.variant))
+            .bind(("min_rating", filter.min_rating))
+            .bind(("max_rating", filter.max_rating))
+            .await?
+            .check()?;
+        let lines: Vec<LineStats> = result.take(0)?;
+
+        let percent = |n: u64, of: u64| (n as f64 * 1000.0 / of as f64).round() / 10.0;
+        let mut continuations: Vec<Continuation> = lines
+            .into_iter()
+            .filter_map(|stats| {
+                let line = parse_line(&stats.line)?;
+                let (&next, before) = line.split_last()?;
+                let losses = stats.games - stats.wins - stats.draws;
+                Some(Continuation {
+                    mv: transfer(&board, before, moves, next)?,
+                    games: stats.games,
+                    win_percent: percent(stats.wins, stats.games),
+                    draw_percent: percent(stats.draws, stats.games),
+                    loss_percent: percent(losses, stats.games),
+                    average_rating: stats.rating.round(),
+                    average_opponent_rating: stats.opponent_rating.round(),
+                })
+            })
+            .collect();
+        continuations.sort_by(|a, b| b.games.cmp(&a.games).then((a.mv.y, a.mv.x).cmp(&(b.mv.y, b.mv.x))));
+        Ok(continuations)
+    }
+}
+
+/// `tictac-server openings index`, adding the games finished before the
+/// opening index existed
+pub async fn cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    match args.first().map(String::as_str) {
+        Some("index") => {
+            let db = crate::db::connect().await?;
+            let indexed = OpeningRepository::new(db).index_completed(INDEX_PAGE).await?;
+            println!("Indexed {} opening moves", indexed);
+            Ok(())
+        }
+        Some(other) => Err(format!("Unknown openings command {}, expected index", other).into()),
+        None => Err("Expected an openings command: index".into()),
+    }
+}
@@ -0,0 +1,228 @@
+use super::{retryable, RepoError, RepoResult, STALE_READ, WRITE_RETRIES};
+use crate::{
+    db::Db,
+    elo::EloRating,
+    game::{GameState, RoomRules},
+    models::{GameRecord, PuzzleRecord},
+};
+use surrealdb::RecordId;
+use tictac_engine::{find_puzzle, Limits, Move, Position, Variant};
+
+/// Rating of a puzzle won with one four, and what every further four adds
+const BASE_RATING: i32 = 1200;
+const RATING_PER_FOUR: i32 = 150;
+/// Completed games read at a time when mining them all
+pub const MINE_PAGE: usize = 50;
+
+/// Build a puzzle record id from either `puzzle:abc` or a bare `abc`
+pub fn puzzle_record(id: &str) -> RecordId {
+    RecordId::from(("puzzle", id.strip_prefix("puzzle:").unwrap_or(id)))
+}
+
+/// Puzzles, the attempts at them and the solvers' puzzle ratings
+#[derive(Clone)]
+pub struct PuzzleRepository {
+    db: Db,
+}
+
+impl PuzzleRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    /// Look for a unique forced win in a finished game and store the first
+    /// one as a puzzle, returning whether one was found. Games that already
+    /// gave a puzzle are skipped.
+    pub async fn mine(&self, game_id: &RecordId, rules: &RoomRules, moves: Vec<Move>) -> RepoResult<bool> {
+        // Forced wins are only searched for two colours placing one stone a turn
+        if rules.players != 2 || rules.teams || rules.variant == Variant::Connect6 {
+            return Ok(false);
+        }
+        let mut result = self.db
+            .query("SELECT VALUE id FROM puzzle WHERE game = $game")
+            .bind(("game", game_id.clone()))
+            .await?;
+        let existing: Vec<RecordId> = result.take(0)?;
+        if !existing.is_empty() {
+            return Ok(false);
+        }
+
+        let start = Position::new(GameState::rules(rules));
+        let search_moves = moves.clone();
+        let found = tokio::task::spawn_blocking(move || find_puzzle(&start, &search_moves, Limits::default()))
+            .await
+            .map_err(|e| RepoError::Invalid(format!("Puzzle search failed: {}", e)))?;
+        let Some(puzzle) = found else {
+            return Ok(false);
+        };
+
+        let fours = puzzle.solution.len().div_ceil(2) as i32 - 1;
+        self.db
+            .query(r#"
+                CREATE puzzle CONTENT {
+                    game: $game,
+                    ply: $ply,
+                    rules: $rules,
+                    moves: $moves,
+                    to_move: $to_move,
+                    solution: $solution,
+                    rating: $rating,
+                    created_at: time::now()
+                };
+            "#)
+            .bind(("game", game_id.clone()))
+            .bind(("ply", puzzle.ply))
+            .bind(("rules", rules.clone()))
+            .bind(("moves", moves[..puzzle.ply].to_vec()))
+            .bind(("to_move", GameState::rules(rules).player_at(puzzle.ply)))
+            .bind(("solution", puzzle.solution))
+            .bind(("rating", BASE_RATING + RATING_PER_FOUR * (fours - 1)))
+            .await?
+            .check()?;
+
+        Ok(true)
+    }
+
+    /// Mine every completed game, `page` games at a time in order of id,
+    /// returning how many games were looked at and how many gave a puzzle.
+    /// A game that fails is reported and skipped.
+    pub async fn mine_completed(&self, page: usize) -> RepoResult<(usize, usize)> {
+        let (mut games_seen, mut mined) = (0, 0);
+        let mut after: Option<RecordId> = None;
+        loop {
+            let mut result = self.db
+                .query(r#"
+                    SELECT * FROM game
+                        WHERE status = 'completed' AND ($after = NONE OR id > $after)
+                        ORDER BY id
+                        LIMIT $page;
+                "#)
+                .bind(("after", after))
+                .bind(("page", page))
+                .await?;
+            let games: Vec<GameRecord> = result.take(0)?;
+            games_seen += games.len();
+            for game in &games {
+                let Some(game_id) = &game.id else {
+                    continue;
+                };
+                let moves = game.moves.iter().map(|mv| Move::new(mv.x, mv.y)).collect();
+                match self.mine(game_id, &game.rules, moves).await {
+                    Ok(true) => mined += 1,
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Failed to mine game {} for puzzles: {}", game_id, e),
+                }
+            }
+            if games.len() < page {
+                return Ok((games_seen, mined));
+            }
+            after = games.last().and_then(|game| game.id.clone());
+        }
+    }
+
+    pub async fn find(&self, puzzle_id: &RecordId) -> RepoResult<Option<PuzzleRecord>> {
+        Ok(self.db.select(puzzle_id.clone()).await?)
+    }
+
+    /// The puzzle the user hasn't tried yet that is rated closest to them
+    pub async fn next_for(&self, user_id: &RecordId) -> RepoResult<Option<PuzzleRecord>> {
+        let mut result = self.db
+            .query(r#"
+                LET $rating = (SELECT VALUE puzzle_rating FROM ONLY $user);
+                LET $tried = (SELECT VALUE puzzle FROM puzzle_attempt WHERE user = $user);
+                SELECT *, math::abs(rating - $rating) AS distance FROM puzzle
+                    WHERE id NOTINSIDE $tried
+                    ORDER BY distance, created_at
+                    LIMIT 1;
+            "#)
+            .bind(("user", user_id.clone()))
+            .await?
+            .check()?;
+
+        let puzzles: Vec<PuzzleRecord> = result.take(2)?;
+        Ok(puzzles.into_iter().next())
+    }
+
+    /// Rate the user's first finished attempt at a puzzle against the puzzle
+    /// itself, returning their rating before and after. Later attempts
+    /// aren't rated and return `None`.
+    pub async fn record_attempt(
+        &self,
+        user_id: &RecordId,
+        puzzle: &PuzzleRecord,
+        solved: bool,
+    ) -> RepoResult<Option<(i32, i32)>> {
+        let puzzle_id = puzzle.id.clone().ok_or_else(|| RepoError::Invalid("Puzzle has no id".to_string()))?;
+        // Both ratings may move under us, from the user's other puzzles or
+        // other solvers of this one; the write only lands on what was read
+        for _ in 0..WRITE_RETRIES {
+            let mut result = self.db
+                .query(r#"
+                    SELECT VALUE id FROM puzzle_attempt WHERE user = $user AND puzzle = $puzzle;
+                    SELECT VALUE puzzle_rating FROM ONLY $user;
+                    SELECT VALUE rating FROM ONLY $puzzle;
+                "#)
+                .bind(("user", user_id.clone()))
+                .bind(("puzzle", puzzle_id.clone()))
+                .await?
+                .check()?;
+            let tried: Vec<RecordId> = result.take(0)?;
+            if !tried.is_empty() {
+                return Ok(None);
+            }
+            let before: Option<i32> = result.take(1)?;
+            let before = before.ok_or_else(|| RepoError::Invalid(format!("User {} not found", user_id)))?;
+            let puzzle_before: Option<i32> = result.take(2)?;
+            let puzzle_before = puzzle_before.ok_or_else(|| RepoError::Invalid(format!("Puzzle {} not found", puzzle_id)))?;
+
+            let score = if solved { 1.0 } else { 0.0 };
+            let (after, puzzle_after) = EloRating::default().calculate_new_ratings(before, puzzle_before, score);
+
+            // The unique index on the attempt makes a second submission roll back
+            let written = self.db
+                .query(r#"
+                    BEGIN TRANSACTION;
+
+                    IF array::len(UPDATE $user SET puzzle_rating = $after, updated_at = time::now()
+                        WHERE puzzle_rating = $before) = 0 {
+                        THROW $stale;
+                    };
+                    IF array::len(UPDATE $puzzle SET
+                            rating = $puzzle_after,
+                            attempts += 1,
+                            solves += IF $solved THEN 1 ELSE 0 END
+                        WHERE rating = $puzzle_before) = 0 {
+                        THROW $stale;
+                    };
+                    CREATE puzzle_attempt CONTENT {
+                        user: $user,
+                        puzzle: $puzzle,
+                        solved: $solved,
+                        rating_before: $before,
+                        rating_after: $after,
+                        created_at: time::now()
+                    };
+
+                    COMMIT TRANSACTION;
+                "#)
+                .bind(("user", user_id.clone()))
+                .bind(("puzzle", puzzle_id.clone()))
+                .bind(("solved", solved))
+                .bind(("before", before))
+                .bind(("after", after))
+                .bind(("puzzle_before", puzzle_before))
+                .bind(("puzzle_after", puzzle_after))
+                .bind(("stale", STALE_READ))
+                .await;
+
+            let error = match written.map(|response| response.check().err()) {
+                Ok(None) => return Ok(Some((before, after))),
+                Ok(Some(e)) | Err(e) => e,
+            };
+            if !retryable(&error) {
+                return Err(error.into());
+            }
+        }
+        Err(RepoError::Invalid(format!("Ratings for puzzle {} kept changing", puzzle_id)))
+    }
+}
@@ -0,0 +1,201 @@
+use super::RepoResult;
+use crate::{
+    db::Db,
+    game::RoomRules,
+    models::{PairingRecord, TournamentRecord},
+    pairing::{Format, PairingResult},
+};
+use serde::Serialize;
+use surrealdb::RecordId;
+
+/// Build a tournament record id from either `tournament:abc` or a bare `abc`
+pub fn tournament_record(id: &str) -> RecordId {
+    RecordId::from(("tournament", id.strip_prefix("tournament:").unwrap_or(id)))
+}
+
+/// Build a pairing record id from either `tournament_pairing:abc` or a bare `abc`
+pub fn pairing_record(id: &str) -> RecordId {
+    RecordId::from(("tournament_pairing", id.strip_prefix("tournament_pairing:").unwrap_or(id)))
+}
+
+/// A pairing about to be stored
+#[derive(Debug, Clone, Serialize)]
+pub struct NewPairing {
+    pub tournament: RecordId,
+    pub round: usize,
+    pub board: usize,
+    pub black: RecordId,
+    pub white: Option<RecordId>,
+    pub result: Option<PairingResult>,
+}
+
+/// Tournaments, their registrations and the pairings of their rounds
+#[derive(Clone)]
+pub struct TournamentRepository {
+    db: Db,
+}
+
+impl TournamentRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    /// Open a tournament for registration
+    pub async fn create(
+        &self,
+        name: &str,
+        format: Format,
+        rules: &RoomRules,
+        rounds: Option<usize>,
+        created_by: &RecordId,
+    ) -> RepoResult<Option<TournamentRecord>> {
+        let mut result = self.db
+            .query(r#"
+                CREATE tournament CONTENT {
+                    name: $name,
+                    format: $format,
+                    rules: $rules,
+                    rounds: $rounds,
+                    round: 0,
+                    status: 'registration',
+                    players: [],
+                    created_by: $created_by,
+                    created_at: time::now()
+                };
+            "#)
+            .bind(("name", name.to_string()))
+            .bind(("format", format))
+            .bind(("rules", rules.clone()))
+            .bind(("rounds", rounds))
+            .bind(("created_by", created_by.clone()))
+            .await?
+            .check()?;
+
+        let created: Vec<TournamentRecord> = result.take(0)?;
+        Ok(created.into_iter().next())
+    }
+
+    pub async fn find(&self, tournament_id: &RecordId) -> RepoResult<Option<TournamentRecord>> {
+        Ok(self.db.select(tournament_id.clone()).await?)
+    }
+
+    /// Every tournament, newest first
+    pub async fn list(&self) -> RepoResult<Vec<TournamentRecord>> {
+        let mut result = self.db
+            .query("SELECT * FROM tournament ORDER BY created_at DESC")
+            .await?;
+
+        Ok(result.take(0)?)
+    }
+
+    /// Register the user, returning whether they were added. Only open
+    /// tournaments take players, and nobody twice.
+    pub async fn join(&self, tournament_id: &RecordId, user_id: &RecordId) -> RepoResult<bool> {
+        let mut result = self.db
+            .query(r#"
+                UPDATE $tournament SET players += $user
+                    WHERE status = 'registration' AND $user NOTINSIDE players;
+            "#)
+            .bind(("tournament", tournament_id.clone()))
+            .bind(("user", user_id.clone()))
+            .await?
+            .check()?;
+
+        let updated: Vec<TournamentRecord> = result.take(0)?;
+        Ok(!updated.is_empty())
+    }
+
+    /// Close registration with the players in seed order, returning whether
+    /// it was still open
+    pub async fn start(&self, tournament_id: &RecordId, players: Vec<RecordId>, rounds: usize) -> RepoResult<bool> {
+        let mut result = self.db
+            .query(r#"
+                UPDATE $tournament SET
+                    status = 'running',
+                    players = $players,
+                    rounds = $rounds,
+                    started_at = time::now()
+                WHERE status = 'registration';
+            "#)
+            .bind(("tournament", tournament_id.clone()))
+            .bind(("players", players))
+            .bind(("rounds", rounds))
+            .await?
+            .check()?;
+
+        let updated: Vec<TournamentRecord> = result.take(0)?;
+        Ok(!updated.is_empty())
+    }
+
+    /// Move on from round `from` to the next one, returning whether this call
+    /// did; results arriving together can't pair the same round twice
+    pub async fn advance(&self, tournament_id: &RecordId, from: usize) -> RepoResult<bool> {
+        let mut result = self.db
+            .query("UPDATE $tournament SET round = $next WHERE status = 'running' AND round = $from")
+            .bind(("tournament", tournament_id.clone()))
+            .bind(("from", from))
+            .bind(("next", from + 1))
+            .await?
+            .check()?;
+
+        let updated: Vec<TournamentRecord> = result.take(0)?;
+        Ok(!updated.is_empty())
+    }
+
+    pub async fn finish(&self, tournament_id: &RecordId) -> RepoResult<()> {
+        self.db
+            .query("UPDATE $tournament SET status = 'finished', ended_at = time::now() WHERE status = 'running'")
+            .bind(("tournament", tournament_id.clone()))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    pub async fn add_pairings(&self, pairings: Vec<NewPairing>) -> RepoResult<Vec<PairingRecord>> {
+        if pairings.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut result = self.db
+            .query("INSERT INTO tournament_pairing $pairings")
+            .bind(("pairings", pairings))
+            .await?
+            .check()?;
+
+        Ok(result.take(0)?)
+    }
+
+    /// Every pairing of the tournament, by round and board, replays last
+    pub async fn pairings(&self, tournament_id: &RecordId) -> RepoResult<Vec<PairingRecord>> {
+        let mut result = self.db
+            .query("SELECT * FROM tournament_pairing WHERE tournament = $tournament ORDER BY round, board, created_at")
+            .bind(("tournament", tournament_id.clone()))
+            .await?;
+
+        Ok(result.take(0)?)
+    }
+
+    pub async fn find_pairing(&self, pairing_id: &RecordId) -> RepoResult<Option<PairingRecord>> {
+        Ok(self.db.select(pairing_id.clone()).await?)
+    }
+
+    /// Store the result of a pairing that has none yet, returning the pairing
+    /// if this call did
+    pub async fn record_result(
+        &self,
+        pairing_id: &RecordId,
+        result: PairingResult,
+        game_id: Option<RecordId>,
+    ) -> RepoResult<Option<PairingRecord>> {
+        let mut response = self.db
+            .query("UPDATE $pairing SET result = $result, game = $game WHERE result = NONE")
+            .bind(("pairing", pairing_id.clone()))
+            .bind(("result", result))
+            .bind(("game", game_id))
+            .await?
+            .check()?;
+
+        let updated: Vec<PairingRecord> = response.take(0)?;
+        Ok(updated.into_iter().next())
+    }
+}
@@ -0,0 +1,396 @@
+use super::{total, RepoResult};
+use crate::{
+    db::Db,
+    models::{User, UserProfile},
+    roles::Role,
+};
+use surrealdb::RecordId;
+
+/// Accounts, their ratings and login state
+#[derive(Clone)]
+pub struct UserRepository {
+    db: Db,
+}
+
+impl UserRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(
+        &self,
+        email: &str,
+        username: &str,
+        password_hash: &str,
+        roles: Vec<Role>,
+    ) -> RepoResult<Option<User>> {
+        // Use raw SQL to handle datetime properly
+        let mut result = self.db
+            .query(r#"
+                CREATE user CONTENT {
+                    email: $email,
+                    username: $username,
+                    password_hash: $password_hash,
+                    profile_picture: NONE,
+                    elo: 1200,
+                    is_admin: $is_admin,
+                    roles: $roles,
+                    is_guest: false,
+                    created_at: time::now(),
+                    updated_at: time::now()
+                };
+            "#)
+            .bind(("email", email.to_string()))
+            .bind(("username", username.to_string()))
+            .bind(("password_hash", password_hash.to_string()))
+            .bind(("is_admin", roles.contains(&Role::Admin)))
+            .bind(("roles", roles))
+            .await?
+            .check()?;
+
+        let created: Vec<User> = result.take(0)?;
+        Ok(created.into_iter().next())
+    }
+
+    pub async fn create_guest(
+        &self,
+        email: &str,
+        username: &str,
+        ttl_hours: i64,
+    ) -> RepoResult<Option<User>> {
+        let mut result = self.db
+            .query(r#"
+                CREATE user CONTENT {
+                    email: $email,
+                    username: $username,
+                    password_hash: "",
+                    profile_picture: NONE,
+                    elo: 1200,
+                    is_admin: false,
+                    roles: [],
+                    is_guest: true,
+                    guest_expires_at: time::now() + type::duration($ttl),
+                    created_at: time::now(),
+                    updated_at: time::now()
+                };
+            "#)
+            .bind(("email", email.to_string()))
+            .bind(("username", username.to_string()))
+            .bind(("ttl", format!("{}h", ttl_hours)))
+            .await?
+            .check()?;
+
+        let created: Vec<User> = result.take(0)?;
+        Ok(created.into_iter().next())
+    }
+
+    pub async fn touch_guest(&self, user_id: &RecordId, ttl_hours: i64) -> RepoResult<()> {
+        self.db
+            .query(r#"
+                UPDATE $uid SET
+                    guest_expires_at = time::now() + type::duration($ttl),
+                    updated_at = time::now()
+                WHERE is_guest = true;
+            "#)
+            .bind(("uid", user_id.clone()))
+            .bind(("ttl", format!("{}h", ttl_hours)))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    pub async fn purge_expired_guests(&self) -> RepoResult<usize> {
+        let mut result = self.db
+            .query("DELETE user WHERE is_guest = true AND guest_expires_at < time::now() RETURN BEFORE")
+            .await?;
+
+        let deleted: Vec<User> = result.take(0)?;
+        Ok(deleted.len())
+    }
+
+    /// Turn a guest into a registered account, keeping its record id
+    pub async fn upgrade_guest(
+        &self,
+        user_id: &RecordId,
+        email: &str,
+        username: &str,
+        password_hash: &str,
+    ) -> RepoResult<Option<User>> {
+        let mut result = self.db
+            .query(r#"
+                UPDATE $uid SET
+                    email = $email,
+                    username = $username,
+                    password_hash = $password_hash,
+                    is_guest = false,
+                    guest_expires_at = NONE,
+                    updated_at = time::now()
+                WHERE is_guest = true;
+            "#)
+            .bind(("uid", user_id.clone()))
+            .bind(("email", email.to_string()))
+            .bind(("username", username.to_string()))
+            .bind(("password_hash", password_hash.to_string()))
+            .await?
+            .check()?;
+
+        let updated: Vec<User> = result.take(0)?;
+        Ok(updated.into_iter().next())
+    }
+
+    pub async fn find(&self, user_id: &RecordId) -> RepoResult<Option<User>> {
+        Ok(self.db.select(user_id.clone()).await?)
+    }
+
+    pub async fn find_by_email(&self, email: &str) -> RepoResult<Option<User>> {
+        let mut result = self.db
+            .query("SELECT * FROM user WHERE email = $email")
+            .bind(("email", email.to_string()))
+            .await?;
+
+        let users: Vec<User> = result.take(0)?;
+        Ok(users.into_iter().next())
+    }
+
+    /// Profile with game statistics
+    pub async fn profile(&self, user_id: &RecordId) -> RepoResult<Option<UserProfile>> {
+        let Some(user) = self.find(user_id).await? else {
+            return Ok(None);
+        };
+        let (games_played, games_won) = self.game_stats(user_id).await?;
+
+        let win_rate = if games_played > 0 {
+            (games_won as f64 * 100.0 / games_played as f64).round()
+        } else {
+            0.0
+        };
+
+        Ok(Some(UserProfile {
+            id: user_id.to_string(),
+            email: user.email,
+            username: user.username,
+            profile_picture: user.profile_picture,
+            elo: user.elo,
+            puzzle_rating: user.puzzle_rating,
+            games_played,
+            games_won,
+            win_rate,
+            is_guest: user.is_guest,
+            roles: user.roles,
+            is_bot: user.is_bot,
+        }))
+    }
+
+    /// Completed games played and won
+    pub async fn game_stats(&self, user_id: &RecordId) -> RepoResult<(i32, i32)> {
+        let mut result = self.db
+            .query(r#"
+                SELECT
+                    count() as total,
+                    count($user_id IN winners) as won
+                FROM game
+                WHERE $user_id IN players
+                AND status = 'completed'
+                GROUP ALL
+            "#)
+            .bind(("user_id", user_id.clone()))
+            .await?;
+
+        let counts: Vec<serde_json::Value> = result.take(0)?;
+        let count = |field: &str| {
+            counts.first()
+                .and_then(|v| v.get(field))
+                .and_then(|v| v.as_i64())
+                .unwrap_or(0) as i32
+        };
+
+        Ok((count("total"), count("won")))
+    }
+
+    pub async fn list(
+        &self,
+        search: Option<String>,
+        limit: usize,
+        offset: usize,
+    ) -> RepoResult<Vec<User>> {
+        let mut result = if let Some(search) = search {
+            self.db
+                .query(
+                    r#"
+                    SELECT * FROM user
+                    WHERE email ~ $search OR username ~ $search
+                    ORDER BY created_at DESC
+                    LIMIT $limit
+                    START $offset
+                    "#,
+                )
+                .bind(("search", search))
+                .bind(("limit", limit))
+                .bind(("offset", offset))
+                .await?
+        } else {
+            self.db
+                .query(
+                    r#"
+                    SELECT * FROM user
+                    ORDER BY created_at DESC
+                    LIMIT $limit
+                    START $offset
+                    "#,
+                )
+                .bind(("limit", limit))
+                .bind(("offset", offset))
+                .await?
+        };
+
+        Ok(result.take(0)?)
+    }
+
+    pub async fn all(&self) -> RepoResult<Vec<User>> {
+        let mut result = self.db.query("SELECT * FROM user").await?;
+        Ok(result.take(0)?)
+    }
+
+    pub async fn count(&self) -> RepoResult<u64> {
+        let mut result = self.db
+            .query("SELECT count() as total FROM user GROUP ALL")
+            .await?;
+
+        Ok(total(result.take(0)?))
+    }
+
+    /// Merge the given fields into the user and stamp `updated_at`
+    pub async fn merge(
+        &self,
+        user_id: &RecordId,
+        data: serde_json::Value,
+    ) -> RepoResult<Option<User>> {
+        let mut result = self.db
+            .query("UPDATE $uid MERGE $data; UPDATE $uid SET updated_at = time::now();")
+            .bind(("uid", user_id.clone()))
+            .bind(("data", data))
+            .await?
+            .check()?;
+
+        let updated: Vec<User> = result.take(1)?;
+        Ok(updated.into_iter().next())
+    }
+
+    pub async fn delete(&self, user_id: &RecordId) -> RepoResult<Option<User>> {
+        Ok(self.db.delete(user_id.clone()).await?)
+    }
+
+    /// Count a failed login and lock the account for `waits[failures]`
+    /// seconds (the last entry past the end), both in one statement so
+    /// concurrent failures can't overwrite each other's count
+    pub async fn record_login_failure(&self, user_id: &RecordId, waits: Vec<u64>) -> RepoResult<u32> {
+        let mut result = self.db
+            .query(r#"
+                UPDATE $uid SET
+                    failed_logins += 1,
+                    locked_until = IF $waits[math::min([failed_logins, $last])] > 0
+                        THEN time::now() + duration::from::secs($waits[math::min([failed_logins, $last])])
+                        ELSE NONE
+                    END
+                RETURN AFTER;
+            "#)
+            .bind(("uid", user_id.clone()))
+            .bind(("last", waits.len().saturating_sub(1)))
+            .bind(("waits", waits))
+            .await?
+            .check()?;
+
+        let updated: Vec<User> = result.take(0)?;
+        Ok(updated.first().map_or(0, |user| user.failed_logins))
+    }
+
+    pub async fn reset_lockout(&self, user_id: &RecordId) -> RepoResult<()> {
+        self.db
+            .query("UPDATE $uid SET failed_logins = 0, locked_until = NONE;")
+            .bind(("uid", user_id.clone()))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    /// Accounts that currently have failed logins or an active lock
+    pub async fn locked(&self) -> RepoResult<Vec<User>> {
+        let mut result = self.db
+            .query(r#"
+                SELECT * FROM user
+                WHERE failed_logins > 0 OR locked_until > time::now()
+                ORDER BY locked_until DESC
+            "#)
+            .await?;
+
+        Ok(result.take(0)?)
+    }
+
+    /// Number of players on the leaderboard (those who have completed rated games)
+    pub async fn ranked_count(&self) -> RepoResult<u64> {
+        let mut result = self.db
+            .query(RANKED_PLAYERS)
+            .query("SELECT count() AS total FROM user WHERE id IN $ranked GROUP ALL")
+            .await?
+            .check()?;
+
+        Ok(total(result.take(1)?))
+    }
+
+    /// Ranked players with game stats, best rating first
+    pub async fn leaderboard(&self, limit: usize, offset: usize) -> RepoResult<Vec<serde_json::Value>> {
+        let mut result = self.db
+            .query(RANKED_PLAYERS)
+            .query(
+                r#"
+                SELECT
+                    <string> id AS id,
+                    username,
+                    elo,
+                    profile_picture,
+                    is_bot,
+                    array::len((SELECT id FROM game WHERE $parent.id IN players AND status = 'completed')) AS games_count,
+                    array::len((SELECT id FROM game WHERE $parent.id IN winners AND status = 'completed')) AS wins_count
+                FROM user
+                WHERE id IN $ranked
+                ORDER BY elo DESC
+                LIMIT $limit
+                START $offset
+                "#,
+            )
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?
+            .check()?;
+
+        Ok(result.take(1)?)
+    }
+
+    /// 1-based leaderboard position, if the player is ranked
+    pub async fn rank(&self, user_id: &RecordId) -> RepoResult<Option<u64>> {
+        let mut result = self.db
+            .query(RANKED_PLAYERS)
+            .query(
+                r#"
+                SELECT VALUE array::len((SELECT id FROM user WHERE id IN $ranked AND elo > $parent.elo)) + 1
+                FROM $user_id
+                WHERE id IN $ranked
+                "#,
+            )
+            .bind(("user_id", user_id.clone()))
+            .await?
+            .check()?;
+
+        let ranks: Vec<u64> = result.take(1)?;
+        Ok(ranks.into_iter().next())
+    }
+}
+
+// Everyone who has completed a rated game, bound as `$ranked`
+const RANKED_PLAYERS: &str = r#"
+    LET $ranked = array::distinct(array::flatten(
+        (SELECT VALUE players FROM game WHERE status = 'completed' AND rated != false)
+    ));
+"#;
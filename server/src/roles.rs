@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Moderator,
+    TournamentDirector,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    MuteMembers,
+    KickMembers,
+    BanMembers,
+    HideChat,
+    ManageTournaments,
+    ManageUsers,
+    ManageRoles,
+    ViewStats,
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Moderator => &[
+                Permission::MuteMembers,
+                Permission::KickMembers,
+                Permission::BanMembers,
+                Permission::HideChat,
+            ],
+            Role::TournamentDirector => &[Permission::ManageTournaments],
+            Role::Admin => &[
+                Permission::MuteMembers,
+                Permission::KickMembers,
+                Permission::BanMembers,
+                Permission::HideChat,
+                Permission::ManageTournaments,
+                Permission::ManageUsers,
+                Permission::ManageRoles,
+                Permission::ViewStats,
+            ],
+        }
+    }
+}
+
+pub fn has_permission(roles: &[Role], permission: Permission) -> bool {
+    roles.iter().any(|role| role.permissions().contains(&permission))
+}
@@ -0,0 +1,36 @@
+use crate::{
+    db::Db,
+    guest::GuestThrottle,
+    repo::{
+        ApiKeyRepository, GameRepository, OpeningRepository, PuzzleRepository, TournamentRepository, UserRepository,
+    },
+};
+
+/// Shared handler state, holding the storage repositories
+#[derive(Clone)]
+pub struct AppState {
+    pub users: UserRepository,
+    pub games: GameRepository,
+    pub puzzles: PuzzleRepository,
+    pub openings: OpeningRepository,
+    pub api_keys: ApiKeyRepository,
+    pub tournaments: TournamentRepository,
+    pub guests: GuestThrottle,
+    /// Review every finished game move by move in the background
+    pub annotate: bool,
+}
+
+impl AppState {
+    pub fn new(db: Db) -> Self {
+        Self {
+            users: UserRepository::new(db.clone()),
+            games: GameRepository::new(db.clone()),
+            puzzles: PuzzleRepository::new(db.clone()),
+            openings: OpeningRepository::new(db.clone()),
+            api_keys: ApiKeyRepository::new(db.clone()),
+            tournaments: TournamentRepository::new(db),
+            guests: GuestThrottle::default(),
+            annotate: false,
+        }
+    }
+}
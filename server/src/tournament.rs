@@ -0,0 +1,181 @@
+//! Running tournaments: seeding, pairing each round once the last one is
+//! over and the rooms the pairings are played in.
+
+use crate::{
+    game::GameState,
+    models::{PairingRecord, TournamentRecord},
+    pairing::{self, Pairing, PairingResult},
+    repo::{pairing_record, record_key, tournaments::NewPairing, RepoError, RepoResult},
+    state::AppState,
+};
+use surrealdb::RecordId;
+
+const ROOM_PREFIX: &str = "tournament-";
+
+/// Name of the room a pairing is played in
+pub fn room_name(pairing_id: &RecordId) -> String {
+    format!("{}{}", ROOM_PREFIX, record_key(pairing_id))
+}
+
+// Seed of a player, by their place in the tournament's seeded players
+fn seed(tournament: &TournamentRecord, player: &RecordId) -> Option<usize> {
+    tournament.players.iter().position(|p| p == player)
+}
+
+/// The pairings of every round so far, by seed
+pub fn history(tournament: &TournamentRecord, pairings: &[PairingRecord]) -> Vec<Vec<Pairing>> {
+    let mut rounds = vec![Vec::new(); tournament.round];
+    for record in pairings {
+        let (Some(round), Some(black)) = (rounds.get_mut(record.round.wrapping_sub(1)), seed(tournament, &record.black))
+        else {
+            continue;
+        };
+        let white = record.white.as_ref().and_then(|white| seed(tournament, white));
+        round.push(Pairing { board: record.board, black, white, result: record.result });
+    }
+    rounds
+}
+
+/// Seed the registered players by rating, highest first, and pair the first
+/// round. Returns whether the tournament was waiting to start.
+pub async fn start(state: &AppState, tournament: &TournamentRecord) -> RepoResult<bool> {
+    let tournament_id = tournament.id.clone().ok_or_else(|| RepoError::Invalid("Tournament has no id".to_string()))?;
+    let mut players = Vec::new();
+    for (registered, id) in tournament.players.iter().enumerate() {
+        let elo = state.users.find(id).await?.map_or(0, |user| user.elo);
+        players.push((id.clone(), elo, registered));
+    }
+    // Equal ratings keep the order of registration
+    players.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)));
+    let players: Vec<RecordId> = players.into_iter().map(|(id, _, _)| id).collect();
+
+    let rounds = pairing::rounds(tournament.format, players.len(), tournament.rounds);
+    if !state.tournaments.start(&tournament_id, players.clone(), rounds).await? {
+        return Ok(false);
+    }
+    println!("Tournament {} started with {} players over {} rounds", tournament_id, players.len(), rounds);
+
+    let started = TournamentRecord { players, rounds: Some(rounds), status: "running".to_string(), ..tournament.clone() };
+    open_round(state, &started, &[]).await?;
+    Ok(true)
+}
+
+// Pair the round after `history`, unless another result got there first
+async fn open_round(state: &AppState, tournament: &TournamentRecord, history: &[Vec<Pairing>]) -> RepoResult<()> {
+    let tournament_id = tournament.id.clone().ok_or_else(|| RepoError::Invalid("Tournament has no id".to_string()))?;
+    if !state.tournaments.advance(&tournament_id, history.len()).await? {
+        return Ok(());
+    }
+    let round = history.len() + 1;
+    // A long Swiss event can take a while to pair, so keep it off the runtime
+    let (format, players, played) = (tournament.format, tournament.players.len(), history.to_vec());
+    let pairings = tokio::task::spawn_blocking(move || pairing::pair_round(format, players, &played))
+        .await
+        .map_err(|e| RepoError::Invalid(format!("Pairing round {} failed: {}", round, e)))?;
+    println!("Tournament {} pairs round {} on {} boards", tournament_id, round, pairings.len());
+    store(state, tournament, round, pairings).await?;
+    Ok(())
+}
+
+async fn store(
+    state: &AppState,
+    tournament: &TournamentRecord,
+    round: usize,
+    pairings: Vec<Pairing>,
+) -> RepoResult<Vec<PairingRecord>> {
+    let tournament_id = tournament.id.clone().ok_or_else(|| RepoError::Invalid("Tournament has no id".to_string()))?;
+    let player = |seed: usize| tournament.players.get(seed).cloned();
+    let rows = pairings
+        .into_iter()
+        .filter_map(|p| {
+            Some(NewPairing {
+                tournament: tournament_id.clone(),
+                round,
+                board: p.board,
+                black: player(p.black)?,
+                white: p.white.and_then(player),
+                result: p.result,
+            })
+        })
+        .collect();
+    state.tournaments.add_pairings(rows).await
+}
+
+/// Record how a pairing's game went, replaying a drawn knockout game and
+/// pairing the next round, or finishing the tournament, once every game of
+/// the round is over. Results already in are left alone.
+pub async fn record_result(
+    state: &AppState,
+    pairing_id: &RecordId,
+    result: PairingResult,
+    game_id: Option<RecordId>,
+) -> RepoResult<()> {
+    let Some(record) = state.tournaments.record_result(pairing_id, result, game_id).await? else {
+        return Ok(());
+    };
+    let tournament = state
+        .tournaments
+        .find(&record.tournament)
+        .await?
+        .ok_or_else(|| RepoError::Invalid(format!("Tournament {} not found", record.tournament)))?;
+    let pairings = state.tournaments.pairings(&record.tournament).await?;
+    let history = history(&tournament, &pairings);
+    let Some(round) = history.get(record.round.wrapping_sub(1)) else {
+        return Ok(());
+    };
+    // Only the round under way moves the tournament on
+    if record.round != tournament.round {
+        return Ok(());
+    }
+
+    let played = Pairing {
+        board: record.board,
+        black: seed(&tournament, &record.black).unwrap_or_default(),
+        white: record.white.as_ref().and_then(|white| seed(&tournament, white)),
+        result: Some(result),
+    };
+    if let Some(replay) = pairing::rematch(tournament.format, &played) {
+        store(state, &tournament, record.round, vec![replay]).await?;
+        return Ok(());
+    }
+    if !pairing::round_complete(tournament.format, round) {
+        return Ok(());
+    }
+
+    if record.round >= tournament.rounds.unwrap_or_default() {
+        println!("Tournament {} finished", record.tournament);
+        state.tournaments.finish(&record.tournament).await?;
+    } else {
+        open_round(state, &tournament, &history).await?;
+    }
+    Ok(())
+}
+
+/// Whether `room` is named for a tournament pairing
+pub fn is_pairing_room(room: &str) -> bool {
+    room.starts_with(ROOM_PREFIX)
+}
+
+/// The room for a tournament pairing still to be played, set up for its two
+/// players, if `room` names one
+pub async fn pairing_room(state: &AppState, room: &str) -> RepoResult<Option<GameState>> {
+    let Some(key) = room.strip_prefix(ROOM_PREFIX) else {
+        return Ok(None);
+    };
+    let pairing_id = pairing_record(key);
+    let Some(pairing) = state.tournaments.find_pairing(&pairing_id).await? else {
+        return Ok(None);
+    };
+    let (None, Some(white)) = (pairing.result, &pairing.white) else {
+        return Ok(None);
+    };
+    let Some(tournament) = state.tournaments.find(&pairing.tournament).await? else {
+        return Ok(None);
+    };
+
+    let (black, white) = (state.users.find(&pairing.black).await?, state.users.find(white).await?);
+    let (Some(black), Some(white)) = (black, white) else {
+        return Ok(None);
+    };
+    Ok(Some(GameState::for_pairing(record_key(&pairing_id), tournament.rules, vec![black.email, white.email])))
+}
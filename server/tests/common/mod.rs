@@ -0,0 +1,220 @@
+//! Boots the full server against an in-memory database for end-to-end tests
+#![allow(dead_code)]
+
+use futures::{SinkExt, StreamExt};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tictac_server::{
+    db,
+    protocol::{ClientMessage, ServerMessage},
+    state::AppState,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+// Long enough for a debug build, short enough that a missing message fails fast
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub state: AppState,
+    client: reqwest::Client,
+}
+
+#[derive(Clone)]
+pub struct TestUser {
+    pub id: String,
+    pub email: String,
+    pub username: String,
+    pub token: String,
+}
+
+impl TestServer {
+    pub async fn spawn() -> Self {
+        Self::spawn_with(|_| {}).await
+    }
+
+    /// A server whose state is adjusted first, such as to leave out the
+    /// background work that finished games start
+    pub async fn spawn_with(configure: impl FnOnce(&mut AppState)) -> Self {
+        let db = db::prepare(db::connect_to("mem://").await.unwrap())
+            .await
+            .unwrap();
+        let mut state = AppState::new(db);
+        state.annotate = true;
+        configure(&mut state);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = tictac_server::app(state.clone());
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+
+        Self {
+            addr,
+            state,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn get(&self, path: &str, token: Option<&str>) -> (StatusCode, Value) {
+        let mut request = self.client.get(format!("http://{}/api{}", self.addr, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        (status, response.json().await.unwrap_or(Value::Null))
+    }
+
+    /// A GET whose response is text rather than JSON
+    pub async fn get_text(&self, path: &str, token: Option<&str>) -> (StatusCode, String) {
+        let mut request = self.client.get(format!("http://{}/api{}", self.addr, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        (status, response.text().await.unwrap_or_default())
+    }
+
+    pub async fn post(&self, path: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+        let mut request = self
+            .client
+            .post(format!("http://{}/api{}", self.addr, path))
+            .json(&body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        (status, response.json().await.unwrap_or(Value::Null))
+    }
+
+    pub async fn put(&self, path: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+        let mut request = self
+            .client
+            .put(format!("http://{}/api{}", self.addr, path))
+            .json(&body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        (status, response.json().await.unwrap_or(Value::Null))
+    }
+
+    pub async fn delete(&self, path: &str, token: Option<&str>) -> (StatusCode, Value) {
+        let mut request = self.client.delete(format!("http://{}/api{}", self.addr, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        (status, response.json().await.unwrap_or(Value::Null))
+    }
+
+    /// Log in again, picking up any roles granted since
+    pub async fn login(&self, email: &str, password: &str) -> String {
+        let (status, body) = self.post("/auth/login", None, json!({ "email": email, "password": password })).await;
+        assert_eq!(status, StatusCode::OK, "login {} failed: {}", email, body);
+        body["token"].as_str().unwrap().to_string()
+    }
+
+    pub async fn register(&self, name: &str) -> TestUser {
+        let email = format!("{}@example.com", name);
+        let (status, body) = self
+            .post(
+                "/auth/register",
+                None,
+                json!({ "email": email, "username": name, "password": "password123" }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "register {} failed: {}", name, body);
+
+        TestUser {
+            id: body["user"]["id"].as_str().unwrap().to_string(),
+            email,
+            username: name.to_string(),
+            token: body["token"].as_str().unwrap().to_string(),
+        }
+    }
+
+    /// Open `/ws/{room}`, as a guest when no token is given
+    pub async fn connect(&self, room: &str, token: Option<&str>) -> TestSocket {
+        let url = match token {
+            Some(token) => format!("ws://{}/ws/{}?token={}", self.addr, room, token),
+            None => format!("ws://{}/ws/{}", self.addr, room),
+        };
+        let (stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        TestSocket { stream }
+    }
+
+    /// Connect and wait until the server has placed us in the room
+    pub async fn join(&self, room: &str, user: &TestUser) -> (TestSocket, usize) {
+        let mut socket = self.connect(room, Some(&user.token)).await;
+        let joined = socket
+            .expect(|m| matches!(m, ServerMessage::JoinedRoom { .. }))
+            .await;
+        let ServerMessage::JoinedRoom { your_id, .. } = joined else {
+            unreachable!()
+        };
+        (socket, your_id)
+    }
+}
+
+pub struct TestSocket {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl TestSocket {
+    pub async fn send(&mut self, message: ClientMessage) {
+        let text = serde_json::to_string(&message).unwrap();
+        self.stream.send(Message::Text(text.into())).await.unwrap();
+    }
+
+    /// Next server message, failing the test if none arrives in time
+    pub async fn recv(&mut self) -> ServerMessage {
+        loop {
+            let frame = tokio::time::timeout(RECV_TIMEOUT, self.stream.next())
+                .await
+                .expect("timed out waiting for a server message")
+                .expect("socket closed")
+                .unwrap();
+            if let Message::Text(text) = frame {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    /// Skip messages until one matches, returning it
+    pub async fn expect(&mut self, matches: impl Fn(&ServerMessage) -> bool) -> ServerMessage {
+        loop {
+            let message = self.recv().await;
+            if matches(&message) {
+                return message;
+            }
+        }
+    }
+
+    /// Assert the next messages match the predicates in order, ignoring others between them
+    pub async fn expect_sequence(&mut self, sequence: &[&dyn Fn(&ServerMessage) -> bool]) -> Vec<ServerMessage> {
+        let mut seen = Vec::new();
+        for matches in sequence {
+            seen.push(self.expect(matches).await);
+        }
+        seen
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.stream.close(None).await;
+    }
+}
@@ -0,0 +1,1273 @@
+mod common;
+
+use common::{TestServer, TestSocket, TestUser};
+use reqwest::StatusCode;
+use serde_json::json;
+use surrealdb::RecordId;
+use tictac_engine::{Choice, Move, OpeningRule, Variant};
+use tictac_server::{
+    game::{GameEnding, GameResult, MoveRejection, RoomRules, TakebackRule},
+    protocol::{ClientMessage, ServerMessage},
+    repo::{record_key, user_record},
+};
+
+/// Both players join `room`, queue up and the first one starts the game
+async fn start_game(server: &TestServer, room: &str, first: &TestUser, second: &TestUser) -> (TestSocket, TestSocket) {
+    let (mut a, _) = server.join(room, first).await;
+    let (mut b, _) = server.join(room, second).await;
+
+    a.send(ClientMessage::StepUp).await;
+    b.send(ClientMessage::StepUp).await;
+    a.expect(|m| matches!(m, ServerMessage::RoomStateUpdate { player_queue, .. } if player_queue.len() == 2))
+        .await;
+
+    a.send(ClientMessage::StartGame).await;
+    for socket in [&mut a, &mut b] {
+        let started = socket.expect(|m| matches!(m, ServerMessage::GameStarted { .. })).await;
+        let ServerMessage::GameStarted { players } = started else { unreachable!() };
+        assert_eq!(players, vec![first.email.clone(), second.email.clone()]);
+    }
+
+    (a, b)
+}
+
+/// Place a stone and wait until the move is reflected in the broadcast board
+async fn place(socket: &mut TestSocket, x: isize, y: isize) {
+    socket.send(ClientMessage::Place { x, y }).await;
+    socket
+        .expect(|m| matches!(m, ServerMessage::GameState { stones, .. } if stones.iter().any(|s| (s.x, s.y) == (x, y))))
+        .await;
+}
+
+#[tokio::test]
+async fn test_win_is_broadcast_and_recorded() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, mut b) = start_game(&server, "win", &alice, &bob).await;
+
+    for i in 0..4 {
+        place(&mut a, i, 0).await;
+        place(&mut b, i, 1).await;
+    }
+    a.send(ClientMessage::Place { x: 4, y: 0 }).await;
+
+    // Final board, then the result, then the room goes back to waiting
+    for socket in [&mut a, &mut b] {
+        let seen = socket
+            .expect_sequence(&[
+                &|m| matches!(m, ServerMessage::GameState { board, .. } if board[4][0].is_some()),
+                &|m| matches!(m, ServerMessage::GameEnd { .. }),
+                &|m| matches!(m, ServerMessage::RoomStateUpdate { .. }),
+            ])
+            .await;
+        let ServerMessage::GameEnd { result, winner, line, .. } = &seen[1] else { unreachable!() };
+        assert_eq!(*result, GameResult::Win);
+        assert_eq!(winner.as_deref(), Some(alice.email.as_str()));
+        assert_eq!(*line, (0..5).map(|x| Move::new(x, 0)).collect::<Vec<_>>());
+    }
+
+    let alice_id = user_record(&alice.id);
+    let games = server.state.games.history(&alice_id, 10, 0).await.unwrap();
+    assert_eq!(games.len(), 1);
+    assert_eq!(games[0].status, "completed");
+    assert_eq!(games[0].winner.as_ref(), Some(&alice_id));
+    assert_eq!(games[0].result, Some(GameResult::Win));
+    assert_eq!(games[0].winning_line.len(), 5);
+
+    let alice_row = server.state.users.find(&alice_id).await.unwrap().unwrap();
+    let bob_row = server.state.users.find(&user_record(&bob.id)).await.unwrap().unwrap();
+    assert_eq!((alice_row.elo, bob_row.elo), (1216, 1184));
+
+    let (status, history) = server.get("/games/history", Some(&bob.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(history["total"], 1);
+    assert_eq!(history["matches"][0]["result"], "loss");
+}
+
+#[tokio::test]
+async fn test_moves_out_of_turn_are_ignored() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, mut b) = start_game(&server, "turns", &alice, &bob).await;
+
+    // Bob moves first, which is rejected without touching the board
+    b.send(ClientMessage::Place { x: 5, y: 5 }).await;
+    let rejected = b.expect(|m| matches!(m, ServerMessage::MoveRejected { .. })).await;
+    let ServerMessage::MoveRejected { player, code, .. } = rejected else { unreachable!() };
+    assert_eq!((player, code), (bob.email.clone(), MoveRejection::NotYourTurn));
+    place(&mut a, 0, 0).await;
+
+    let state = b
+        .expect(|m| matches!(m, ServerMessage::GameState { board, .. } if board[0][0].is_some()))
+        .await;
+    let ServerMessage::GameState { board, turn, .. } = state else { unreachable!() };
+    assert!(board[5][5].is_none());
+    assert_eq!(board[0][0], Some(0));
+    assert_eq!(turn, 1);
+}
+
+#[tokio::test]
+async fn test_disconnect_forfeits_the_game() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, b) = start_game(&server, "leave", &alice, &bob).await;
+
+    b.close().await;
+
+    let end = a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let ServerMessage::GameEnd { result, winner, line, .. } = end else { unreachable!() };
+    assert_eq!(result, GameResult::Forfeit);
+    assert_eq!(winner, Some(alice.email.clone()));
+    assert!(line.is_empty());
+
+    let games = server.state.games.history(&user_record(&bob.id), 10, 0).await.unwrap();
+    assert_eq!(games[0].winner.as_ref(), Some(&user_record(&alice.id)));
+    assert_eq!(games[0].result, Some(GameResult::Forfeit));
+    assert_eq!(games[0].reason.as_deref(), Some("opponent disconnected"));
+}
+
+#[tokio::test]
+async fn test_resignation_is_a_rated_loss() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, mut b) = start_game(&server, "resign", &alice, &bob).await;
+
+    place(&mut a, 0, 0).await;
+    b.send(ClientMessage::Resign).await;
+
+    let end = a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let ServerMessage::GameEnd { result, winner, .. } = end else { unreachable!() };
+    assert_eq!(result, GameResult::Resign);
+    assert_eq!(winner, Some(alice.email.clone()));
+
+    let bob_row = server.state.users.find(&user_record(&bob.id)).await.unwrap().unwrap();
+    assert_eq!(bob_row.elo, 1184);
+}
+
+#[tokio::test]
+async fn test_draw_by_agreement() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, mut b) = start_game(&server, "agree", &alice, &bob).await;
+
+    // Only the opponent can answer an offer
+    a.send(ClientMessage::OfferDraw).await;
+    b.expect(|m| matches!(m, ServerMessage::DrawOffered { by } if *by == alice.email)).await;
+    a.send(ClientMessage::AcceptDraw).await;
+    b.send(ClientMessage::DeclineDraw).await;
+    b.expect(|m| matches!(m, ServerMessage::DrawDeclined { .. })).await;
+
+    b.send(ClientMessage::OfferDraw).await;
+    a.expect(|m| matches!(m, ServerMessage::DrawOffered { .. })).await;
+    a.send(ClientMessage::AcceptDraw).await;
+
+    let end = b.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let ServerMessage::GameEnd { result, winner, .. } = end else { unreachable!() };
+    assert_eq!((result, winner), (GameResult::Draw, None));
+
+    let games = server.state.games.history(&user_record(&alice.id), 10, 0).await.unwrap();
+    assert_eq!(games[0].result, Some(GameResult::Draw));
+    assert!(games[0].rated);
+}
+
+#[tokio::test]
+async fn test_abort_only_before_the_game_gets_going() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    let (mut a, mut b) = start_game(&server, "abort-late", &alice, &bob).await;
+    place(&mut a, 0, 0).await;
+    place(&mut b, 1, 1).await;
+    a.send(ClientMessage::Abort).await;
+    // Still in play: the next move goes through
+    place(&mut a, 2, 2).await;
+    a.send(ClientMessage::Resign).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    drop((a, b));
+
+    let carol = server.register("carol").await;
+    let dave = server.register("dave").await;
+    let (mut c, _d) = start_game(&server, "abort-early", &carol, &dave).await;
+    place(&mut c, 0, 0).await;
+    c.send(ClientMessage::Abort).await;
+
+    let end = c.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let ServerMessage::GameEnd { result, winner, .. } = end else { unreachable!() };
+    assert_eq!((result, winner), (GameResult::Abort, None));
+
+    let games = server.state.games.history(&user_record(&carol.id), 10, 0).await.unwrap();
+    assert_eq!(games[0].result, Some(GameResult::Abort));
+    assert!(!games[0].rated);
+    let carol_row = server.state.users.find(&user_record(&carol.id)).await.unwrap().unwrap();
+    assert_eq!(carol_row.elo, 1200);
+}
+
+#[tokio::test]
+async fn test_takeback_reverts_to_the_requesters_turn() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    // Games between registered players are rated, so the room has to opt in
+    let (mut a, _) = server.join("takeback", &alice).await;
+    a.send(ClientMessage::UpdateRules { rules: RoomRules { takebacks: TakebackRule::Allowed, ..Default::default() } }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    a.close().await;
+
+    let (mut a, mut b) = start_game(&server, "takeback", &alice, &bob).await;
+    place(&mut a, 0, 0).await;
+    place(&mut b, 1, 1).await;
+
+    a.send(ClientMessage::RequestTakeback).await;
+    b.expect(|m| matches!(m, ServerMessage::TakebackRequested { by } if *by == alice.email)).await;
+    b.send(ClientMessage::AcceptTakeback).await;
+
+    a.expect(|m| matches!(m, ServerMessage::TakebackAccepted { plies: 2 })).await;
+    let state = a.expect(|m| matches!(m, ServerMessage::GameState { .. })).await;
+    let ServerMessage::GameState { board, turn, .. } = state else { unreachable!() };
+    assert!(board.iter().flatten().all(Option::is_none));
+    assert_eq!(turn, 0);
+
+    place(&mut a, 5, 5).await;
+    a.send(ClientMessage::Resign).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let games = server.state.games.history(&user_record(&alice.id), 10, 0).await.unwrap();
+    assert_eq!((games[0].takebacks, games[0].plies_taken_back), (1, 2));
+}
+
+#[tokio::test]
+async fn test_rated_games_refuse_takebacks_by_default() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, mut b) = start_game(&server, "no-takeback", &alice, &bob).await;
+
+    place(&mut a, 0, 0).await;
+    a.send(ClientMessage::RequestTakeback).await;
+    b.send(ClientMessage::AcceptTakeback).await;
+    b.send(ClientMessage::Place { x: 1, y: 1 }).await;
+
+    let state = b
+        .expect(|m| matches!(m, ServerMessage::GameState { board, .. } if board[1][1].is_some()))
+        .await;
+    let ServerMessage::GameState { board, .. } = state else { unreachable!() };
+    assert_eq!(board[0][0], Some(0));
+}
+
+#[tokio::test]
+async fn test_pie_rule_lets_the_responder_take_black() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    let (mut a, _) = server.join("pie", &alice).await;
+    let rules = RoomRules { opening: Some(OpeningRule::Pie), ..Default::default() };
+    a.send(ClientMessage::UpdateRules { rules }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    a.close().await;
+
+    let (mut a, mut b) = start_game(&server, "pie", &alice, &bob).await;
+    b.expect(|m| matches!(m, ServerMessage::OpeningStage { player, remaining: 1, .. } if *player == alice.email))
+        .await;
+
+    // Bob can't place Alice's opening stone, nor choose before it is down
+    b.send(ClientMessage::Place { x: 0, y: 0 }).await;
+    b.send(ClientMessage::ChooseOpening { choice: Choice::Black }).await;
+    place(&mut a, 4, 4).await;
+
+    let stage = b.expect(|m| matches!(m, ServerMessage::OpeningStage { remaining: 0, .. })).await;
+    let ServerMessage::OpeningStage { player, choices, .. } = stage else { unreachable!() };
+    assert_eq!(player, bob.email);
+    assert_eq!(choices, vec![Choice::Black, Choice::White]);
+
+    b.send(ClientMessage::ChooseOpening { choice: Choice::Black }).await;
+    let chosen = a.expect(|m| matches!(m, ServerMessage::ColorsChosen { .. })).await;
+    let ServerMessage::ColorsChosen { players } = chosen else { unreachable!() };
+    assert_eq!(players, vec![bob.email.clone(), alice.email.clone()]);
+
+    // Black's stone is Bob's now, so Alice plays white next
+    let state = a.expect(|m| matches!(m, ServerMessage::GameState { .. })).await;
+    let ServerMessage::GameState { board, turn, .. } = state else { unreachable!() };
+    assert_eq!((board[0][0], board[4][4], turn), (None, Some(0), 1));
+    place(&mut a, 5, 5).await;
+
+    b.send(ClientMessage::Resign).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let games = server.state.games.history(&user_record(&bob.id), 10, 0).await.unwrap();
+    assert_eq!(games[0].player1, user_record(&bob.id));
+    assert_eq!(games[0].winner.as_ref(), Some(&user_record(&alice.id)));
+
+    // Seats follow the colours, so Bob's loss is recorded against him as black
+    assert_eq!(games[0].players, vec![user_record(&bob.id), user_record(&alice.id)]);
+    assert!(games[0].player1_elo_after.unwrap() < 1200);
+    assert!(games[0].player2_elo_after.unwrap() > 1200);
+    let path = format!("/games/{}/export", record_key(games[0].id.as_ref().unwrap()));
+    let (_, text) = server.get_text(&path, Some(&alice.token)).await;
+    assert!(text.contains("[Black \"bob\"]") && text.contains("[White \"alice\"]"), "{}", text);
+    assert!(text.contains("[Result \"0-1\"]"), "{}", text);
+}
+
+#[tokio::test]
+async fn test_renju_rejects_a_black_double_three() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    let (mut a, _) = server.join("renju", &alice).await;
+    let rules = RoomRules { variant: Variant::Renju, ..Default::default() };
+    a.send(ClientMessage::UpdateRules { rules }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    a.close().await;
+
+    let (mut a, mut b) = start_game(&server, "renju", &alice, &bob).await;
+    for (black, white) in [((3, 5), (0, 9)), ((4, 5), (2, 9)), ((5, 3), (4, 9)), ((5, 4), (6, 9))] {
+        place(&mut a, black.0, black.1).await;
+        place(&mut b, white.0, white.1).await;
+    }
+
+    a.send(ClientMessage::Place { x: 5, y: 5 }).await;
+    let rejected = b.expect(|m| matches!(m, ServerMessage::MoveRejected { .. })).await;
+    let ServerMessage::MoveRejected { player, x, y, code } = rejected else { unreachable!() };
+    assert_eq!((player, x, y, code), (alice.email.clone(), 5, 5, MoveRejection::DoubleThree));
+
+    // Black is still to move and may play elsewhere
+    place(&mut a, 9, 0).await;
+}
+
+#[tokio::test]
+async fn test_pente_captures_are_broadcast_and_replayed() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    let (mut a, _) = server.join("pente", &alice).await;
+    let rules = RoomRules { variant: Variant::Pente, ..Default::default() };
+    a.send(ClientMessage::UpdateRules { rules }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    a.close().await;
+
+    let (mut a, mut b) = start_game(&server, "pente", &alice, &bob).await;
+    place(&mut a, 0, 0).await;
+    place(&mut b, 1, 0).await;
+    place(&mut a, 9, 9).await;
+    place(&mut b, 2, 0).await;
+    a.send(ClientMessage::Place { x: 3, y: 0 }).await;
+
+    let state = b
+        .expect(|m| matches!(m, ServerMessage::GameState { board, .. } if board[3][0].is_some()))
+        .await;
+    let ServerMessage::GameState { board, captures, removed, .. } = state else { unreachable!() };
+    assert_eq!((board[1][0], board[2][0]), (None, None));
+    assert_eq!(captures, vec![1, 0]);
+    assert_eq!(removed, vec![Move::new(2, 0), Move::new(1, 0)]);
+
+    b.send(ClientMessage::Resign).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let games = server.state.games.history(&user_record(&alice.id), 10, 0).await.unwrap();
+    let moves = &games[0].moves;
+    assert_eq!(moves.len(), 5);
+    assert_eq!((moves[4].x, moves[4].y), (3, 0));
+    assert_eq!(moves[4].captured, vec![Move::new(2, 0), Move::new(1, 0)]);
+    assert!(moves[..4].iter().all(|mv| mv.captured.is_empty()));
+}
+
+#[tokio::test]
+async fn test_connect6_turns_place_two_stones() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    let (mut a, _) = server.join("connect6", &alice).await;
+    let rules = RoomRules { variant: Variant::Connect6, ..Default::default() };
+    a.send(ClientMessage::UpdateRules { rules }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    a.close().await;
+
+    let (mut a, mut b) = start_game(&server, "connect6", &alice, &bob).await;
+    place(&mut a, 0, 0).await;
+    place(&mut b, 9, 9).await;
+
+    // Bob is halfway through his turn, so Alice has to wait
+    let state = a.expect(|m| matches!(m, ServerMessage::GameState { .. })).await;
+    let ServerMessage::GameState { turn, stones_left, .. } = state else { unreachable!() };
+    assert_eq!((turn, stones_left), (1, 1));
+    a.send(ClientMessage::Place { x: 1, y: 0 }).await;
+    a.expect(|m| matches!(m, ServerMessage::MoveRejected { code: MoveRejection::NotYourTurn, .. }))
+        .await;
+
+    place(&mut b, 9, 8).await;
+    let state = a.expect(|m| matches!(m, ServerMessage::GameState { .. })).await;
+    let ServerMessage::GameState { turn, stones_left, .. } = state else { unreachable!() };
+    assert_eq!((turn, stones_left), (0, 2));
+    place(&mut a, 1, 0).await;
+    place(&mut a, 2, 0).await;
+
+    b.send(ClientMessage::Resign).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let games = server.state.games.history(&user_record(&alice.id), 10, 0).await.unwrap();
+    let players: Vec<usize> = games[0].moves.iter().map(|mv| mv.player).collect();
+    assert_eq!(players, vec![0, 1, 1, 0, 0]);
+}
+
+#[tokio::test]
+async fn test_three_player_free_for_all() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let carol = server.register("carol").await;
+
+    let (mut a, _) = server.join("ffa", &alice).await;
+    let rules = RoomRules { players: 3, ..Default::default() };
+    a.send(ClientMessage::UpdateRules { rules }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    let (mut b, _) = server.join("ffa", &bob).await;
+    let (mut c, _) = server.join("ffa", &carol).await;
+    for socket in [&mut a, &mut b, &mut c] {
+        socket.send(ClientMessage::StepUp).await;
+    }
+    a.expect(|m| matches!(m, ServerMessage::RoomStateUpdate { player_queue, .. } if player_queue.len() == 3))
+        .await;
+    a.send(ClientMessage::StartGame).await;
+
+    let state = a.expect(|m| matches!(m, ServerMessage::GameState { .. })).await;
+    let ServerMessage::GameState { board, turn, .. } = state else { unreachable!() };
+    assert_eq!((board.len(), board[0].len(), turn), (15, 15, 0));
+
+    for i in 0..4 {
+        place(&mut a, i, 0).await;
+        place(&mut b, i, 2).await;
+        place(&mut c, i, 4).await;
+    }
+    a.send(ClientMessage::Place { x: 4, y: 0 }).await;
+
+    let end = c.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let ServerMessage::GameEnd { winner, placements, .. } = end else { unreachable!() };
+    assert_eq!(winner, Some(alice.email.clone()));
+    let ranks: Vec<_> = placements.iter().map(|p| (p.player.clone(), p.rank)).collect();
+    assert_eq!(ranks, vec![(alice.email.clone(), 1), (bob.email.clone(), 2), (carol.email.clone(), 2)]);
+
+    // The winner takes half of K from each of the others
+    let games = server.state.games.history(&user_record(&carol.id), 10, 0).await.unwrap();
+    let elo: Vec<_> = games[0].placements.iter().map(|p| (p.rank, p.elo_after)).collect();
+    assert_eq!(elo, vec![(1, 1216), (2, 1192), (2, 1192)]);
+    let carol_row = server.state.users.find(&user_record(&carol.id)).await.unwrap().unwrap();
+    assert_eq!(carol_row.elo, 1192);
+
+    let (_, history) = server.get("/games/history", Some(&carol.token)).await;
+    assert_eq!(history["matches"][0]["result"], "loss");
+    assert_eq!(history["matches"][0]["players"], 3);
+    assert_eq!(history["matches"][0]["opponent_name"], alice.username.as_str());
+}
+
+#[tokio::test]
+async fn test_two_versus_two() {
+    let server = TestServer::spawn().await;
+    let users = [
+        server.register("alice").await,
+        server.register("bob").await,
+        server.register("carol").await,
+        server.register("dave").await,
+    ];
+
+    let (mut a, _) = server.join("teams", &users[0]).await;
+    let rules = RoomRules { players: 4, teams: true, ..Default::default() };
+    a.send(ClientMessage::UpdateRules { rules }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    let (mut b, _) = server.join("teams", &users[1]).await;
+    let (mut c, _) = server.join("teams", &users[2]).await;
+    let (mut d, _) = server.join("teams", &users[3]).await;
+    for socket in [&mut a, &mut b, &mut c, &mut d] {
+        socket.send(ClientMessage::StepUp).await;
+    }
+    a.expect(|m| matches!(m, ServerMessage::RoomStateUpdate { player_queue, .. } if player_queue.len() == 4))
+        .await;
+    a.send(ClientMessage::StartGame).await;
+
+    // Alice and Carol play the first colour, Bob and Dave the second
+    let state = a.expect(|m| matches!(m, ServerMessage::GameState { .. })).await;
+    let ServerMessage::GameState { board, .. } = state else { unreachable!() };
+    assert_eq!(board.len(), 10);
+
+    // Team chat reaches the teammate but not the other team
+    a.send(ClientMessage::TeamChat { content: "top row".to_string() }).await;
+    c.expect(|m| matches!(m, ServerMessage::TeamChat { content, .. } if content == "top row"))
+        .await;
+    d.send(ClientMessage::Chat { content: "hi all".to_string() }).await;
+    b.expect(|m| {
+        assert!(!matches!(m, ServerMessage::TeamChat { .. }), "team chat leaked to the other team");
+        matches!(m, ServerMessage::Chat { content, .. } if content == "hi all")
+    })
+    .await;
+
+    // Teammates take turns for their colour
+    for i in 0..2 {
+        place(&mut a, i * 2, 0).await;
+        place(&mut b, i * 2, 2).await;
+        place(&mut c, i * 2 + 1, 0).await;
+        place(&mut d, i * 2 + 1, 2).await;
+    }
+    c.send(ClientMessage::Place { x: 4, y: 0 }).await;
+    c.expect(|m| matches!(m, ServerMessage::MoveRejected { .. })).await;
+    a.send(ClientMessage::Place { x: 4, y: 0 }).await;
+
+    let end = d.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let ServerMessage::GameEnd { winner, placements, .. } = end else { unreachable!() };
+    assert_eq!(winner, Some(users[0].email.clone()));
+    let teams: Vec<_> = placements.iter().map(|p| (p.rank, p.team)).collect();
+    assert_eq!(teams, vec![(1, 0), (2, 1), (1, 0), (2, 1)]);
+
+    // Both winners gain and both losers lose, as in a one-on-one game
+    for (user, elo) in users.iter().zip([1216, 1184, 1216, 1184]) {
+        let row = server.state.users.find(&user_record(&user.id)).await.unwrap().unwrap();
+        assert_eq!(row.elo, elo);
+    }
+    let (_, history) = server.get("/games/history", Some(&users[2].token)).await;
+    assert_eq!(history["matches"][0]["result"], "win");
+    let (_, profile) = server.get(&format!("/users/{}", users[2].id), None).await;
+    assert_eq!(profile["games_won"], 1);
+}
+
+#[tokio::test]
+async fn test_infinite_board() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    let (mut a, _) = server.join("infinite", &alice).await;
+    let rules = RoomRules { infinite: true, ..Default::default() };
+    a.send(ClientMessage::UpdateRules { rules }).await;
+    a.expect(|m| matches!(m, ServerMessage::RulesUpdated { .. })).await;
+    let (mut b, _) = server.join("infinite", &bob).await;
+    a.send(ClientMessage::StepUp).await;
+    b.send(ClientMessage::StepUp).await;
+    a.expect(|m| matches!(m, ServerMessage::RoomStateUpdate { player_queue, .. } if player_queue.len() == 2))
+        .await;
+    a.send(ClientMessage::StartGame).await;
+
+    // Far from the origin, on both sides of it, and far apart
+    for i in 0..4 {
+        place(&mut a, -100 + i, -50).await;
+        place(&mut b, 1_000_000, 50 + i).await;
+    }
+    let state = a.expect(|m| matches!(m, ServerMessage::GameState { .. })).await;
+    let ServerMessage::GameState { board, stones, bounds, .. } = state else { unreachable!() };
+    assert!(board.is_empty());
+    assert_eq!(stones.len(), 8);
+    assert_eq!((bounds.min_x, bounds.min_y, bounds.max_x, bounds.max_y), (-103, -53, 1_000_003, 56));
+
+    // There is still an edge, well short of overflowing
+    for (x, y) in [(isize::MAX, 0), (0, isize::MIN), (1 << 21, 0)] {
+        a.send(ClientMessage::Place { x, y }).await;
+        let rejected = a.expect(|m| matches!(m, ServerMessage::MoveRejected { .. })).await;
+        let ServerMessage::MoveRejected { code, .. } = rejected else { unreachable!() };
+        assert_eq!(code, MoveRejection::OutOfBounds);
+    }
+
+    a.send(ClientMessage::Place { x: -96, y: -50 }).await;
+    let end = b.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let ServerMessage::GameEnd { winner, line, .. } = end else { unreachable!() };
+    assert_eq!(winner, Some(alice.email.clone()));
+    assert_eq!(line, (-100..-95).map(|x| Move::new(x, -50)).collect::<Vec<_>>());
+
+    // Stored as the stones' coordinates
+    let games = server.state.games.history(&user_record(&alice.id), 10, 0).await.unwrap();
+    assert_eq!(games[0].stones.len(), 9);
+    assert!(games[0].stones.iter().any(|s| (s.x, s.y, s.player) == (-96, -50, 0)));
+}
+
+#[tokio::test]
+async fn test_position_analysis() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+
+    // Black's open three makes a straight four
+    let moves = json!([
+        { "x": 3, "y": 5 }, { "x": 0, "y": 0 }, { "x": 4, "y": 5 },
+        { "x": 0, "y": 9 }, { "x": 5, "y": 5 }, { "x": 9, "y": 0 },
+    ]);
+    let (status, analysis) = server.post("/analysis", Some(&alice.token), json!({ "moves": moves })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(analysis["threats"][0]["open_threes"], json!([{ "x": 2, "y": 5 }, { "x": 6, "y": 5 }]));
+    assert_eq!(analysis["forced_win"].as_array().unwrap().len(), 3);
+    assert_eq!(analysis["best_move"], json!({ "x": 2, "y": 5 }));
+
+    let (status, _) = server.post("/analysis", Some(&alice.token), json!({ "moves": [{ "x": 10, "y": 0 }] })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = server.post("/analysis", None, json!({ "moves": moves })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // More stones than a 19x19 board holds are refused before any search
+    let crowded: Vec<_> = (0..400).map(|i| json!({ "x": 10 * i, "y": 0 })).collect();
+    let infinite = json!({ "infinite": true });
+    let (status, _) = server.post("/analysis", Some(&alice.token), json!({ "rules": infinite, "moves": crowded })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A stored game, stopped before the winning move
+    let (mut a, mut b) = start_game(&server, "analysis", &alice, &bob).await;
+    for i in 0..4 {
+        place(&mut a, i, 0).await;
+        place(&mut b, i, 1).await;
+    }
+    a.send(ClientMessage::Place { x: 4, y: 0 }).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+
+    let (_, history) = server.get("/games/history", Some(&bob.token)).await;
+    let game_id = history["matches"][0]["id"].as_str().unwrap();
+    let (status, analysis) = server
+        .post("/analysis", Some(&bob.token), json!({ "game_id": game_id, "ply": 7 }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    // Bob to move must block the four
+    assert_eq!(analysis["to_move"], 1);
+    assert_eq!(analysis["threats"][0]["wins"], json!([{ "x": 4, "y": 0 }]));
+    assert_eq!(analysis["best_move"], json!({ "x": 4, "y": 0 }));
+}
+
+#[tokio::test]
+async fn test_finished_games_are_annotated() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, mut b) = start_game(&server, "annotated", &alice, &bob).await;
+
+    // Bob keeps building his own row instead of blocking alice's four
+    for i in 0..4 {
+        place(&mut a, i, 0).await;
+        place(&mut b, i, 1).await;
+    }
+    a.send(ClientMessage::Place { x: 4, y: 0 }).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+
+    let (_, history) = server.get("/games/history", Some(&bob.token)).await;
+    let game_id = history["matches"][0]["id"].as_str().unwrap().to_string();
+    let path = format!("/games/{}", game_id.trim_start_matches("game:"));
+
+    // The review runs in the background after the game is recorded
+    let mut game = serde_json::Value::Null;
+    for _ in 0..50 {
+        let (status, body) = server.get(&path, Some(&alice.token)).await;
+        assert_eq!(status, StatusCode::OK);
+        game = body;
+        if !game["annotations"].is_null() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let annotations = game["annotations"].as_array().expect("game was never annotated");
+    assert_eq!(annotations.len(), 9);
+    assert_eq!(annotations[7]["player"], 1);
+    assert_eq!(annotations[7]["judgement"], "blunder");
+    assert_eq!(annotations[7]["best_move"], json!({ "x": 4, "y": 0 }));
+    assert_eq!(annotations[8]["judgement"], "best");
+
+    let accuracy: Vec<f64> = game["players"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["accuracy"].as_f64().unwrap())
+        .collect();
+    assert!(accuracy[0] > accuracy[1]);
+}
+
+#[tokio::test]
+async fn test_puzzles_are_mined_and_rated() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let carol = server.register("carol").await;
+    let (mut a, mut b) = start_game(&server, "puzzles", &alice, &bob).await;
+
+    // Alice's broken three only wins by filling the gap, and she finds it
+    let black = [(3, 5), (5, 5), (6, 5), (4, 5)];
+    let white = [(0, 0), (0, 9), (9, 0), (2, 5)];
+    for (&(bx, by), &(wx, wy)) in black.iter().zip(&white) {
+        place(&mut a, bx, by).await;
+        place(&mut b, wx, wy).await;
+    }
+    a.send(ClientMessage::Place { x: 7, y: 5 }).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+
+    // Mining runs in the background after the game is recorded
+    let mut puzzle = serde_json::Value::Null;
+    for _ in 0..50 {
+        let (status, body) = server.get("/puzzles/next", Some(&carol.token)).await;
+        if status == StatusCode::OK {
+            puzzle = body;
+            break;
+        }
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(puzzle["to_move"], 0, "no puzzle was mined");
+    assert_eq!(puzzle["moves"].as_array().unwrap().len(), 6);
+    assert_eq!(puzzle["length"], 2);
+    assert!(puzzle.get("solution").is_none());
+    let path = format!("/puzzles/{}/attempt", puzzle["id"].as_str().unwrap());
+
+    // Bob solves it: the gap, then whichever end is left open
+    let (status, step) = server.post(&path, Some(&bob.token), json!({ "moves": [{ "x": 4, "y": 5 }] })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(step["status"], "continue");
+    let open = if step["reply"] == json!({ "x": 2, "y": 5 }) { 7 } else { 2 };
+    let moves = json!([{ "x": 4, "y": 5 }, { "x": open, "y": 5 }]);
+    let (_, solved) = server.post(&path, Some(&bob.token), json!({ "moves": moves })).await;
+    assert_eq!(solved["status"], "solved");
+    assert_eq!(solved["rating"]["before"], 1200);
+    assert!(solved["rating"]["after"].as_i64().unwrap() > 1200);
+
+    // Carol fails it, and only her first attempt is rated
+    let (_, failed) = server.post(&path, Some(&carol.token), json!({ "moves": [{ "x": 8, "y": 8 }] })).await;
+    assert_eq!(failed["status"], "failed");
+    assert!(failed["rating"]["after"].as_i64().unwrap() < 1200);
+    assert_eq!(failed["solution"].as_array().unwrap().len(), 3);
+    let (_, again) = server.post(&path, Some(&carol.token), json!({ "moves": moves })).await;
+    assert_eq!(again["status"], "solved");
+    assert!(again["rating"].is_null());
+
+    let (status, _) = server.post(&path, Some(&carol.token), json!({ "moves": [{ "x": 3, "y": 5 }] })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, profile) = server.get(&format!("/users/{}", bob.id), Some(&bob.token)).await;
+    assert!(profile["puzzle_rating"].as_i64().unwrap() > 1200);
+
+    // The game already gave its puzzle, so mining again finds nothing new
+    let (status, _) = server.post("/admin/puzzles/mine", Some(&carol.token), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, login) = server
+        .post("/auth/login", None, json!({ "email": "admin@example.com", "password": "adminpass" }))
+        .await;
+    let admin = login["token"].as_str().unwrap();
+    let (status, _) = server.post("/admin/puzzles/mine", Some(admin), json!({})).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    // The same run in the foreground, a game at a time
+    assert_eq!(server.state.puzzles.mine_completed(1).await.unwrap(), (1, 0));
+}
+
+#[tokio::test]
+async fn test_opening_explorer_merges_mirror_images() {
+    let server = TestServer::spawn().await;
+    let users = [
+        server.register("alice").await,
+        server.register("bob").await,
+        server.register("carol").await,
+        server.register("dave").await,
+    ];
+
+    // The same game twice, the second turned half way round the board
+    for (room, pair, turn) in [("explorer-a", 0, false), ("explorer-b", 2, true)] {
+        let (mut a, mut b) = start_game(&server, room, &users[pair], &users[pair + 1]).await;
+        let at = |x: isize, y: isize| if turn { (9 - x, 9 - y) } else { (x, y) };
+        for i in 0..4 {
+            let (x, y) = at(i, 0);
+            place(&mut a, x, y).await;
+            let (x, y) = at(i, 1);
+            place(&mut b, x, y).await;
+        }
+        let (x, y) = at(4, 0);
+        a.send(ClientMessage::Place { x, y }).await;
+        a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    }
+
+    // Games are indexed in the background once recorded
+    let mut explored = serde_json::Value::Null;
+    for _ in 0..50 {
+        let (status, body) = server.get("/openings?moves=0,0", None).await;
+        assert_eq!(status, StatusCode::OK);
+        explored = body;
+        if explored["games"] == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(explored["to_move"], 1);
+    let continuations = explored["continuations"].as_array().unwrap();
+    assert_eq!(continuations.len(), 1, "{}", explored);
+    // A corner stone mirrors onto itself across the diagonal
+    assert!([json!({ "x": 0, "y": 1 }), json!({ "x": 1, "y": 0 })].contains(&continuations[0]["move"]));
+    assert_eq!(continuations[0]["games"], 2);
+    assert_eq!(continuations[0]["loss_percent"], 100.0);
+    assert_eq!(continuations[0]["average_rating"], 1200.0);
+
+    // Asked from the other corner, the answer turns with it
+    let (_, line) = server.get("/openings?moves=0,0;0,1", None).await;
+    assert_eq!(line["continuations"][0]["move"], json!({ "x": 1, "y": 0 }));
+    let (_, turned) = server.get("/openings?moves=9,9;9,8", None).await;
+    assert_eq!(turned["continuations"][0]["move"], json!({ "x": 8, "y": 9 }));
+    let (_, first) = server.get("/openings", None).await;
+    assert_eq!(first["continuations"][0]["games"], 2);
+    assert_eq!(first["continuations"][0]["win_percent"], 100.0);
+
+    let (_, renju) = server.get("/openings?moves=0,0&variant=renju", None).await;
+    assert_eq!(renju["games"], 0);
+    let (_, strong) = server.get("/openings?moves=0,0&min_rating=1300", None).await;
+    assert_eq!(strong["games"], 0);
+    let (_, band) = server.get("/openings?moves=0,0&min_rating=1100&max_rating=1300", None).await;
+    assert_eq!(band["games"], 2);
+
+    // Indexing every game again, a game at a time, replaces rather than adds
+    assert_eq!(server.state.openings.index_completed(1).await.unwrap(), 18);
+    let (_, again) = server.get("/openings?moves=0,0", None).await;
+    assert_eq!(again["games"], 2);
+
+    let (status, _) = server.get("/openings?moves=0,0;0,0", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = server.get("/openings?moves=zero", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_games_export_and_import_as_text() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, mut b) = start_game(&server, "notation", &alice, &bob).await;
+    for i in 0..4 {
+        place(&mut a, i, 0).await;
+        place(&mut b, i, 1).await;
+    }
+    a.send(ClientMessage::Place { x: 4, y: 0 }).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+
+    let games = server.state.games.history(&user_record(&alice.id), 10, 0).await.unwrap();
+    let game_id = record_key(games[0].id.as_ref().unwrap());
+    let (status, text) = server.get_text(&format!("/games/{}/export", game_id), Some(&bob.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(text.contains("[Black \"alice\"]"), "{}", text);
+    assert!(text.contains("[WhiteElo \"1200\"]"));
+    assert!(text.contains("[Result \"1-0\"]"));
+    assert!(text.contains("[Board \"10x10\"]"));
+    assert!(text.contains("1. a10 a9 2. b10 b9 3. c10 c9 4. d10 d9 5. e10 1-0"));
+    let (_, psq) = server.get_text(&format!("/games/{}/export?format=psq", game_id), Some(&bob.token)).await;
+    assert!(psq.starts_with("Piskvorky 10x10") && psq.contains("\n5,1,0\n"), "{}", psq);
+
+    // The export reads back in, replayed to the same finish
+    let (status, imported) = server.post("/games/import", Some(&bob.token), json!({ "text": text })).await;
+    assert_eq!(status, StatusCode::OK, "{}", imported);
+    assert_eq!(imported["moves"], 9);
+    assert_eq!(imported["result"], "win");
+    let id = imported["id"].as_str().unwrap();
+    let (_, details) = server.get(&format!("/games/{}", id), Some(&bob.token)).await;
+    assert_eq!(details["status"], "imported");
+    assert_eq!(details["rated"], false);
+    assert_eq!(details["winning_line"].as_array().unwrap().len(), 5);
+    let (_, again) = server.get_text(&format!("/games/{}/export", id), Some(&bob.token)).await;
+    assert!(again.contains("1. a10 a9 2. b10 b9"));
+    // Only Bob may export or step through what he imported
+    let (status, _) = server.get_text(&format!("/games/{}/export", id), Some(&alice.token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let replay = json!({ "game_id": id, "ply": 3 });
+    let (status, _) = server.post("/analysis", Some(&alice.token), replay.clone()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = server.post("/analysis", Some(&bob.token), replay).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, history) = server.get("/games/history", Some(&bob.token)).await;
+    assert_eq!(history["total"], 1);
+
+    // A RenLib game on the 15x15 board is played out without edges
+    let renlib = json!({ "text": "h8 i9 h9 i10", "format": "renlib" });
+    let (status, imported) = server.post("/games/import", Some(&alice.token), renlib).await;
+    assert_eq!(status, StatusCode::OK, "{}", imported);
+    assert_eq!(imported["rules"]["infinite"], true);
+    assert_eq!(imported["result"], json!(null));
+    let path = format!("/games/{}/export?format=renlib", imported["id"].as_str().unwrap());
+    let (_, moves) = server.get_text(&path, Some(&alice.token)).await;
+    assert_eq!(moves.trim(), "h8i9h9i10");
+
+    let occupied = json!({ "text": "1. a1 a1" });
+    let (status, _) = server.post("/games/import", Some(&alice.token), occupied).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let unknown = json!({ "text": "[Variant \"go\"]\n\n1. a1" });
+    let (status, _) = server.post("/games/import", Some(&alice.token), unknown).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = server.get_text("/games/missing/export", Some(&alice.token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_knockout_tournament_plays_out_through_pairing_rooms() {
+    // Annotating the games would only slow down pairing the next round
+    let server = TestServer::spawn_with(|state| state.annotate = false).await;
+    let players = [
+        server.register("alice").await,
+        server.register("bob").await,
+        server.register("carol").await,
+        server.register("dave").await,
+    ];
+    let (_, login) = server
+        .post("/auth/login", None, json!({ "email": "admin@example.com", "password": "adminpass" }))
+        .await;
+    let admin = login["token"].as_str().unwrap();
+
+    let cup = json!({ "name": "Cup", "format": "single_elimination" });
+    let (status, _) = server.post("/tournaments", Some(&players[0].token), cup.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, created) = server.post("/tournaments", Some(admin), cup).await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    let path = format!("/tournaments/{}", created["id"].as_str().unwrap());
+    for player in &players {
+        let (status, _) = server.post(&format!("{}/join", path), Some(&player.token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = server.post(&format!("{}/join", path), Some(&players[0].token), json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = server.post(&format!("{}/start", path), Some(&players[0].token), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, started) = server.post(&format!("{}/start", path), Some(admin), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", started);
+    assert_eq!(started["rounds"], 2);
+
+    // Equal ratings seed by registration, top seed against bottom seed
+    let player = |id: &serde_json::Value| {
+        players.iter().find(|p| user_record(&p.id).to_string() == id.as_str().unwrap()).unwrap()
+    };
+    let (_, cup) = server.get(&path, None).await;
+    let semifinals = cup["pairings"].as_array().unwrap().clone();
+    assert_eq!(semifinals.len(), 2);
+    assert_eq!(player(&semifinals[0]["black"]).username, "alice");
+    assert_eq!(player(&semifinals[0]["white"]).username, "dave");
+
+    // Only the paired players may play, and the game starts once both step up
+    for pairing in &semifinals {
+        let room = pairing["room"].as_str().unwrap();
+        let (black, white) = (player(&pairing["black"]), player(&pairing["white"]));
+        let outsider = players.iter().find(|p| p.email != black.email && p.email != white.email).unwrap();
+        let (mut o, _) = server.join(room, outsider).await;
+        o.send(ClientMessage::StepUp).await;
+        let (mut b, _) = server.join(room, black).await;
+        let (mut w, _) = server.join(room, white).await;
+        b.send(ClientMessage::StepUp).await;
+        w.send(ClientMessage::StepUp).await;
+        let started = b.expect(|m| matches!(m, ServerMessage::GameStarted { .. })).await;
+        let ServerMessage::GameStarted { players: seated } = started else { unreachable!() };
+        assert_eq!(seated, vec![black.email.clone(), white.email.clone()]);
+
+        for i in 0..4 {
+            place(&mut b, i, 0).await;
+            place(&mut w, i, 1).await;
+        }
+        b.send(ClientMessage::Place { x: 4, y: 0 }).await;
+        b.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+        for socket in [o, b, w] {
+            socket.close().await;
+        }
+    }
+
+    // The next round is paired in the background once both results are in,
+    // alongside indexing and mining the games. The round moves on before its
+    // pairing is stored, so wait for the pairing itself.
+    let mut cup = serde_json::Value::Null;
+    for _ in 0..300 {
+        (_, cup) = server.get(&path, None).await;
+        if cup["pairings"].as_array().is_some_and(|pairings| pairings.len() == 3) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(cup["round"], 2, "{}", cup);
+    let pairings = cup["pairings"].as_array().unwrap();
+    assert_eq!(pairings.len(), 3, "{}", cup);
+    assert!(pairings[..2].iter().all(|p| p["result"] == "black" && p["game"].is_string() && p["room"].is_null()));
+    let last = &pairings[2];
+    let finalists = [player(&last["black"]).username.as_str(), player(&last["white"]).username.as_str()];
+    assert_eq!(finalists, ["alice", "bob"]);
+    let final_room = last["room"].as_str().unwrap().to_string();
+
+    // The director settles the final by hand
+    let result = format!("{}/pairings/{}/result", path, last["id"].as_str().unwrap());
+    let (status, _) = server.post(&result, Some(admin), json!({ "result": "white" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = server.post(&result, Some(admin), json!({ "result": "black" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, cup) = server.get(&path, None).await;
+    assert_eq!(cup["status"], "finished");
+    // Its room, never entered, isn't opened now there's nothing to play
+    for room in [final_room.as_str(), "tournament-missing"] {
+        let url = format!("ws://{}/ws/{}?token={}", server.addr, room, players[0].token);
+        let Err(tokio_tungstenite::tungstenite::Error::Http(response)) = tokio_tungstenite::connect_async(url).await else {
+            panic!("room {} was opened", room);
+        };
+        assert_eq!(response.status().as_u16(), 404);
+    }
+    let standings = cup["standings"].as_array().unwrap();
+    assert_eq!(standings[0]["rank"], 1);
+    assert_eq!(player(&standings[0]["player"]).username, "bob");
+    assert_eq!(standings[0]["points"], 2.0);
+    assert_eq!(player(&standings[1]["player"]).username, "alice");
+}
+
+#[tokio::test]
+async fn test_chat_reaches_the_room() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let (mut a, _) = server.join("chat", &alice).await;
+    let (mut b, _) = server.join("chat", &bob).await;
+
+    a.send(ClientMessage::Register { name: "Alice".to_string() }).await;
+    a.send(ClientMessage::Chat { content: "hello".to_string() }).await;
+
+    let chat = b
+        .expect(|m| matches!(m, ServerMessage::Chat { content, .. } if content == "hello"))
+        .await;
+    let ServerMessage::Chat { who, .. } = chat else { unreachable!() };
+    assert_eq!(who, "Alice");
+
+    // The sender sees its own message too
+    a.expect(|m| matches!(m, ServerMessage::Chat { content, .. } if content == "hello"))
+        .await;
+}
+
+#[tokio::test]
+async fn test_guest_gets_a_session() {
+    let server = TestServer::spawn().await;
+    let mut socket = server.connect("guests", None).await;
+
+    let ServerMessage::GuestSession { token, email, .. } = socket.recv().await else {
+        panic!("expected a guest session first");
+    };
+    socket.expect(|m| matches!(m, ServerMessage::JoinedRoom { .. })).await;
+
+    let (status, me) = server.get("/auth/me", Some(&token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["email"], email);
+    assert_eq!(me["is_guest"], true);
+}
+
+#[tokio::test]
+async fn test_guest_creation_is_throttled_per_address() {
+    let server = TestServer::spawn().await;
+    for _ in 0..20 {
+        let (status, _) = server.post("/auth/guest", None, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, body) = server.post("/auth/guest", None, json!({})).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(body["retry_after"].as_u64().unwrap() > 0);
+
+    // Registered players still get in
+    let alice = server.register("alice").await;
+    server.join("throttled", &alice).await;
+}
+
+#[tokio::test]
+async fn test_invalid_token_is_rejected() {
+    let server = TestServer::spawn().await;
+    let url = format!("ws://{}/ws/room?token=not-a-token", server.addr);
+    assert!(tokio_tungstenite::connect_async(url).await.is_err());
+}
+
+#[tokio::test]
+async fn test_permissions_follow_granted_roles() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let admin = server.login("admin@example.com", "adminpass").await;
+    let user_path = format!("/admin/users/{}", alice.id);
+
+    for path in ["/admin/users", "/admin/stats", "/admin/locked"] {
+        let (status, _) = server.get(path, Some(&alice.token)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", path);
+    }
+    let (status, _) = server.put(&user_path, Some(&alice.token), json!({ "roles": ["admin"] })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Roles reach new tokens, and each only grants its own permissions
+    let (status, updated) = server.put(&user_path, Some(&admin), json!({ "roles": ["tournament_director"] })).await;
+    assert_eq!(status, StatusCode::OK, "{}", updated);
+    assert_eq!(updated["roles"], json!(["tournament_director"]));
+    let director = server.login(&alice.email, "password123").await;
+    let cup = json!({ "name": "Cup", "format": "swiss" });
+    let (status, _) = server.post("/tournaments", Some(&alice.token), cup.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = server.post("/tournaments", Some(&director), cup).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = server.get("/admin/users", Some(&director)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The legacy flag and the admin role stay in step
+    let (_, updated) = server.put(&user_path, Some(&admin), json!({ "is_admin": true })).await;
+    assert_eq!(updated["roles"], json!(["tournament_director", "admin"]));
+    let promoted = server.login(&alice.email, "password123").await;
+    let (status, _) = server.get("/admin/stats", Some(&promoted)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_api_keys_are_scoped_and_revocable() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let admin = server.login("admin@example.com", "adminpass").await;
+
+    let (status, created) = server.post("/keys", Some(&alice.token), json!({ "name": "reader", "scopes": ["read"] })).await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    let reader = created["key"].as_str().unwrap().to_string();
+    assert!(reader.starts_with(created["info"]["prefix"].as_str().unwrap()));
+
+    // A read key can look but not change anything, nor make more keys
+    let (status, me) = server.get("/auth/me", Some(&reader)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["email"], alice.email);
+    let (status, _) = server.put("/users/profile", Some(&reader), json!({ "is_bot": true })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = server.post("/keys", Some(&reader), json!({ "name": "more", "scopes": ["write"] })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let url = format!("ws://{}/ws/keys?token={}", server.addr, reader);
+    assert!(tokio_tungstenite::connect_async(url).await.is_err());
+    let (_, keys) = server.get("/keys", Some(&alice.token)).await;
+    assert!(keys[0]["last_used_at"].is_string());
+
+    // Admin powers need the admin scope on top of the role
+    let (_, created) = server.post("/keys", Some(&admin), json!({ "name": "stats", "scopes": ["read"] })).await;
+    let (status, _) = server.get("/admin/stats", created["key"].as_str()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, created) = server.post("/keys", Some(&admin), json!({ "name": "ops", "scopes": ["read", "admin"] })).await;
+    let (status, _) = server.get("/admin/stats", created["key"].as_str()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A bot flagged through its key shows up as one to its opponents
+    let (_, created) = server
+        .post("/keys", Some(&alice.token), json!({ "name": "bot", "scopes": ["read", "write", "play"] }))
+        .await;
+    let bot = created["key"].as_str().unwrap().to_string();
+    let (status, profile) = server.put("/users/profile", Some(&bot), json!({ "is_bot": true })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(profile["is_bot"], true);
+    let bot_user = TestUser { token: bot.clone(), ..alice.clone() };
+    let (mut a, mut b) = start_game(&server, "bots", &bot_user, &bob).await;
+    for i in 0..4 {
+        place(&mut a, i, 0).await;
+        place(&mut b, i, 1).await;
+    }
+    a.send(ClientMessage::Place { x: 4, y: 0 }).await;
+    a.expect(|m| matches!(m, ServerMessage::GameEnd { .. })).await;
+    let (_, history) = server.get("/games/history", Some(&bob.token)).await;
+    assert_eq!(history["matches"][0]["opponent_is_bot"], true);
+
+    // Revoked keys stop working at once
+    let id = created["info"]["id"].as_str().unwrap();
+    let (status, revoked) = server.delete(&format!("/keys/{}", id), Some(&alice.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(revoked["revoked_at"].is_string());
+    let (status, _) = server.get("/auth/me", Some(&bot)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = server.delete(&format!("/keys/{}", id), Some(&bob.token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_parallel_wrong_passwords_all_count_towards_the_lock() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+
+    // Kept under the per-address allowance, which every test here shares
+    let wrong = json!({ "email": alice.email, "password": "not-it" });
+    let guesses = (0..6).map(|_| server.post("/auth/login", None, wrong.clone()));
+    for (status, _) in futures::future::join_all(guesses).await {
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let row = server.state.users.find(&user_record(&alice.id)).await.unwrap().unwrap();
+    assert_eq!(row.failed_logins, 6);
+    assert!(row.locked_until.is_some());
+
+    // The locked account looks just like a wrong password or an unknown email
+    let right = json!({ "email": alice.email, "password": "password123" });
+    let (status, locked) = server.post("/auth/login", None, right.clone()).await;
+    let unknown = json!({ "email": "nobody@example.com", "password": "password123" });
+    let (_, missing) = server.post("/auth/login", None, unknown).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(locked, missing);
+
+    let admin = server.login("admin@example.com", "adminpass").await;
+    let (status, _) = server.post(&format!("/admin/users/{}/unlock", alice.id), Some(&admin), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = server.post("/auth/login", None, right).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_parallel_puzzle_attempts_all_move_the_ratings() {
+    let server = TestServer::spawn().await;
+    let alice = user_record(&server.register("alice").await.id);
+    let bob = user_record(&server.register("bob").await.id);
+    let carol = user_record(&server.register("carol").await.id);
+
+    // The broken three from test_puzzles_are_mined_and_rated, as if played twice
+    let moves: Vec<Move> = [(3, 5), (0, 0), (5, 5), (0, 9), (6, 5), (9, 0), (4, 5), (2, 5), (7, 5)]
+        .into_iter()
+        .map(|(x, y)| Move::new(x, y))
+        .collect();
+    let mut puzzles = Vec::new();
+    for game in ["first", "second"] {
+        let game = RecordId::from(("game", game));
+        assert!(server.state.puzzles.mine(&game, &RoomRules::default(), moves.clone()).await.unwrap());
+        // Carol tries each one so the next is handed out after it
+        let puzzle = server.state.puzzles.next_for(&carol).await.unwrap().unwrap();
+        server.state.puzzles.record_attempt(&carol, &puzzle, true).await.unwrap();
+        puzzles.push(server.state.puzzles.find(puzzle.id.as_ref().unwrap()).await.unwrap().unwrap());
+    }
+    assert_ne!(puzzles[0].id, puzzles[1].id);
+
+    // Alice and Bob each try both at once
+    let repo = &server.state.puzzles;
+    let (a1, a2, b1, b2) = tokio::join!(
+        repo.record_attempt(&alice, &puzzles[0], true),
+        repo.record_attempt(&alice, &puzzles[1], false),
+        repo.record_attempt(&bob, &puzzles[0], false),
+        repo.record_attempt(&bob, &puzzles[1], true),
+    );
+    for (user, first, second) in [(&alice, a1, a2), (&bob, b1, b2)] {
+        let (first, second) = (first.unwrap().unwrap(), second.unwrap().unwrap());
+        // Whichever landed second started from where the first left off
+        let (earlier, later) = if first.0 == 1200 { (first, second) } else { (second, first) };
+        assert_eq!(earlier.0, 1200);
+        assert_eq!(later.0, earlier.1);
+        let profile = server.state.users.find(user).await.unwrap().unwrap();
+        assert_eq!(profile.puzzle_rating, later.1);
+    }
+    for puzzle in &puzzles {
+        let rated = repo.find(puzzle.id.as_ref().unwrap()).await.unwrap().unwrap();
+        assert_eq!((rated.attempts, rated.solves), (3, 2));
+    }
+}
+
+#[tokio::test]
+async fn test_games_ending_together_both_move_a_shared_rating() {
+    let server = TestServer::spawn().await;
+    let alice = server.register("alice").await;
+    let bob = server.register("bob").await;
+    let carol = server.register("carol").await;
+
+    // Alice wins two games that end at the same moment
+    let games = &server.state.games;
+    let mut endings = Vec::new();
+    for opponent in [&bob, &carol] {
+        let emails = vec![alice.email.clone(), opponent.email.clone()];
+        let (game_id, rated) = games.create(&emails, &RoomRules::default()).await.unwrap();
+        assert!(rated);
+        let ending = GameEnding::new(GameResult::Win, "Five in a row", Some(alice.email.clone())).ranked(&emails, 2);
+        endings.push((game_id, ending));
+    }
+    let (first, second) = tokio::join!(games.end(&endings[0].0, &endings[0].1), games.end(&endings[1].0, &endings[1].1));
+    first.unwrap();
+    second.unwrap();
+
+    // Whichever ended second started from the rating the first left her
+    let mut placements = Vec::new();
+    for (game_id, _) in &endings {
+        let game = games.find(game_id).await.unwrap().unwrap();
+        placements.push(game.placements.into_iter().find(|p| p.player == user_record(&alice.id)).unwrap());
+    }
+    placements.sort_by_key(|p| p.elo_before);
+    assert_eq!(placements[0].elo_before, 1200);
+    assert_eq!(placements[1].elo_before, placements[0].elo_after);
+    let profile = server.state.users.find(&user_record(&alice.id)).await.unwrap().unwrap();
+    assert_eq!(profile.elo, placements[1].elo_after);
+}
+